@@ -0,0 +1,116 @@
+use std::{fmt, str::FromStr};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// The hash function of the checksums of the migration files, stored with each record so the ones computed with
+/// another algorithm are still verified, then upgraded, after a switch.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    /// SHA-256, the algorithm of the records without one.
+    #[default]
+    Sha256,
+    /// BLAKE3, faster on large migration files. Requires the `blake3` feature.
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    /// The name of the algorithm, stored in the `checksum_algorithm` field of the records.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            #[cfg(feature = "blake3")]
+            ChecksumAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Returns the hex encoded checksum of a migration file content.
+    pub fn digest(&self, content: &str) -> String {
+        match *self {
+            ChecksumAlgorithm::Sha256 => format!("{:x}", Sha256::digest(content.as_bytes())),
+            #[cfg(feature = "blake3")]
+            ChecksumAlgorithm::Blake3 => blake3::hash(content.as_bytes()).to_hex().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "sha256" => Ok(ChecksumAlgorithm::Sha256),
+            #[cfg(feature = "blake3")]
+            "blake3" => Ok(ChecksumAlgorithm::Blake3),
+            _ => Err(format!("Unknown checksum algorithm '{}' (the `blake3` feature may be disabled).", name)),
+        }
+    }
+}
+
+/// Computes the checksums of a migration file with every algorithm as it is read, for the files too large to be
+/// held in memory.
+pub(crate) struct StreamingDigest {
+    sha256: Sha256,
+    #[cfg(feature = "blake3")]
+    blake3: blake3::Hasher,
+}
+
+impl StreamingDigest {
+    pub(crate) fn new() -> Self {
+        StreamingDigest {
+            sha256: Sha256::new(),
+            #[cfg(feature = "blake3")]
+            blake3: blake3::Hasher::new(),
+        }
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        self.sha256.update(bytes);
+        #[cfg(feature = "blake3")]
+        self.blake3.update(bytes);
+    }
+
+    /// Returns the hex encoded checksums, by algorithm.
+    pub(crate) fn finish(self) -> Vec<(ChecksumAlgorithm, String)> {
+        vec![
+            (ChecksumAlgorithm::Sha256, format!("{:x}", self.sha256.finalize())),
+            #[cfg(feature = "blake3")]
+            (ChecksumAlgorithm::Blake3, self.blake3.finalize().to_hex().to_string()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChecksumAlgorithm, StreamingDigest};
+
+    #[test]
+    fn it_names_and_parses_the_algorithms() {
+        assert_eq!(ChecksumAlgorithm::default(), ChecksumAlgorithm::Sha256);
+        assert_eq!("sha256".parse::<ChecksumAlgorithm>(), Ok(ChecksumAlgorithm::Sha256));
+        assert!("md5".parse::<ChecksumAlgorithm>().is_err());
+        assert_eq!(
+            ChecksumAlgorithm::Sha256.digest(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn it_computes_the_same_checksums_when_streaming() {
+        let mut digest = StreamingDigest::new();
+        digest.update(b"DEFINE TABLE users;\n");
+        digest.update(b"DEFINE TABLE posts;\n");
+
+        for (algorithm, checksum) in digest.finish() {
+            assert_eq!(checksum, algorithm.digest("DEFINE TABLE users;\nDEFINE TABLE posts;\n"));
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use surrealdb::{Connection, Surreal};
+use tracing::info;
+
+use crate::{diff::read_schema, Error, SchemaDefinition};
+
+/// Removes every table of the database (with its records, fields, indexes and events), function, param and
+/// analyzer, except the kept tables, in a single transaction, and returns the removed resources.
+pub(crate) async fn run_clean<C: Connection>(db: &Surreal<C>, kept_tables: &[String]) -> Result<Vec<SchemaDefinition>, Error> {
+    // The fields, indexes and events of a removed table are removed with it.
+    let removed: Vec<SchemaDefinition> = read_schema(db, kept_tables)
+        .await?
+        .into_iter()
+        .filter(|definition| definition.table.is_none())
+        .collect();
+
+    if removed.is_empty() {
+        return Ok(removed);
+    }
+
+    let statements: Vec<String> = removed
+        .iter()
+        .map(SchemaDefinition::remove_statement)
+        .collect();
+
+    let _ = db
+        .query(format!("BEGIN TRANSACTION;\n{}\nCOMMIT TRANSACTION;", statements.join("\n")))
+        .await?
+        .check()?;
+
+    for definition in &removed {
+        info!(resource = %definition, "Resource removed");
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{progress::define_progress_table, Migrator, VecSource};
+
+    #[tokio::test]
+    async fn it_removes_everything_but_the_migrations_table() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        Migrator::new(&db)
+            .source(VecSource::new([("001_users.surql", "DEFINE TABLE users;\nDEFINE FUNCTION fn::greet() { RETURN 'Hello'; };")]))
+            .run()
+            .await
+            .unwrap();
+        db.query("DEFINE PARAM $endpoint VALUE 'https://example.com';\nCREATE scratch:1;").await.unwrap().check().unwrap();
+        // The progress of an unfinished data migration, resumed by the next run.
+        define_progress_table(&db, "migrations").await.unwrap();
+
+        let removed = Migrator::new(&db).clean().await.unwrap();
+        assert_eq!(removed.len(), 4);
+
+        let info: serde_json::Value = db.query("INFO FOR DB;").await.unwrap().take::<Option<serde_json::Value>>(0).unwrap().unwrap();
+        let tables = info["tables"].as_object().unwrap();
+        assert!(tables.get("migrations").is_some() && tables.get("migrations_progress").is_some());
+        assert!(tables.get("users").is_none() && tables.get("scratch").is_none());
+        assert!(info["functions"].as_object().unwrap().is_empty());
+        assert!(info["params"].as_object().unwrap().is_empty());
+
+        let applied: Vec<String> = db.query("SELECT VALUE filename FROM migrations;").await.unwrap().take(0).unwrap();
+        assert_eq!(applied, ["001_users.surql"]);
+    }
+}
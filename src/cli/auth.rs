@@ -0,0 +1,138 @@
+use std::{collections::BTreeMap, process::Command};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use surrealdb::{
+    engine::any::Any,
+    opt::auth::{Database, Namespace, Record, Root},
+    Surreal,
+};
+
+/// The level of the user signing in to the SurrealDB instance.
+#[derive(ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthLevel {
+    /// A root user, with access to every namespace.
+    #[default]
+    Root,
+    /// A user defined on the namespace.
+    Namespace,
+    /// A user defined on the database.
+    Database,
+}
+
+/// How to authenticate on the SurrealDB instance.
+#[derive(Debug, Clone)]
+pub enum Credentials {
+    /// The username and password of a user.
+    User { username: String, password: String },
+    /// The parameters (e.g. `email` and `password`) of a record user signing in with an access method of the
+    /// database, for the deployments only exposing those.
+    Record { access: String, params: BTreeMap<String, String> },
+    /// A pre-issued token (JWT).
+    Token(String),
+}
+
+/// Signs in with the credentials of a user of the given level.
+pub async fn signin(
+    db: &Surreal<Any>,
+    level: AuthLevel,
+    namespace: &str,
+    database: &str,
+    username: &str,
+    password: &str,
+) -> Result<(), surrealdb::Error> {
+    match level {
+        AuthLevel::Root => {
+            db.signin(Root { username, password }).await?;
+        },
+        AuthLevel::Namespace => {
+            db.signin(Namespace { namespace, username, password }).await?;
+        },
+        AuthLevel::Database => {
+            db.signin(Database { namespace, database, username, password }).await?;
+        },
+    }
+
+    Ok(())
+}
+
+/// Signs in as a record user with an access method of the database (a scope, before SurrealDB 2).
+pub async fn signin_record(
+    db: &Surreal<Any>,
+    namespace: &str,
+    database: &str,
+    access: &str,
+    params: &BTreeMap<String, String>,
+) -> Result<(), surrealdb::Error> {
+    db.signin(Record { namespace, database, access, params: params.clone() }).await?;
+
+    Ok(())
+}
+
+/// Parses a `KEY=VALUE` parameter of a record access method (`--auth-param`).
+pub fn parse_auth_param(param: &str) -> Result<(String, String), String> {
+    match param.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.to_string())),
+        _ => Err(format!("Invalid parameter '{}': expected KEY=VALUE.", param)),
+    }
+}
+
+/// Authenticates with a pre-issued token (JWT).
+pub async fn authenticate(db: &Surreal<Any>, token: String) -> Result<(), surrealdb::Error> {
+    db.authenticate(token).await?;
+
+    Ok(())
+}
+
+/// Runs the command printing the password (e.g. `op read op://vault/surrealdb/password`, or a keychain lookup like
+/// `security find-generic-password -s surrealdb -w`) in the shell, and returns its output without the trailing
+/// newline, so the password is never stored in an environment variable or the shell history.
+pub fn password_from_command(command: &str) -> Result<String, String> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    };
+
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Failed to run the password command: {}", e)),
+    };
+    if !output.status.success() {
+        return Err(format!(
+            "The password command failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let password = match String::from_utf8(output.stdout) {
+        Ok(password) => password.trim_end_matches(['\r', '\n']).to_string(),
+        Err(_) => return Err("The password command did not print valid UTF-8.".to_string()),
+    };
+    if password.is_empty() {
+        return Err("The password command printed an empty password.".to_string());
+    }
+
+    Ok(password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_auth_param, password_from_command};
+
+    #[test]
+    fn it_reads_the_password_printed_by_the_command() {
+        assert_eq!(password_from_command("echo s3cret").unwrap(), "s3cret");
+        assert!(password_from_command("exit 1").is_err());
+    }
+
+    #[test]
+    fn it_parses_the_parameters_of_the_record_access() {
+        assert_eq!(parse_auth_param("email=a@b.c").unwrap(), ("email".to_string(), "a@b.c".to_string()));
+        assert_eq!(parse_auth_param("token=a=b").unwrap(), ("token".to_string(), "a=b".to_string()));
+        assert!(parse_auth_param("email").is_err());
+        assert!(parse_auth_param("=a").is_err());
+    }
+}
@@ -0,0 +1,38 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use surrealdb::{engine::any::Any, Surreal};
+use surrealdb_simple_migration::Error;
+
+/// Exports the database in use to a new `<namespace>_<database>_<timestamp>.surql` file of a directory (created
+/// when missing), returning its path. Imported with `surreal import` to recover from a destructive command.
+pub async fn backup(db: &Surreal<Any>, dir: &Path, namespace: &str, database: &str) -> Result<PathBuf, Error> {
+    tokio::fs::create_dir_all(dir).await?;
+
+    let path = dir.join(backup_filename(namespace, database, &Utc::now().format("%Y%m%d%H%M%S").to_string()));
+    db.export(path.clone()).await?;
+
+    Ok(path)
+}
+
+fn backup_filename(namespace: &str, database: &str, timestamp: &str) -> String {
+    // The names can contain characters not allowed in filenames (e.g. "`my/db`").
+    let sanitize = |name: &str| -> String {
+        name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect()
+    };
+
+    format!("{}_{}_{}.surql", sanitize(namespace), sanitize(database), timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::backup_filename;
+
+    #[test]
+    fn it_names_the_backups_after_the_database() {
+        assert_eq!(backup_filename("app", "prod", "20240521143000"), "app_prod_20240521143000.surql");
+        assert_eq!(backup_filename("app", "my db/1", "20240521143000"), "app_my_db_1_20240521143000.surql");
+    }
+}
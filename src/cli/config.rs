@@ -0,0 +1,230 @@
+use std::{collections::HashMap, fs::read_to_string, io::ErrorKind, path::PathBuf};
+
+use serde::Deserialize;
+use surrealdb_simple_migration::{ChecksumAlgorithm, LintRule, LintSeverity, Policy};
+
+use super::{auth::AuthLevel, hooks::ShellHooks, output::OutputFormat};
+
+/// The configuration file read when `--config` is not given.
+pub const DEFAULT_CONFIG_PATH: &str = "ssm.toml";
+
+/// The options that can be set in the configuration file, at the top level or in a profile.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Settings {
+    pub host: Option<String>,
+    /// The PEM file of the certificate authorities trusted for a `wss://` or `https://` host.
+    pub ca_cert: Option<PathBuf>,
+    /// The PEM file of the client certificate and its private key, for mutual TLS.
+    pub client_cert: Option<PathBuf>,
+    pub insecure_skip_verify: Option<bool>,
+    /// The directory of the exports made before the destructive commands.
+    pub backup_dir: Option<PathBuf>,
+    /// How the failures and warnings are printed.
+    pub output: Option<OutputFormat>,
+    pub namespace: Option<String>,
+    pub database: Option<String>,
+    pub path: Option<String>,
+    /// Whether the migration files of the subdirectories of `path` are read too.
+    pub recursive: Option<bool>,
+    pub table: Option<String>,
+    pub auth_level: Option<AuthLevel>,
+    pub username: Option<String>,
+    /// The command printing the password, e.g. reading it from a password manager or the OS keychain.
+    pub password_command: Option<String>,
+    /// The access method to sign in with as a record user.
+    pub access: Option<String>,
+    /// The parameters of the record access method (e.g. `email`), except the secret ones.
+    #[serde(default)]
+    pub auth_params: HashMap<String, String>,
+    /// The algorithm of the recorded checksums, e.g. `"blake3"`.
+    pub checksum_algorithm: Option<ChecksumAlgorithm>,
+    /// The accepted extensions of the migration files, e.g. `["surql", "sql"]`.
+    pub extensions: Option<Vec<String>>,
+    /// The regex the names of the numbered migration files must match.
+    pub migration_pattern: Option<String>,
+    /// The directory of the templates of the new migration files.
+    pub templates_dir: Option<String>,
+    /// The values of the `${VAR}` placeholders of the migration files.
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
+    /// The databases to run the commands on, instead of `database`.
+    #[serde(default)]
+    pub targets: Vec<Target>,
+    /// How many targets `apply` and `up` run on concurrently.
+    pub parallel: Option<usize>,
+    /// The severity of the rules of the `lint` command, overriding their default one.
+    #[serde(default)]
+    pub lint: HashMap<LintRule, LintSeverity>,
+    /// Whether the changed, missing and out of order migration files are errors, warnings or ignored.
+    pub policy: Option<Policy>,
+    /// The shell commands run around `apply`, each one overriding the one of the top level in a profile.
+    #[serde(default)]
+    pub hooks: ShellHooks,
+}
+
+/// A database to run the commands on, in a `[[targets]]` entry.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    /// The namespace of the database. (default: the `namespace` option)
+    pub namespace: Option<String>,
+    pub database: String,
+}
+
+impl Settings {
+    /// Returns the settings, overridden by the ones set in `other`.
+    fn merge(self, other: Settings) -> Settings {
+        Settings {
+            host: other.host.or(self.host),
+            ca_cert: other.ca_cert.or(self.ca_cert),
+            client_cert: other.client_cert.or(self.client_cert),
+            insecure_skip_verify: other.insecure_skip_verify.or(self.insecure_skip_verify),
+            backup_dir: other.backup_dir.or(self.backup_dir),
+            output: other.output.or(self.output),
+            namespace: other.namespace.or(self.namespace),
+            database: other.database.or(self.database),
+            path: other.path.or(self.path),
+            recursive: other.recursive.or(self.recursive),
+            table: other.table.or(self.table),
+            auth_level: other.auth_level.or(self.auth_level),
+            username: other.username.or(self.username),
+            password_command: other.password_command.or(self.password_command),
+            access: other.access.or(self.access),
+            auth_params: self.auth_params.into_iter().chain(other.auth_params).collect(),
+            checksum_algorithm: other.checksum_algorithm.or(self.checksum_algorithm),
+            extensions: other.extensions.or(self.extensions),
+            migration_pattern: other.migration_pattern.or(self.migration_pattern),
+            templates_dir: other.templates_dir.or(self.templates_dir),
+            vars: self.vars.into_iter().chain(other.vars).collect(),
+            targets: if other.targets.is_empty() { self.targets } else { other.targets },
+            parallel: other.parallel.or(self.parallel),
+            lint: self.lint.into_iter().chain(other.lint).collect(),
+            policy: other.policy.or(self.policy),
+            hooks: self.hooks.merge(other.hooks),
+        }
+    }
+}
+
+/// The content of an `ssm.toml` file.
+///
+/// ```toml
+/// path = "./migrations"
+/// namespace = "app"
+///
+/// [lint]
+/// non_idempotent_define = "error"
+/// naming = "off"
+///
+/// [policy]
+/// modified = "warn"
+/// missing = "ignore"
+///
+/// [hooks]
+/// after_apply = "./scripts/notify.sh"
+///
+/// [profiles.staging]
+/// host = "staging.example.com:8000"
+/// database = "staging"
+///
+/// [profiles.staging.vars]
+/// BUCKET = "staging-assets"
+///
+/// [[profiles.prod.targets]]
+/// database = "tenant_a"
+///
+/// [[profiles.prod.targets]]
+/// namespace = "legacy"
+/// database = "tenant_b"
+/// ```
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Config {
+    #[serde(flatten)]
+    pub settings: Settings,
+    #[serde(default)]
+    pub profiles: HashMap<String, Settings>,
+}
+
+impl Config {
+    /// Reads the configuration file at `path`, or `ssm.toml` if it exists when no path is given.
+    pub fn load(path: Option<&str>) -> Result<Config, String> {
+        let content = match read_to_string(path.unwrap_or(DEFAULT_CONFIG_PATH)) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound && path.is_none() => return Ok(Config::default()),
+            Err(err) => {
+                return Err(format!("Failed to read the configuration file '{}': {}", path.unwrap_or(DEFAULT_CONFIG_PATH), err))
+            },
+        };
+
+        toml::from_str(&content)
+            .map_err(|err| format!("Failed to parse the configuration file: {}", err))
+    }
+
+    /// Returns the top level settings, overridden by the ones of the selected profile.
+    pub fn settings(&self, profile: Option<&str>) -> Result<Settings, String> {
+        let Some(profile) = profile else {
+            return Ok(self.settings.clone());
+        };
+
+        match self.profiles.get(profile) {
+            Some(settings) => Ok(self.settings.clone().merge(settings.clone())),
+            None => Err(format!("The profile '{}' is not defined in the configuration file.", profile)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb_simple_migration::{LintRule, LintSeverity};
+
+    use super::{Config, Settings, Target};
+
+    #[test]
+    fn it_overrides_the_settings_with_the_profile() {
+        let config: Config = toml::from_str(r#"
+            host = "localhost:8000"
+            path = "./migrations"
+
+            [lint]
+            naming = "off"
+
+            [profiles.prod]
+            lint = { naming = "error", empty_file = "warning" }
+            host = "prod.example.com:8000"
+            database = "prod"
+
+            [[profiles.tenants.targets]]
+            database = "tenant_a"
+
+            [[profiles.tenants.targets]]
+            namespace = "legacy"
+            database = "tenant_b"
+        "#).unwrap();
+
+        assert_eq!(
+            config.settings(None).unwrap(),
+            Settings {
+                host: Some("localhost:8000".to_string()),
+                path: Some("./migrations".to_string()),
+                lint: [(LintRule::Naming, LintSeverity::Off)].into(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            config.settings(Some("prod")).unwrap(),
+            Settings {
+                host: Some("prod.example.com:8000".to_string()),
+                database: Some("prod".to_string()),
+                path: Some("./migrations".to_string()),
+                lint: [(LintRule::Naming, LintSeverity::Error), (LintRule::EmptyFile, LintSeverity::Warning)].into(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            config.settings(Some("tenants")).unwrap().targets,
+            vec![
+                Target { namespace: None, database: "tenant_a".to_string() },
+                Target { namespace: Some("legacy".to_string()), database: "tenant_b".to_string() },
+            ]
+        );
+        assert!(config.settings(Some("staging")).is_err());
+    }
+}
@@ -0,0 +1,30 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Asks the user to confirm a destructive action by typing the name of the database, unless `yes` is set (e.g.
+/// with `--yes`). Fails in a non-interactive environment rather than acting without confirmation.
+pub fn confirm(action: &str, database: &str, yes: bool) -> Result<(), String> {
+    if yes {
+        return Ok(());
+    }
+
+    if !io::stdin().is_terminal() {
+        return Err(format!("This will {}: pass --yes to confirm it in a non-interactive environment.", action));
+    }
+
+    print!("This will {} - type the database name to confirm: ", action);
+    io::stdout()
+        .flush()
+        .map_err(|err| format!("Failed to ask for confirmation: {}", err))?;
+
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|err| format!("Failed to read the confirmation: {}", err))?;
+
+    if answer.trim() != database {
+        return Err("The typed name does not match the database name, nothing has been done.".to_string());
+    }
+
+    Ok(())
+}
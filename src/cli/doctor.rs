@@ -0,0 +1,269 @@
+use std::{fmt::Display, process::ExitCode};
+
+use surrealdb::{engine::any::Any, opt::Config, Surreal};
+use surrealdb_simple_migration::{lint, Error, FilePattern, FsSource, LintConfig, LintRule, MigrationState, Migrator, MultiSource};
+
+use super::{auth::{self, AuthLevel, Credentials}, endpoint::{self, Endpoint}, exit, output::{self, Marker}};
+
+/// What the `doctor` command diagnoses.
+pub struct DoctorOptions<'a> {
+    pub host: &'a str,
+    pub endpoint: &'a Endpoint,
+    /// The configuration of the connection (e.g. its TLS options).
+    pub config: Config,
+    /// The credentials (none for an embedded database), or why they are missing.
+    pub credentials: Result<Option<Credentials>, String>,
+    pub auth_level: AuthLevel,
+    /// The namespaces and databases to check, in order.
+    pub targets: &'a [(String, String)],
+    pub table: &'a str,
+    pub source: &'a MultiSource<FsSource>,
+    pub pattern: &'a FilePattern,
+}
+
+/// Prints the result of each check with, for the failing ones, how to fix them.
+#[derive(Default)]
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn pass(&mut self, check: &str, message: impl Display) {
+        output::success(format!("{}: {}", check, message));
+    }
+
+    fn fail(&mut self, check: &str, problem: impl Display, fix: impl Display) {
+        self.failures += 1;
+        output::item(Marker::Failure, format!("{}: {}", check, problem));
+        output::detail(format!("Fix: {}", fix));
+    }
+}
+
+/// Checks the migration files, the connection, the authentication, then for each target the existence of the
+/// namespace and the database, the permission to write and the health of the migrations table. Exits with 1
+/// when a check fails.
+pub async fn doctor(options: DoctorOptions<'_>) -> ExitCode {
+    let mut report = Report::default();
+
+    check_files(&mut report, options.source, options.pattern).await;
+
+    match connect(options.endpoint, options.config.clone()).await {
+        Ok((db, version)) => {
+            report.pass("Connection", format!("connected to {} (SurrealDB {})", options.host, version));
+
+            match options.credentials {
+                Ok(ref credentials) => {
+                    for (namespace, database) in options.targets {
+                        if options.targets.len() > 1 {
+                            output::heading(format!("{}/{}", namespace, database));
+                        }
+                        check_target(&mut report, &db, credentials.as_ref(), &options, namespace, database).await;
+                    }
+                },
+                Err(ref message) => report.fail(
+                    "Authentication",
+                    message,
+                    "Set a username and a password (-U and -P, or SSM_USERNAME and SSM_PASSWORD), or a token (--token or SSM_TOKEN).",
+                ),
+            }
+        },
+        Err(e) => report.fail(
+            "Connection",
+            format!("failed to connect to {}: {}", options.host, e),
+            "Check the host (-H or SSM_HOST), its scheme and TLS options, and that the SurrealDB instance is running and reachable.",
+        ),
+    }
+
+    if report.failures > 0 {
+        output::info(format!("{} check(s) failed.", report.failures));
+        ExitCode::from(exit::FAILURE)
+    } else {
+        output::success("Everything looks good.");
+        ExitCode::SUCCESS
+    }
+}
+
+async fn connect(endpoint: &Endpoint, config: Config) -> Result<(Surreal<Any>, String), surrealdb::Error> {
+    let db = endpoint::connect(endpoint, config).await?;
+    let version = db.version().await?;
+
+    Ok((db, version.to_string()))
+}
+
+/// Checks the migration files follow the naming convention, without connecting to the database.
+async fn check_files(report: &mut Report, source: &MultiSource<FsSource>, pattern: &FilePattern) {
+    let issues = match lint(source, &LintConfig::new().file_pattern(pattern.clone())).await {
+        Ok(issues) => issues,
+        Err(e) => {
+            report.fail("Migration files", e, "Check the path of the migration files (-p or SSM_PATH) and its permissions.");
+            return;
+        },
+    };
+
+    let misnamed: Vec<String> = issues
+        .into_iter()
+        .filter(|issue| issue.rule == LintRule::Naming)
+        .map(|issue| issue.filename)
+        .collect();
+
+    if misnamed.is_empty() {
+        report.pass("Migration files", "the files follow the naming convention");
+    } else {
+        report.fail(
+            "Migration files",
+            format!("not named after the convention: {}", misnamed.join(", ")),
+            "Rename them `<number>_<name>.surql` (e.g. `004_add_email_index.surql`), or `R__<name>.surql` for the repeatable ones.",
+        );
+    }
+}
+
+async fn check_target(
+    report: &mut Report,
+    db: &Surreal<Any>,
+    credentials: Option<&Credentials>,
+    options: &DoctorOptions<'_>,
+    namespace: &str,
+    database: &str,
+) {
+    let result = match credentials {
+        Some(Credentials::User { username, password }) => {
+            auth::signin(db, options.auth_level, namespace, database, username, password).await
+        },
+        Some(Credentials::Record { access, params }) => auth::signin_record(db, namespace, database, access, params).await,
+        Some(Credentials::Token(token)) => auth::authenticate(db, token.clone()).await,
+        None => Ok(()),
+    };
+    match (result, credentials) {
+        (Err(e), Some(Credentials::Record { access, .. })) => {
+            report.fail(
+                "Authentication",
+                format!("failed to sign in as a record user of the {} access: {}", access, e),
+                "Check the access method (--access) and its parameters (--auth-param), which are the ones its SIGNIN clause uses.",
+            );
+            return;
+        },
+        (Err(e), _) => {
+            report.fail(
+                "Authentication",
+                format!("failed to sign in as a {} user: {}", level_name(options.auth_level), e),
+                "Check the credentials, and that --auth-level (or SSM_AUTH_LEVEL) is the level the user is defined on: root, namespace or database.",
+            );
+            return;
+        },
+        (Ok(_), Some(Credentials::Record { access, .. })) => {
+            report.pass("Authentication", format!("signed in as a record user of the {} access", access))
+        },
+        (Ok(_), Some(_)) => report.pass("Authentication", format!("signed in as a {} user", level_name(options.auth_level))),
+        (Ok(_), None) => report.pass("Authentication", "not needed for an embedded database"),
+    }
+
+    // Using a namespace or a database does not create them, but reading the database fails when they are missing.
+    let result = match db.use_ns(namespace).use_db(database).await {
+        Ok(_) => db.query("INFO FOR DB;").await.and_then(|response| response.check()),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(_) => report.pass("Database", format!("{}/{} exists", namespace, database)),
+        Err(e) if e.to_string().contains("does not exist") => {
+            report.fail(
+                "Database",
+                e,
+                format!(
+                    "Check the namespace and the database (-n and -d), or create them with `DEFINE NAMESPACE IF NOT EXISTS {}; \
+                     USE NS {}; DEFINE DATABASE IF NOT EXISTS {};`.",
+                    namespace, namespace, database
+                ),
+            );
+            return;
+        },
+        Err(e) => {
+            report.fail("Database", e, "Check the user is allowed to access the database, with its level and its roles.");
+            return;
+        },
+    }
+
+    // A temporary table, so nothing is left behind once the permission is checked.
+    let result = db
+        .query("CREATE ssm_doctor:check SET checked_at = time::now();")
+        .query("REMOVE TABLE ssm_doctor;")
+        .await
+        .and_then(|response| response.check());
+    match result {
+        Ok(_) => report.pass("Write permission", "created and removed a temporary record"),
+        Err(e) => report.fail(
+            "Write permission",
+            e,
+            "Grant the user the EDITOR (or OWNER) role on the database, as the migrations define its tables and change its data. \
+             Remove the `ssm_doctor` table if it is left behind.",
+        ),
+    }
+
+    check_migrations_table(report, db, options).await;
+}
+
+/// Checks the migrations table is consistent with the migration files.
+async fn check_migrations_table(report: &mut Report, db: &Surreal<Any>, options: &DoctorOptions<'_>) {
+    let migrator = || {
+        Migrator::new(db)
+            .source(options.source)
+            .file_pattern(options.pattern.clone())
+            .table(options.table)
+    };
+
+    let result = match migrator().status().await {
+        Ok(statuses) => migrator().dry_run(true).repair().await.map(|repair| (statuses, repair)),
+        Err(e) => Err(e),
+    };
+
+    let (statuses, repair) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            let fix = match e {
+                Error::InvalidName { .. } => "Use a table name (--table-name) of letters, numbers and underscores.",
+                Error::DuplicateVersion { .. } => "Renumber one of the migration files sharing the version.",
+                Error::MissingDependency { .. } | Error::DependencyCycle { .. } => "Fix the `-- ssm:requires` directives of the migration files.",
+                _ => "Run `ssm status` for more details.",
+            };
+            report.fail("Migrations table", e, fix);
+            return;
+        },
+    };
+
+    if !repair.updated_checksums.is_empty() {
+        report.fail(
+            "Migrations table",
+            format!("applied files changed since their migration: {}", repair.updated_checksums.join(", ")),
+            "Restore the files (e.g. with git), or run `ssm repair` if the changes are intended.",
+        );
+    }
+    if !repair.removed.is_empty() {
+        report.fail(
+            "Migrations table",
+            format!("applied files missing from the migration directory: {}", repair.removed.join(", ")),
+            "Restore the files (e.g. with git), or run `ssm prune --yes` if they have been deleted on purpose.",
+        );
+    }
+    if !repair.deduplicated.is_empty() {
+        report.fail(
+            "Migrations table",
+            format!("migrations recorded more than once: {}", repair.deduplicated.join(", ")),
+            "Run `ssm repair` to remove the extra records.",
+        );
+    }
+
+    if repair.is_empty() {
+        let count = |state: MigrationState| statuses.iter().filter(|status| status.state == state).count();
+        report.pass(
+            "Migrations table",
+            format!("{} applied, {} pending", count(MigrationState::Applied), count(MigrationState::Pending)),
+        );
+    }
+}
+
+fn level_name(level: AuthLevel) -> &'static str {
+    match level {
+        AuthLevel::Root => "root",
+        AuthLevel::Namespace => "namespace",
+        AuthLevel::Database => "database",
+    }
+}
@@ -0,0 +1,27 @@
+use std::{env, io::ErrorKind};
+
+/// The `.env` file read when `--env-file` is not given.
+pub const DEFAULT_ENV_FILE_PATH: &str = ".env";
+
+/// Sets the `SSM_*` variables of the `.env` file at `path` (or of `.env` if it exists when no path is given)
+/// that are not already set in the process environment.
+pub fn load(path: Option<&str>) -> Result<(), String> {
+    let variables = match dotenvy::from_path_iter(path.unwrap_or(DEFAULT_ENV_FILE_PATH)) {
+        Ok(variables) => variables,
+        Err(dotenvy::Error::Io(err)) if err.kind() == ErrorKind::NotFound && path.is_none() => return Ok(()),
+        Err(err) => {
+            return Err(format!("Failed to read the env file '{}': {}", path.unwrap_or(DEFAULT_ENV_FILE_PATH), err))
+        },
+    };
+
+    for variable in variables {
+        let (key, value) = variable.map_err(|err| format!("Failed to parse the env file: {}", err))?;
+
+        // The process environment takes precedence over the file.
+        if key.starts_with("SSM_") && env::var_os(&key).is_none() {
+            env::set_var(key, value);
+        }
+    }
+
+    Ok(())
+}
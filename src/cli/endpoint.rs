@@ -0,0 +1,248 @@
+use std::path::PathBuf;
+#[cfg(feature = "rustls")]
+use std::{fs, path::Path, sync::Arc};
+
+use surrealdb::{engine::any::{self, Any}, opt::Config, Surreal};
+
+/// The SurrealDB instance to connect to, parsed from the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    /// The address of the instance for the `any` engine, e.g. "ws://localhost:8000" or "mem://".
+    pub url: String,
+    /// Whether the database is embedded in the process (in memory or on disk), without users to sign in as.
+    pub embedded: bool,
+    /// Whether the connection is encrypted (`wss://` or `https://`).
+    pub tls: bool,
+}
+
+impl Endpoint {
+    /// Parses a host, inferring the engine from its scheme: `ws://`, `wss://`, `http://`, `https://`, `memory`
+    /// (or `mem://`) and `rocksdb://<path>`. A host without scheme (e.g. "localhost:8000") is a WebSocket one.
+    pub fn parse(host: &str) -> Result<Self, String> {
+        let host = host.trim();
+        if host == "memory" || host == "mem://" {
+            return Ok(Endpoint { url: "mem://".to_string(), embedded: true, tls: false });
+        }
+
+        let Some((scheme, address)) = host.split_once("://") else {
+            return Ok(Endpoint { url: format!("ws://{}", host), embedded: false, tls: false });
+        };
+
+        let scheme = scheme.to_ascii_lowercase();
+        match scheme.as_str() {
+            "ws" | "wss" => {},
+            "http" | "https" if !cfg!(feature = "protocol-http") => {
+                return Err(format!("The {}:// hosts require the `protocol-http` feature.", scheme));
+            },
+            "http" | "https" => {},
+            "rocksdb" if !cfg!(feature = "kv-rocksdb") => {
+                return Err("The rocksdb:// hosts require the `kv-rocksdb` feature.".to_string());
+            },
+            "rocksdb" if address.is_empty() => {
+                return Err("The rocksdb:// host must be followed by the path of the database (e.g. rocksdb://./data).".to_string());
+            },
+            "rocksdb" => return Ok(Endpoint { url: format!("rocksdb://{}", address), embedded: true, tls: false }),
+            _ => {
+                return Err(format!(
+                    "Unsupported scheme '{}://' in the host: use ws://, wss://, http://, https://, memory or rocksdb://.",
+                    scheme
+                ));
+            },
+        }
+
+        if address.is_empty() {
+            return Err(format!("The host '{}' has no address.", host));
+        }
+
+        Ok(Endpoint {
+            url: format!("{}://{}", scheme, address),
+            embedded: false,
+            tls: scheme == "wss" || scheme == "https",
+        })
+    }
+}
+
+/// The options of the encrypted connections, e.g. to an instance with a certificate of a private authority.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    /// The PEM file of the certificate authorities trusted in addition to the public ones.
+    pub ca_cert: Option<PathBuf>,
+    /// The PEM file of the client certificate and its private key, for the instances requiring mutual TLS.
+    pub client_cert: Option<PathBuf>,
+    /// Accept any certificate of the server. Only to debug a connection, never in production.
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsOptions {
+    fn is_set(&self) -> bool {
+        self.ca_cert.is_some() || self.client_cert.is_some() || self.insecure_skip_verify
+    }
+
+    /// Returns the configuration of the connections to an endpoint.
+    pub fn config(&self, endpoint: &Endpoint) -> Result<Config, String> {
+        if !self.is_set() {
+            return Ok(Config::new());
+        }
+
+        if !endpoint.tls {
+            return Err(
+                "The TLS options (--ca-cert, --client-cert and --insecure-skip-verify) only apply to wss:// and https:// hosts."
+                    .to_string()
+            );
+        }
+
+        #[cfg(feature = "rustls")]
+        return Ok(Config::new().rustls(self.rustls_config()?));
+
+        #[cfg(not(feature = "rustls"))]
+        Err("The TLS options require the `rustls` feature.".to_string())
+    }
+
+    #[cfg(feature = "rustls")]
+    fn rustls_config(&self) -> Result<rustls::ClientConfig, String> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+        if let Some(ref path) = self.ca_cert {
+            for cert in read_certs(path)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| format!("Invalid CA certificate in {}: {}", path.display(), e))?;
+            }
+        }
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_root_certificates(roots);
+
+        let mut config = match self.client_cert {
+            Some(ref path) => {
+                let certs = read_certs(path)?;
+                let pem = read_pem(path)?;
+                let key = rustls_pemfile::private_key(&mut pem.as_slice())
+                    .map_err(|e| format!("Failed to read the private key of {}: {}", path.display(), e))?
+                    .ok_or_else(|| format!("The client certificate {} has no private key.", path.display()))?;
+
+                builder
+                    .with_client_auth_cert(certs, key)
+                    .map_err(|e| format!("Invalid client certificate {}: {}", path.display(), e))?
+            },
+            None => builder.with_no_client_auth(),
+        };
+
+        if self.insecure_skip_verify {
+            config.dangerous().set_certificate_verifier(Arc::new(danger::SkipVerification(provider)));
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(feature = "rustls")]
+fn read_pem(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+/// Reads the certificates of a PEM file.
+#[cfg(feature = "rustls")]
+fn read_certs(path: &Path) -> Result<Vec<rustls::pki_types::CertificateDer<'static>>, String> {
+    let pem = read_pem(path)?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read the certificates of {}: {}", path.display(), e))?;
+
+    if certs.is_empty() {
+        return Err(format!("{} contains no certificate.", path.display()));
+    }
+
+    Ok(certs)
+}
+
+#[cfg(feature = "rustls")]
+mod danger {
+    use std::sync::Arc;
+
+    use rustls::{
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+        pki_types::{CertificateDer, ServerName, UnixTime},
+        DigitallySignedStruct, SignatureScheme,
+    };
+
+    /// Accepts any certificate of the server (`--insecure-skip-verify`), still checking the handshake signatures.
+    #[derive(Debug)]
+    pub struct SkipVerification(pub Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for SkipVerification {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+}
+
+/// Connects to an endpoint with the engine matching its scheme.
+pub async fn connect(endpoint: &Endpoint, config: Config) -> Result<Surreal<Any>, surrealdb::Error> {
+    any::connect((endpoint.url.as_str(), config)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Endpoint, TlsOptions};
+
+    #[test]
+    fn it_infers_the_engine_from_the_host() {
+        assert_eq!(
+            Endpoint::parse("localhost:8000").unwrap(),
+            Endpoint { url: "ws://localhost:8000".to_string(), embedded: false, tls: false }
+        );
+        assert_eq!(
+            Endpoint::parse("wss://db.example.com").unwrap(),
+            Endpoint { url: "wss://db.example.com".to_string(), embedded: false, tls: true }
+        );
+        assert_eq!(
+            Endpoint::parse("memory").unwrap(),
+            Endpoint { url: "mem://".to_string(), embedded: true, tls: false }
+        );
+        assert!(Endpoint::parse("ftp://db.example.com").is_err());
+        assert!(Endpoint::parse("ws://").is_err());
+    }
+
+    #[test]
+    fn it_only_accepts_the_tls_options_with_an_encrypted_connection() {
+        let tls = TlsOptions { insecure_skip_verify: true, ..Default::default() };
+
+        assert!(tls.config(&Endpoint::parse("ws://localhost:8000").unwrap()).is_err());
+        assert!(TlsOptions::default().config(&Endpoint::parse("ws://localhost:8000").unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,89 @@
+use std::process::ExitCode;
+
+use surrealdb_simple_migration::Error;
+
+use super::output;
+
+/// A connection, authentication, configuration or usage failure.
+pub const FAILURE: u8 = 1;
+
+/// An applied migration file changed.
+pub const FORBIDDEN_UPDATE: u8 = 2;
+
+/// An applied migration file (or its down file) is missing.
+pub const MISSING_MIGRATION: u8 = 3;
+
+/// A statement failed on the database.
+pub const SQL_ERROR: u8 = 4;
+
+/// A pending migration file has a lower version than the last applied one.
+pub const OUT_OF_ORDER: u8 = 5;
+
+/// The migrations are locked by another migrator.
+pub const LOCK_HELD: u8 = 6;
+
+/// Two migration files share the same version.
+pub const DUPLICATE_VERSION: u8 = 7;
+
+/// The schema of the database differs from the one resulting from the migrations.
+pub const SCHEMA_DRIFT: u8 = 8;
+
+/// The lint found an issue with the `error` severity.
+pub const LINT_FAILED: u8 = 9;
+
+/// The migrations (or a migration file) did not complete within the timeout.
+pub const TIMEOUT: u8 = 10;
+
+/// The pending migrations differ from the plan passed to `apply --plan`.
+pub const PLAN_MISMATCH: u8 = 11;
+
+/// The user is not allowed to apply the migrations.
+pub const INSUFFICIENT_PERMISSIONS: u8 = 12;
+
+/// The migration history does not apply to the empty shadow database of `apply --shadow`.
+pub const SHADOW_FAILED: u8 = 13;
+
+/// An assertion file of `test` did not return true.
+pub const ASSERTION_FAILED: u8 = 14;
+
+/// Returns the exit code matching an error.
+pub fn code(err: &Error) -> u8 {
+    match *err {
+        Error::ChecksumMismatch { .. } => FORBIDDEN_UPDATE,
+        Error::MissingMigration { .. } | Error::MissingDownMigration { .. } => MISSING_MIGRATION,
+        Error::Surreal(_) | Error::ParseError { .. } | Error::StatementFailed { .. } => SQL_ERROR,
+        Error::OutOfOrder { .. } => OUT_OF_ORDER,
+        Error::LockHeld { .. } => LOCK_HELD,
+        Error::DuplicateVersion { .. } => DUPLICATE_VERSION,
+        Error::Timeout { .. } => TIMEOUT,
+        Error::PlanMismatch { .. } => PLAN_MISMATCH,
+        Error::InsufficientPermissions { .. } => INSUFFICIENT_PERMISSIONS,
+        Error::ShadowFailed { .. } => SHADOW_FAILED,
+        // The IO, naming and variable errors, and the ones added to the library later.
+        _ => FAILURE,
+    }
+}
+
+/// Prints an error with its context and returns the matching exit code.
+pub fn fail(context: &str, err: Error) -> ExitCode {
+    output::failure_in(err.filename(), err.line(), format!("{}: {} (error code {})", context, err, err.code()));
+    if is_permission_error(&err) {
+        output::detail(
+            "The user is not allowed to do this. A record user (--access) only has the permissions granted by the \
+             PERMISSIONS clauses of the tables: defining resources or writing the migrations table may need a system user \
+             (--auth-level) or permissions granted to the record users."
+        );
+    }
+
+    ExitCode::from(code(&err))
+}
+
+/// Returns whether the database refused an operation to the user signed in.
+fn is_permission_error(err: &Error) -> bool {
+    if let Error::InsufficientPermissions { .. } = *err {
+        return true;
+    }
+
+    let message = err.to_string();
+    message.contains("Not enough permissions") || message.contains("permissions to perform this action")
+}
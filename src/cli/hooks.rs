@@ -0,0 +1,73 @@
+use std::process::Command;
+
+use serde::Deserialize;
+
+/// The shell commands run around `apply` and `up`, in the `[hooks]` table of the configuration file, e.g. to send
+/// a notification or bust a cache without writing Rust. They receive the context of the run as `SSM_*`
+/// environment variables.
+///
+/// ```toml
+/// [hooks]
+/// before_apply = "./scripts/maintenance.sh on"
+/// after_apply = "curl -X POST https://cdn.example.com/purge && ./scripts/maintenance.sh off"
+/// on_failure = "./scripts/notify.sh \"$SSM_DATABASE: $SSM_ERROR\""
+/// ```
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ShellHooks {
+    /// Run before the migrations are applied, which are not when it fails.
+    pub before_apply: Option<String>,
+    /// Run once the migrations are applied, with `SSM_APPLIED_COUNT`.
+    pub after_apply: Option<String>,
+    /// Run when the migrations fail, with `SSM_APPLIED_COUNT` and `SSM_ERROR`.
+    pub on_failure: Option<String>,
+}
+
+impl ShellHooks {
+    /// Returns the hooks, overridden by the ones set in `other`.
+    pub fn merge(self, other: ShellHooks) -> ShellHooks {
+        ShellHooks {
+            before_apply: other.before_apply.or(self.before_apply),
+            after_apply: other.after_apply.or(self.after_apply),
+            on_failure: other.on_failure.or(self.on_failure),
+        }
+    }
+}
+
+/// Runs the command of a hook in the shell, its output printed along the one of the CLI, with the `SSM_HOOK`
+/// (its name) and the given environment variables.
+pub fn run(name: &str, command: &str, vars: &[(&str, String)]) -> Result<(), String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    };
+
+    let status = shell
+        .env("SSM_HOOK", name)
+        .envs(vars.iter().cloned())
+        .status()
+        .map_err(|e| format!("Failed to run the {} hook: {}", name, e))?;
+
+    if !status.success() {
+        return Err(format!("The {} hook failed ({}).", name, status));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+
+    #[test]
+    #[cfg_attr(windows, ignore)]
+    fn it_runs_the_hooks_with_the_context() {
+        assert!(run("after_apply", "test \"$SSM_HOOK:$SSM_APPLIED_COUNT\" = after_apply:2", &[("SSM_APPLIED_COUNT", "2".to_string())]).is_ok());
+        assert!(run("before_apply", "exit 1", &[]).is_err());
+    }
+}
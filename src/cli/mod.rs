@@ -0,0 +1,12 @@
+pub mod auth;
+pub mod backup;
+pub mod config;
+pub mod confirm;
+pub mod doctor;
+pub mod dotenv;
+pub mod endpoint;
+pub mod exit;
+pub mod hooks;
+pub mod output;
+pub mod preview;
+pub mod watch;
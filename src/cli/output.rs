@@ -0,0 +1,295 @@
+use std::{
+    env,
+    fmt::Display,
+    future::Future,
+    io::{stderr, stdout, IsTerminal},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const DIM: &str = "\x1b[2m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// How much the commands print, set by `--quiet` and `--verbose`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only the errors.
+    Quiet,
+    Normal,
+    /// Also the debug logs, including the SQL executed for each migration file.
+    Verbose,
+}
+
+struct Output {
+    verbosity: Verbosity,
+    /// Whether the colors are allowed, still only used on a terminal.
+    color: bool,
+}
+
+static OUTPUT: OnceLock<Output> = OnceLock::new();
+
+/// How the failures and warnings are printed, set by `--output`.
+#[derive(ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// For a terminal.
+    #[default]
+    Text,
+    /// As GitHub Actions workflow commands (e.g. `::error file=...,line=...::message`), annotating the migration
+    /// files on the pull requests.
+    Github,
+}
+
+struct Annotations {
+    format: OutputFormat,
+    /// The migration directories, to find the path of the annotated files.
+    dirs: Vec<PathBuf>,
+}
+
+static ANNOTATIONS: OnceLock<Annotations> = OnceLock::new();
+
+tokio::task_local! {
+    /// The target (e.g. "app/tenant_1") whose command prints the lines, when several run concurrently.
+    static TARGET: String;
+}
+
+/// Sets how much is printed and whether colors are allowed (they are not with `--no-color` or `NO_COLOR`), once
+/// the arguments are parsed.
+pub fn init(verbosity: Verbosity, no_color: bool) {
+    let _ = OUTPUT.set(Output { verbosity, color: !no_color && env::var_os("NO_COLOR").is_none() });
+}
+
+/// Sets how the failures and warnings are printed, once the migration directories are known.
+pub fn init_format(format: OutputFormat, dirs: Vec<PathBuf>) {
+    let _ = ANNOTATIONS.set(Annotations { format, dirs });
+}
+
+/// Runs the command of a target, each line it prints starting with `[<target>]` so the ones of the targets run
+/// concurrently can be told apart.
+pub async fn with_target<F: Future>(target: String, command: F) -> F::Output {
+    TARGET.scope(target, command).await
+}
+
+/// Prefixes a message with the target printing it, if any.
+fn prefixed(message: impl Display) -> String {
+    TARGET
+        .try_with(|target| format!("[{}] {}", target, message))
+        .unwrap_or_else(|_| message.to_string())
+}
+
+fn is_github() -> bool {
+    ANNOTATIONS.get().is_some_and(|annotations| annotations.format == OutputFormat::Github)
+}
+
+/// Returns the path of a migration file from the root of the repository, as expected by the annotations: the
+/// first of the migration directories containing it (the subdirectories are not searched).
+fn annotated_path(filename: &str) -> PathBuf {
+    let path = ANNOTATIONS
+        .get()
+        .and_then(|annotations| annotations.dirs.iter().map(|dir| dir.join(filename)).find(|path| path.is_file()))
+        .unwrap_or_else(|| PathBuf::from(filename));
+
+    path.strip_prefix(".").map(Path::to_path_buf).unwrap_or(path)
+}
+
+/// Prints a GitHub Actions workflow command (`error` or `warning`), annotating a line of a file when known.
+fn workflow_command(command: &str, filename: Option<&str>, line: Option<usize>, message: impl Display) {
+    let mut properties: Vec<String> = vec![];
+    if let Some(filename) = filename {
+        properties.push(format!("file={}", escape_property(&annotated_path(filename).to_string_lossy())));
+        if let Some(line) = line {
+            properties.push(format!("line={}", line));
+        }
+    }
+
+    let properties = if properties.is_empty() { String::new() } else { format!(" {}", properties.join(",")) };
+    println!("::{}{}::{}", command, properties, escape_data(&prefixed(message)));
+}
+
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+fn escape_property(property: &str) -> String {
+    escape_data(property).replace(':', "%3A").replace(',', "%2C")
+}
+
+fn output() -> &'static Output {
+    OUTPUT.get_or_init(|| Output { verbosity: Verbosity::Normal, color: false })
+}
+
+fn is_quiet() -> bool {
+    output().verbosity == Verbosity::Quiet
+}
+
+/// The marker of a line, telling the state of what it reports at a glance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    /// Applied, verified or created.
+    Success,
+    /// Failed, or inconsistent with the migration files. Printed even with `--quiet`.
+    Failure,
+    /// Not an error, but worth a look.
+    Warning,
+    /// Not applied yet.
+    Pending,
+    /// Left aside (e.g. for other environments), or not defined by the migrations.
+    Skipped,
+    /// Missing from the database.
+    Added,
+    /// Defined differently.
+    Changed,
+}
+
+impl Marker {
+    fn symbol(self) -> &'static str {
+        match self {
+            Marker::Success => "✓",
+            Marker::Failure => "✗",
+            Marker::Warning => "!",
+            Marker::Pending => "○",
+            Marker::Skipped => "-",
+            Marker::Added => "+",
+            Marker::Changed => "~",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Marker::Success | Marker::Added => GREEN,
+            Marker::Failure => RED,
+            Marker::Warning | Marker::Changed => YELLOW,
+            Marker::Pending | Marker::Skipped => DIM,
+        }
+    }
+}
+
+fn paint(text: impl Display, color: &str, enabled: bool) -> String {
+    if enabled {
+        format!("{}{}{}", color, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Prints a line of a report (e.g. a migration file and its state) on the standard output. Only the failures are
+/// printed with `--quiet`.
+pub fn item(marker: Marker, message: impl Display) {
+    if is_quiet() && marker != Marker::Failure {
+        return;
+    }
+
+    let color = output().color && stdout().is_terminal();
+    println!("{}", prefixed(format!("{} {}", paint(marker.symbol(), marker.color(), color), message)));
+}
+
+/// Prints a success, e.g. a migration file applied.
+pub fn success(message: impl Display) {
+    item(Marker::Success, message);
+}
+
+/// Prints a line of a report about a migration file, at a line of the file if known. With `--output github`, the
+/// failures and warnings annotate the file instead.
+pub fn item_in(marker: Marker, filename: &str, line: Option<usize>, message: impl Display) {
+    match marker {
+        Marker::Failure if is_github() => workflow_command("error", Some(filename), line, message),
+        Marker::Warning if is_github() => {
+            if !is_quiet() {
+                workflow_command("warning", Some(filename), line, message);
+            }
+        },
+        _ => item(marker, message),
+    }
+}
+
+/// Prints a failure on the standard error, even with `--quiet`.
+pub fn failure(message: impl Display) {
+    failure_in(None, None, message);
+}
+
+/// Prints a failure about a migration file (at a line of the file, if known), annotating the file with `--output
+/// github`. Printed even with `--quiet`.
+pub fn failure_in(filename: Option<&str>, line: Option<usize>, message: impl Display) {
+    if is_github() {
+        return workflow_command("error", filename, line, message);
+    }
+
+    let color = output().color && stderr().is_terminal();
+    eprintln!("{}", prefixed(format!("{} {}", paint(Marker::Failure.symbol(), RED, color), paint(message, RED, color))));
+}
+
+/// Prints a warning in yellow on the standard error, unless `--quiet`.
+pub fn warning(message: impl Display) {
+    warning_in(None, message);
+}
+
+/// Prints a warning about a migration file, annotating the file with `--output github`. Not printed with
+/// `--quiet`.
+pub fn warning_in(filename: Option<&str>, message: impl Display) {
+    if is_quiet() {
+        return;
+    }
+    if is_github() {
+        return workflow_command("warning", filename, None, message);
+    }
+
+    let color = output().color && stderr().is_terminal();
+    eprintln!("{}", prefixed(paint(format!("{} {}", Marker::Warning.symbol(), message), YELLOW, color)));
+}
+
+/// Prints an error without marker (e.g. an invalid option) on the standard error, even with `--quiet`.
+pub fn error(message: impl Display) {
+    if is_github() {
+        return workflow_command("error", None, None, message);
+    }
+
+    let color = output().color && stderr().is_terminal();
+    eprintln!("{}", prefixed(paint(message, RED, color)));
+}
+
+/// Prints a message (e.g. a summary), unless `--quiet`.
+pub fn info(message: impl Display) {
+    if !is_quiet() {
+        println!("{}", prefixed(message));
+    }
+}
+
+/// Prints the indented details of the previous line (e.g. a statement, or how to fix a failure), unless `--quiet`.
+pub fn detail(message: impl Display) {
+    if !is_quiet() {
+        let color = output().color && stdout().is_terminal();
+        println!("{}", prefixed(format!("    {}", paint(message, DIM, color))));
+    }
+}
+
+/// Prints the heading of a section (e.g. the database a command runs on), unless `--quiet`.
+pub fn heading(message: impl Display) {
+    if !is_quiet() {
+        let color = output().color && stdout().is_terminal();
+        println!("{}", paint(format!("== {} ==", message), BOLD, color));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_data, escape_property, prefixed, with_target};
+
+    #[test]
+    fn it_escapes_the_workflow_commands() {
+        assert_eq!(escape_data("100% failed:\nDEFINE TABLE a, b;"), "100%25 failed:%0ADEFINE TABLE a, b;");
+        assert_eq!(escape_property("migrations/001_a,b:c.surql"), "migrations/001_a%2Cb%3Ac.surql");
+    }
+
+    #[tokio::test]
+    async fn it_prefixes_the_lines_of_a_target() {
+        assert_eq!(prefixed("002_posts.surql"), "002_posts.surql");
+        assert_eq!(with_target("app/tenant_1".to_string(), async { prefixed("002_posts.surql") }).await, "[app/tenant_1] 002_posts.surql");
+    }
+}
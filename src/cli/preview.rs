@@ -0,0 +1,146 @@
+use std::{
+    env,
+    io::{stdout, IsTerminal, Write},
+    process::{Command, Stdio},
+};
+
+const KEYWORD: &str = "\x1b[1;34m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const COMMENT: &str = "\x1b[2;37m";
+const HEADER: &str = "\x1b[1;35m";
+const RESET: &str = "\x1b[0m";
+
+/// The words highlighted as keywords, compared case-insensitively.
+const KEYWORDS: &[&str] = &[
+    "ALTER", "ANALYZER", "AND", "AS", "ASSERT", "BEGIN", "BY", "CANCEL", "COMMIT", "CONTENT", "CREATE", "DATABASE",
+    "DEFAULT", "DEFINE", "DELETE", "ELSE", "END", "EVENT", "EXISTS", "FALSE", "FIELD", "FILTERS", "FLEXIBLE", "FOR",
+    "FROM", "FULL", "FUNCTION", "IF", "IN", "INDEX", "INSERT", "INTO", "LET", "LIMIT", "MERGE", "NAMESPACE", "NONE",
+    "NOT", "NULL", "ON", "OR", "ORDER", "OVERWRITE", "PARAM", "PERMISSIONS", "READONLY", "RELATE", "REMOVE",
+    "RETURN", "SCHEMAFULL", "SCHEMALESS", "SELECT", "SET", "TABLE", "THEN", "THROW", "TOKENIZERS", "TRANSACTION",
+    "TRUE", "TYPE", "UNIQUE", "UPDATE", "UPSERT", "USE", "VALUE", "WHEN", "WHERE",
+];
+
+/// Whether the output should be colored: on a terminal, unless `--no-color` is given or `NO_COLOR` is set.
+pub fn use_color(no_color: bool) -> bool {
+    !no_color && env::var_os("NO_COLOR").is_none() && stdout().is_terminal()
+}
+
+/// Formats the header of a previewed migration file.
+pub fn header(text: &str, color: bool) -> String {
+    if color {
+        format!("{}{}{}", HEADER, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Highlights the keywords, strings, numbers and comments of SurrealQL statements with ANSI colors.
+pub fn highlight(sql: &str) -> String {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut output = String::with_capacity(sql.len());
+    let mut i = 0;
+
+    let colored = |output: &mut String, color: &str, token: &[char]| {
+        output.push_str(color);
+        output.extend(token);
+        output.push_str(RESET);
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        // Comments run until the end of the line (or of the block).
+        if c == '#' || (c == '-' && next == Some('-')) || (c == '/' && next == Some('/')) {
+            let end = chars[i..].iter().position(|c| *c == '\n').map_or(chars.len(), |end| i + end);
+            colored(&mut output, COMMENT, &chars[i..end]);
+            i = end;
+        } else if c == '/' && next == Some('*') {
+            let end = (i + 2..chars.len().saturating_sub(1))
+                .find(|j| chars[*j] == '*' && chars[*j + 1] == '/')
+                .map_or(chars.len(), |end| end + 2);
+            colored(&mut output, COMMENT, &chars[i..end]);
+            i = end;
+        } else if c == '\'' || c == '"' {
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != c {
+                end += if chars[end] == '\\' { 2 } else { 1 };
+            }
+            let end = (end + 1).min(chars.len());
+            colored(&mut output, STRING, &chars[i..end]);
+            i = end;
+        } else if c.is_alphabetic() || c == '_' {
+            let end = chars[i..]
+                .iter()
+                .position(|c| !c.is_alphanumeric() && *c != '_')
+                .map_or(chars.len(), |end| i + end);
+            let word: String = chars[i..end].iter().collect();
+            // Not a keyword when part of a path (e.g. `fn::string::len`) or a field (e.g. `user.type`).
+            let is_path = i > 0 && matches!(chars[i - 1], ':' | '.' | '$');
+            if !is_path && KEYWORDS.contains(&word.to_uppercase().as_str()) {
+                colored(&mut output, KEYWORD, &chars[i..end]);
+            } else {
+                output.push_str(&word);
+            }
+            i = end;
+        } else if c.is_ascii_digit() {
+            let end = chars[i..]
+                .iter()
+                .position(|c| !c.is_ascii_alphanumeric() && *c != '.')
+                .map_or(chars.len(), |end| i + end);
+            colored(&mut output, NUMBER, &chars[i..end]);
+            i = end;
+        } else {
+            output.push(c);
+            i += 1;
+        }
+    }
+
+    output
+}
+
+/// Prints the output through the `PAGER` (default: `less -FRX`) when it is a terminal, or else directly.
+pub fn page(output: &str, no_pager: bool) {
+    if no_pager || !stdout().is_terminal() || spawn_pager(output).is_err() {
+        print!("{}", output);
+    }
+}
+
+fn spawn_pager(output: &str) -> std::io::Result<()> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less -FRX".to_string());
+    let mut words = pager.split_whitespace();
+    let Some(program) = words.next() else {
+        return Err(std::io::ErrorKind::NotFound.into());
+    };
+
+    let mut child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may be quit before reading the whole output.
+        let _ = stdin.write_all(output.as_bytes());
+    }
+    child.wait()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::highlight;
+
+    #[test]
+    fn it_highlights_surrealql() {
+        let highlighted = highlight("DEFINE field name ON user TYPE string; -- The name.\nCREATE user:1 SET name = 'type', age = 42;");
+
+        assert_eq!(
+            highlighted,
+            "\x1b[1;34mDEFINE\x1b[0m \x1b[1;34mfield\x1b[0m name \x1b[1;34mON\x1b[0m user \x1b[1;34mTYPE\x1b[0m string; \
+             \x1b[2;37m-- The name.\x1b[0m\n\
+             \x1b[1;34mCREATE\x1b[0m user:\x1b[33m1\x1b[0m \x1b[1;34mSET\x1b[0m name = \x1b[32m'type'\x1b[0m, age = \x1b[33m42\x1b[0m;"
+        );
+    }
+}
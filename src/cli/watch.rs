@@ -0,0 +1,89 @@
+use std::{io, path::Path, time::Duration};
+
+use notify::{Event, RecursiveMode, Watcher};
+use surrealdb::Connection;
+use surrealdb_simple_migration::{Error, FilePattern, Migrator};
+use tokio::{signal::ctrl_c, sync::mpsc::unbounded_channel, time::sleep};
+use tracing::{debug, warn};
+
+use super::output;
+
+/// How long to wait for the other changes of a save (e.g. an editor writing a temporary file first).
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Applies the pending migrations, then again whenever a migration file of the directories (or of their
+/// subdirectories, when recursive) is saved, until interrupted with Ctrl-C.
+pub async fn watch<C: Connection>(
+    migrator: &Migrator<'_, C>,
+    paths: &[String],
+    recursive: bool,
+    pattern: &FilePattern
+) -> Result<(), Error> {
+    let (sender, mut receiver) = unbounded_channel::<notify::Result<Event>>();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = sender.send(event);
+    })
+    .map_err(watch_error)?;
+
+    let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    for path in paths {
+        watcher
+            .watch(Path::new(path), mode)
+            .map_err(watch_error)?;
+    }
+
+    output::info(format!("Watching {} for migration files, press Ctrl-C to stop.", paths.join(", ")));
+    apply(migrator).await;
+
+    loop {
+        let event = tokio::select! {
+            _ = ctrl_c() => return Ok(()),
+            event = receiver.recv() => event,
+        };
+
+        match event {
+            Some(Ok(event)) if is_migration_change(&event, pattern) => {
+                debug!(paths = ?event.paths, "Migration files changed");
+            },
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => {
+                warn!(error = %e, "Failed to watch the migration directory");
+                continue;
+            },
+            None => return Ok(()),
+        }
+
+        sleep(DEBOUNCE).await;
+        while receiver.try_recv().is_ok() {}
+
+        apply(migrator).await;
+    }
+}
+
+/// Applies the pending migrations, reporting the failures without stopping the watch.
+async fn apply<C: Connection>(migrator: &Migrator<'_, C>) {
+    match migrator.run().await {
+        Ok(report) if report.applied.is_empty() => output::info("No pending migrations."),
+        Ok(report) => {
+            for migration in &report.applied {
+                output::success(format!("{} (applied in {:?})", migration.filename, migration.duration));
+            }
+        },
+        Err(e) => output::failure(format!("Failed to apply migrations: {}", e)),
+    }
+}
+
+/// Returns whether a filesystem event concerns a file with an extension of the migration files.
+fn is_migration_change(event: &Event, pattern: &FilePattern) -> bool {
+    (event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove())
+        && event
+            .paths
+            .iter()
+            .filter_map(|path| path.file_name()?.to_str())
+            .any(|filename| pattern.has_extension(filename))
+}
+
+fn watch_error(err: notify::Error) -> Error {
+    Error::IO(io::Error::other(format!("Failed to watch the migration directory: {}", err)))
+}
@@ -0,0 +1,107 @@
+use semver::{Version, VersionReq};
+use surrealdb::{Connection, Surreal};
+use tracing::{debug, error};
+
+use crate::{Directives, Error, MigrationFile, RetryPolicy};
+
+/// Fails unless the SurrealDB server matches the version required (`-- ssm:requires-surreal`) by each migration
+/// file, before any of them is applied. The version of the server is only queried when a file requires one.
+pub(crate) async fn check_server_version<'f, C: Connection>(
+    db: &Surreal<C>,
+    retry: &RetryPolicy,
+    entries: impl IntoIterator<Item = &'f MigrationFile>,
+) -> Result<(), Error> {
+    let mut server: Option<Version> = None;
+
+    for entry in entries {
+        let Some(requirement) = Directives::parse(&entry.content).requires_surreal else {
+            continue;
+        };
+
+        let parsed = match VersionReq::parse(&requirement) {
+            Ok(parsed) => parsed,
+            Err(e) => return Err(Error::ParseError {
+                filename: entry.filename.clone(),
+                line: directive_line(&entry.content),
+                message: format!("Invalid SurrealDB version requirement '{}': {}", requirement, e),
+            }),
+        };
+
+        let version = match server {
+            Some(ref version) => version.clone(),
+            None => {
+                let version = retry.run(|_| async { Ok(db.version().await?) }).await?;
+                debug!(%version, "SurrealDB server version");
+
+                // The pre-releases of a version (e.g. "2.1.0-beta.1") satisfy the requirements of the version.
+                let version = Version::new(version.major, version.minor, version.patch);
+                server = Some(version.clone());
+                version
+            },
+        };
+
+        if !parsed.matches(&version) {
+            error!(filename = %entry.filename, %requirement, %version, "The SurrealDB server is not supported by the migration file");
+            return Err(Error::IncompatibleServer {
+                filename: entry.filename.clone(),
+                requirement,
+                version: version.to_string(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the line number of the `-- ssm:requires-surreal` directive of a file.
+fn directive_line(content: &str) -> usize {
+    content
+        .lines()
+        .position(|line| line.contains("ssm:requires-surreal"))
+        .map_or(1, |index| index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Error, Migrator, VecSource};
+
+    #[tokio::test]
+    async fn it_refuses_the_files_requiring_another_surrealdb_version() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let source = VecSource::new([
+            ("001_users.surql", "-- ssm:requires-surreal >=2.0\nDEFINE TABLE users;"),
+            ("002_posts.surql", "-- ssm:requires-surreal >=99\nDEFINE TABLE posts;"),
+        ]);
+        match Migrator::new(&db).source(&source).run().await {
+            Err(Error::IncompatibleServer { filename, requirement, .. }) => {
+                assert_eq!(filename, "002_posts.surql");
+                assert_eq!(requirement, ">=99");
+            },
+            result => panic!("Unexpected result: {:?}", result.map(|report| report.applied.len())),
+        }
+
+        // Nothing is applied.
+        let report = Migrator::new(&db)
+            .source(VecSource::new([("001_users.surql", "-- ssm:requires-surreal >=2.0\nDEFINE TABLE users;")]))
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(report.applied.len(), 1);
+
+        assert!(matches!(
+            Migrator::new(&db)
+                .source(VecSource::new([
+                    ("001_users.surql", "-- ssm:requires-surreal >=2.0\nDEFINE TABLE users;"),
+                    ("002_posts.surql", "-- Posts.\n-- ssm:requires-surreal soon\nDEFINE TABLE posts;"),
+                ]))
+                .dry_run(true)
+                .run()
+                .await,
+            Err(Error::ParseError { line: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+use serde::Deserialize;
+use surrealdb::{Connection, Surreal};
+use tracing::{debug, info};
+
+use crate::{
+    migrator::{checked_statements, query_error, terminate_statements},
+    parse_statements,
+    progress::{define_progress_table, progress_table},
+    Error, MigrationFile,
+};
+
+/// The number of records of each batch of a data migration without a `-- ssm:batch` directive.
+pub const DEFAULT_DATA_BATCH_SIZE: usize = 1000;
+
+/// How far a data migration went, in the `<table>_progress` table until it is recorded as applied.
+#[derive(Deserialize, Debug)]
+struct DataProgress {
+    checksum: String,
+    batches: u64,
+    rows: u64,
+}
+
+/// Executes the batches of a data migration not completed by a previous run with the same checksum, until its last
+/// statement returns no records, and returns the number of records of the batches. Each batch is bound to
+/// `$batch_size` and `$offset` (`$batch_size` times the batches done), and committed with the progress of the
+/// migration, which is left for the migrator to remove once it records the file. `on_batch` is called with the
+/// batches and records done after each batch. A batch returning the same records as the previous one fails with
+/// `Error::StatementFailed`, as the migration would never complete (e.g. its records still match its filter).
+pub(crate) async fn run_data_migration<C: Connection>(
+    db: &Surreal<C>,
+    table: &str,
+    entry: &MigrationFile,
+    checksum: &str,
+    batch_size: usize,
+    on_batch: impl Fn(u64, u64),
+) -> Result<u64, Error> {
+    let statements = parse_statements(&entry.content);
+    let Some(last) = statements.last() else {
+        return Ok(0);
+    };
+
+    define_progress_table(db, table).await?;
+    let progress = progress_table(table);
+
+    let (mut batches, mut rows) = match read_progress(db, &progress, &entry.filename).await? {
+        Some(done) if done.checksum == checksum => {
+            info!(batches = done.batches, rows = done.rows, "Resuming the data migration");
+            (done.batches, done.rows)
+        },
+        _ => (0, 0),
+    };
+
+    let sql = format!(
+        "BEGIN TRANSACTION;\n{}\nUPSERT type::thing($progress_table, $filename) SET checksum=$checksum, batches=$batches;\nCOMMIT TRANSACTION;",
+        terminate_statements(&entry.content)
+    );
+
+    let mut previous: Option<Vec<serde_json::Value>> = None;
+    loop {
+        let response = db
+            .query(sql.as_str())
+            .bind(("batch_size", batch_size))
+            .bind(("offset", batches * batch_size as u64))
+            .bind(("progress_table", progress.clone()))
+            .bind(("filename", entry.filename.clone()))
+            .bind(("checksum", checksum.to_string()))
+            .bind(("batches", batches + 1))
+            .await
+            .map_err(|err| query_error(entry, err, 1))?;
+
+        let records = checked_statements(entry, response)?.take::<Vec<serde_json::Value>>(statements.len() - 1)?;
+        if records.is_empty() {
+            break;
+        }
+        if previous.as_ref() == Some(&records) {
+            return Err(
+                Error::StatementFailed {
+                    filename: entry.filename.clone(),
+                    statement: statements.len(),
+                    line: last.line,
+                    sql: last.sql.clone(),
+                    message: format!("The batch {} returned the same records as the previous one, so the data migration would never complete.", batches + 1),
+                }
+            );
+        }
+        let found = records.len() as u64;
+        previous = Some(records);
+
+        batches += 1;
+        rows += found;
+
+        // The records of the batch are only known once it is committed.
+        let _ = db
+            .query("UPDATE type::thing($progress_table, $filename) SET rows=$rows;")
+            .bind(("progress_table", progress.clone()))
+            .bind(("filename", entry.filename.clone()))
+            .bind(("rows", rows))
+            .await?
+            .check()?;
+
+        debug!(batches, rows, "Batch of the data migration committed");
+        on_batch(batches, rows);
+    }
+
+    info!(batches, rows, "Data migration completed");
+
+    Ok(rows)
+}
+
+async fn read_progress<C: Connection>(db: &Surreal<C>, progress: &str, filename: &str) -> Result<Option<DataProgress>, Error> {
+    let done = db
+        .query("SELECT checksum, batches, rows ?? 0 AS rows FROM ONLY type::thing($progress_table, $filename);")
+        .bind(("progress_table", progress.to_string()))
+        .bind(("filename", filename.to_string()))
+        .await?
+        .check()?
+        .take::<Option<DataProgress>>(0)?;
+
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Error, Migrator, VecSource};
+
+    #[tokio::test]
+    async fn it_resumes_the_data_migrations_from_the_last_batch() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        db.query("FOR $n IN 1..=5 { CREATE user SET n = $n; };").await.unwrap().check().unwrap();
+
+        let source = VecSource::new([(
+            "001_activate_users.surql",
+            "-- ssm:kind data\n-- ssm:batch 2\n\
+            LET $users = SELECT VALUE id FROM user WHERE active IS NONE LIMIT $batch_size;\n\
+            IF $offset >= ${FAIL_AT} { THROW 'interrupted' };\n\
+            UPDATE $users SET active = true;\n",
+        )]);
+
+        // The third batch fails, once the first two are committed.
+        let result = Migrator::new(&db).source(&source).var("FAIL_AT", "4").run().await;
+        assert!(result.is_err());
+
+        let active: Vec<bool> = db.query("SELECT VALUE active FROM user WHERE active = true;").await.unwrap().take(0).unwrap();
+        assert_eq!(active.len(), 4);
+
+        // The checksum is the one of the file, whatever its variables: the migration resumes from the third batch.
+        let report = Migrator::new(&db).source(&source).var("FAIL_AT", "100").run().await.unwrap();
+        assert_eq!(report.applied[0].rows, Some(5));
+
+        let progress: Vec<serde_json::Value> = db.query("SELECT * FROM migrations_progress;").await.unwrap().take(0).unwrap();
+        assert!(progress.is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_fails_the_data_migrations_returning_the_same_records_again() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        db.query("FOR $n IN 1..=5 { CREATE user SET n = $n; };").await.unwrap().check().unwrap();
+
+        // The records keep matching the filter, as the field set is not the one filtered on.
+        let source = VecSource::new([(
+            "001_activate_users.surql",
+            "-- ssm:kind data\n-- ssm:batch 2\n\
+            UPDATE (SELECT VALUE id FROM user WHERE active IS NONE LIMIT $batch_size) SET enabled = true;\n",
+        )]);
+
+        let result = Migrator::new(&db).source(&source).run().await;
+        assert!(matches!(result, Err(Error::StatementFailed { statement: 1, .. })), "{:?}", result);
+
+        let applied: Vec<String> = db.query("SELECT VALUE filename FROM migrations;").await.unwrap().take(0).unwrap();
+        assert!(applied.is_empty());
+    }
+}
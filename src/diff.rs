@@ -0,0 +1,420 @@
+// Only the `diff` of the `kv-mem` feature compares the schemas, `clean` and the rehearsal also read them.
+#![cfg_attr(not(feature = "kv-mem"), allow(dead_code))]
+
+use std::{collections::BTreeMap, fmt};
+
+use regex::Regex;
+use serde::Deserialize;
+use surrealdb::{Connection, Surreal};
+
+use crate::{reset::Resource, Error};
+
+/// Returns the name as is when it is an identifier, or between backticks (e.g. `` `user-events` ``).
+fn escape_ident(name: &str) -> String {
+    let mut chars = name.chars();
+    let is_ident = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if is_ident {
+        name.to_string()
+    } else {
+        format!("`{}`", name.replace('\\', "\\\\").replace('`', "\\`"))
+    }
+}
+
+/// Escapes each part of the path of a field (e.g. `` `first-name`.en `` or `tags[*]`).
+fn field_path(name: &str) -> String {
+    name
+        .split('.')
+        .map(|part| {
+            let (ident, index) = part.find('[').map_or((part, ""), |at| part.split_at(at));
+            format!("{}{}", escape_ident(ident), index)
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// The kind of a resource of a database schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SchemaKind {
+    Table,
+    Field,
+    Index,
+    Event,
+    Function,
+    Param,
+    Analyzer,
+}
+
+impl fmt::Display for SchemaKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SchemaKind::Table => write!(f, "table"),
+            SchemaKind::Field => write!(f, "field"),
+            SchemaKind::Index => write!(f, "index"),
+            SchemaKind::Event => write!(f, "event"),
+            SchemaKind::Function => write!(f, "function"),
+            SchemaKind::Param => write!(f, "param"),
+            SchemaKind::Analyzer => write!(f, "analyzer"),
+        }
+    }
+}
+
+/// A resource of a database schema, as returned by `INFO FOR DB` and `INFO FOR TABLE`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaDefinition {
+    pub kind: SchemaKind,
+    pub name: String,
+    /// The table of a field, an index or an event.
+    pub table: Option<String>,
+    /// The `DEFINE` statement of the resource.
+    pub statement: String,
+}
+
+impl SchemaDefinition {
+    /// Returns whether both definitions are about the same resource, whatever their statements.
+    fn same_resource(&self, other: &SchemaDefinition) -> bool {
+        self.kind == other.kind && self.name == other.name && self.table == other.table
+    }
+
+    /// The statement defining the resource, replacing its current definition if any.
+    fn overwrite_statement(&self) -> String {
+        let regex = Regex::new(r"(?i)^\s*DEFINE\s+(TABLE|FIELD|INDEX|EVENT|FUNCTION|PARAM|ANALYZER)\s+(?:IF\s+NOT\s+EXISTS\s+|OVERWRITE\s+)?")
+            .expect("Failed to build the regexp");
+
+        format!("{};", regex.replace(self.statement.trim().trim_end_matches(';'), "DEFINE $1 OVERWRITE "))
+    }
+
+    /// The statement removing the resource, its names escaped as `INFO FOR DB` returns them unescaped.
+    pub(crate) fn remove_statement(&self) -> String {
+        // The names of the functions and params can only be identifiers.
+        let name = match self.kind {
+            SchemaKind::Field => field_path(&self.name),
+            SchemaKind::Function | SchemaKind::Param => self.name.clone(),
+            _ => escape_ident(&self.name),
+        };
+        let table = escape_ident(self.table.as_deref().unwrap_or_default());
+
+        let resource = match self.kind {
+            SchemaKind::Table => Resource::Table(name),
+            SchemaKind::Field => Resource::Field { name, table },
+            SchemaKind::Index => Resource::Index { name, table },
+            SchemaKind::Event => Resource::Event { name, table },
+            // `INFO FOR DB` names the functions and params without their prefix.
+            SchemaKind::Function if !name.starts_with("fn::") => Resource::Function(format!("fn::{}", name)),
+            SchemaKind::Function => Resource::Function(name),
+            SchemaKind::Param if !name.starts_with('$') => Resource::Param(format!("${}", name)),
+            SchemaKind::Param => Resource::Param(name),
+            SchemaKind::Analyzer => Resource::Analyzer(name),
+        };
+
+        resource.remove_statement()
+    }
+
+    /// The order in which the resources are defined: the ones used by the others first.
+    fn rank(&self) -> u8 {
+        match self.kind {
+            SchemaKind::Analyzer => 0,
+            SchemaKind::Param => 1,
+            SchemaKind::Function => 2,
+            SchemaKind::Table => 3,
+            SchemaKind::Field => 4,
+            SchemaKind::Index => 5,
+            SchemaKind::Event => 6,
+        }
+    }
+}
+
+impl fmt::Display for SchemaDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.table {
+            Some(ref table) => write!(f, "{} {} on {}", self.kind, self.name, table),
+            None => write!(f, "{} {}", self.kind, self.name),
+        }
+    }
+}
+
+/// A resource defined differently by the migrations and in the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedDefinition {
+    /// The definition resulting from the migrations.
+    pub expected: SchemaDefinition,
+    /// The definition found in the database.
+    pub actual: SchemaDefinition,
+}
+
+/// The differences between the schema resulting from the migrations and the schema of the database.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaDiff {
+    /// The resources defined by the migrations but missing from the database.
+    pub missing: Vec<SchemaDefinition>,
+    /// The resources of the database that are not defined by the migrations (e.g. hot-fixed by hand).
+    pub unexpected: Vec<SchemaDefinition>,
+    /// The resources defined differently by the migrations and in the database.
+    pub changed: Vec<ChangedDefinition>,
+}
+
+impl SchemaDiff {
+    /// Returns whether the schema of the database matches the migrations.
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.changed.is_empty()
+    }
+
+    /// Returns the differences seen from the other side: the schema of the database becomes the expected one.
+    pub fn reversed(&self) -> SchemaDiff {
+        SchemaDiff {
+            missing: self.unexpected.clone(),
+            unexpected: self.missing.clone(),
+            changed: self
+                .changed
+                .iter()
+                .map(|changed| ChangedDefinition { expected: changed.actual.clone(), actual: changed.expected.clone() })
+                .collect(),
+        }
+    }
+
+    /// Returns the best-effort SurrealQL statements bringing the database in line with the migrations: the
+    /// unexpected resources are removed, the missing and changed ones are defined with `OVERWRITE`.
+    ///
+    /// Review them before applying them: e.g. a renamed field is removed and defined again, losing its data.
+    pub fn to_statements(&self) -> String {
+        let mut statements: Vec<String> = vec![];
+
+        // The fields, indexes and events of a removed table are removed with it.
+        for definition in &self.unexpected {
+            let table_removed = definition.table.as_ref().is_some_and(|table| {
+                self.unexpected
+                    .iter()
+                    .any(|other| other.kind == SchemaKind::Table && other.name == *table)
+            });
+
+            if !table_removed {
+                statements.push(definition.remove_statement());
+            }
+        }
+
+        let mut definitions: Vec<&SchemaDefinition> = self
+            .missing
+            .iter()
+            .chain(self.changed.iter().map(|changed| &changed.expected))
+            .collect();
+        definitions.sort_by_key(|definition| definition.rank());
+
+        for definition in definitions {
+            statements.push(definition.overwrite_statement());
+        }
+
+        statements.join("\n")
+    }
+}
+
+/// The output of `INFO FOR DB`, restricted to the compared resources.
+#[derive(Deserialize, Debug, Default)]
+struct DbInfo {
+    #[serde(default)]
+    tables: BTreeMap<String, String>,
+    #[serde(default)]
+    functions: BTreeMap<String, String>,
+    #[serde(default)]
+    params: BTreeMap<String, String>,
+    #[serde(default)]
+    analyzers: BTreeMap<String, String>,
+}
+
+/// The output of `INFO FOR TABLE`, restricted to the compared resources.
+#[derive(Deserialize, Debug, Default)]
+struct TableInfo {
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+    #[serde(default)]
+    indexes: BTreeMap<String, String>,
+    #[serde(default)]
+    events: BTreeMap<String, String>,
+}
+
+/// Reads the schema of the database in use, ignoring the given tables (e.g. the migrations table).
+pub(crate) async fn read_schema<C: Connection>(db: &Surreal<C>, ignored_tables: &[String]) -> Result<Vec<SchemaDefinition>, Error> {
+    let info = db
+        .query("INFO FOR DB;")
+        .await?
+        .check()?
+        .take::<Option<DbInfo>>(0)?
+        .unwrap_or_default();
+
+    let mut definitions: Vec<SchemaDefinition> = vec![];
+
+    for (kind, resources) in [
+        (SchemaKind::Function, &info.functions),
+        (SchemaKind::Param, &info.params),
+        (SchemaKind::Analyzer, &info.analyzers),
+    ] {
+        for (name, statement) in resources {
+            definitions.push(SchemaDefinition { kind, name: name.clone(), table: None, statement: statement.clone() });
+        }
+    }
+
+    for (table, statement) in &info.tables {
+        if ignored_tables.contains(table) {
+            continue;
+        }
+
+        definitions.push(SchemaDefinition {
+            kind: SchemaKind::Table,
+            name: table.clone(),
+            table: None,
+            statement: statement.clone(),
+        });
+
+        let table_info = db
+            .query(format!("INFO FOR TABLE `{}`;", table))
+            .await?
+            .check()?
+            .take::<Option<TableInfo>>(0)?
+            .unwrap_or_default();
+
+        for (kind, resources) in [
+            (SchemaKind::Field, &table_info.fields),
+            (SchemaKind::Index, &table_info.indexes),
+            (SchemaKind::Event, &table_info.events),
+        ] {
+            for (name, statement) in resources {
+                definitions.push(SchemaDefinition {
+                    kind,
+                    name: name.clone(),
+                    table: Some(table.clone()),
+                    statement: statement.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(definitions)
+}
+
+/// Compares the schema resulting from the migrations with the schema of the database.
+pub(crate) fn compare_schemas(expected: &[SchemaDefinition], actual: &[SchemaDefinition]) -> SchemaDiff {
+    let mut diff = SchemaDiff::default();
+
+    for definition in expected {
+        match actual.iter().find(|other| definition.same_resource(other)) {
+            Some(other) if normalize(&other.statement) != normalize(&definition.statement) => {
+                diff.changed.push(ChangedDefinition { expected: definition.clone(), actual: other.clone() });
+            },
+            Some(_) => (),
+            None => diff.missing.push(definition.clone()),
+        }
+    }
+
+    for definition in actual {
+        if !expected.iter().any(|other| definition.same_resource(other)) {
+            diff.unexpected.push(definition.clone());
+        }
+    }
+
+    diff
+}
+
+/// Ignores the whitespace differences between two statements.
+fn normalize(statement: &str) -> String {
+    statement.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_schemas, ChangedDefinition, SchemaDefinition, SchemaDiff, SchemaKind};
+
+    fn field(name: &str, statement: &str) -> SchemaDefinition {
+        SchemaDefinition {
+            kind: SchemaKind::Field,
+            name: name.to_string(),
+            table: Some("users".to_string()),
+            statement: statement.to_string(),
+        }
+    }
+
+    #[test]
+    fn it_compares_schemas() {
+        let expected = vec![
+            field("email", "DEFINE FIELD email ON users TYPE string"),
+            field("name", "DEFINE FIELD name ON users TYPE string"),
+            field("age", "DEFINE FIELD age ON users TYPE int"),
+        ];
+        let actual = vec![
+            field("email", "DEFINE FIELD email ON users  TYPE string"),
+            field("age", "DEFINE FIELD age ON users TYPE number"),
+            field("nickname", "DEFINE FIELD nickname ON users TYPE string"),
+        ];
+
+        let diff = compare_schemas(&expected, &actual);
+
+        assert_eq!(diff.missing, vec![expected[1].clone()]);
+        assert_eq!(diff.unexpected, vec![actual[2].clone()]);
+        assert_eq!(diff.changed, vec![ChangedDefinition { expected: expected[2].clone(), actual: actual[1].clone() }]);
+    }
+
+    #[test]
+    fn it_generates_the_statements_fixing_a_diff() {
+        let table = SchemaDefinition {
+            kind: SchemaKind::Table,
+            name: "posts".to_string(),
+            table: None,
+            statement: "DEFINE TABLE posts TYPE NORMAL SCHEMAFULL PERMISSIONS NONE".to_string(),
+        };
+        let function = SchemaDefinition {
+            kind: SchemaKind::Function,
+            name: "greet".to_string(),
+            table: None,
+            statement: "DEFINE FUNCTION fn::greet() { RETURN 'Hello'; } PERMISSIONS FULL".to_string(),
+        };
+
+        let diff = SchemaDiff {
+            missing: vec![field("email", "DEFINE FIELD email ON users TYPE string PERMISSIONS FULL"), table],
+            unexpected: vec![function],
+            changed: vec![ChangedDefinition {
+                expected: field("age", "DEFINE FIELD age ON users TYPE int PERMISSIONS FULL"),
+                actual: field("age", "DEFINE FIELD age ON users TYPE number PERMISSIONS FULL"),
+            }],
+        };
+
+        assert_eq!(
+            diff.to_statements(),
+            "REMOVE FUNCTION IF EXISTS fn::greet;\n\
+             DEFINE TABLE OVERWRITE posts TYPE NORMAL SCHEMAFULL PERMISSIONS NONE;\n\
+             DEFINE FIELD OVERWRITE email ON users TYPE string PERMISSIONS FULL;\n\
+             DEFINE FIELD OVERWRITE age ON users TYPE int PERMISSIONS FULL;"
+        );
+        assert_eq!(
+            diff.reversed().to_statements(),
+            "REMOVE FIELD IF EXISTS email ON TABLE users;\n\
+             REMOVE TABLE IF EXISTS posts;\n\
+             DEFINE FUNCTION OVERWRITE fn::greet() { RETURN 'Hello'; } PERMISSIONS FULL;\n\
+             DEFINE FIELD OVERWRITE age ON users TYPE number PERMISSIONS FULL;"
+        );
+    }
+
+    #[test]
+    fn it_escapes_the_names_of_the_removed_resources() {
+        let table = SchemaDefinition {
+            kind: SchemaKind::Table,
+            name: "user-events".to_string(),
+            table: None,
+            statement: "DEFINE TABLE `user-events` TYPE ANY SCHEMALESS PERMISSIONS NONE".to_string(),
+        };
+        let field = SchemaDefinition {
+            kind: SchemaKind::Field,
+            name: "first name.en".to_string(),
+            table: Some("user-events".to_string()),
+            statement: "DEFINE FIELD `first name`.en ON `user-events` TYPE string PERMISSIONS FULL".to_string(),
+        };
+        let tags = SchemaDefinition {
+            kind: SchemaKind::Field,
+            name: "tags[*]".to_string(),
+            table: Some("posts".to_string()),
+            statement: "DEFINE FIELD tags[*] ON posts TYPE string PERMISSIONS FULL".to_string(),
+        };
+
+        assert_eq!(table.remove_statement(), "REMOVE TABLE IF EXISTS `user-events`;");
+        assert_eq!(field.remove_statement(), "REMOVE FIELD IF EXISTS `first name`.en ON TABLE `user-events`;");
+        assert_eq!(tags.remove_statement(), "REMOVE FIELD IF EXISTS tags[*] ON TABLE posts;");
+    }
+}
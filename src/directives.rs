@@ -0,0 +1,189 @@
+/// Settings declared in the header comments of a migration file (e.g. `-- ssm:no-transaction`).
+///
+/// The header is made of the comment and blank lines at the top of the file; directives
+/// after the first statement are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Directives {
+    /// Do not wrap the file in a transaction.
+    pub no_transaction: bool,
+    /// The environments the file is applied in (`-- ssm:env prod,staging`), every one when empty.
+    pub envs: Vec<String>,
+    /// The migration files applied before this one, by name with or without their extension
+    /// (`-- ssm:requires 0042_create_accounts`).
+    pub requires: Vec<String>,
+    /// The versions of the SurrealDB server the file can be applied to, as a semver requirement
+    /// (`-- ssm:requires-surreal >=2.0`), e.g. when it uses syntax older servers do not support.
+    pub requires_surreal: Option<String>,
+    /// A long-running data migration (`-- ssm:kind data`), executed in batches until its last statement returns no
+    /// records, each batch committed with its progress so a failed run resumes from the last completed one.
+    pub data: bool,
+    /// The number of records of each batch of a data migration (`-- ssm:batch 1000`), bound to `$batch_size`.
+    pub batch_size: Option<usize>,
+    /// The tags of the file (`-- ssm:tags auth,critical`), to apply or inspect a subset of the migrations.
+    pub tags: Vec<String>,
+}
+
+impl Directives {
+    pub fn parse(content: &str) -> Self {
+        let mut directives = Directives::default();
+
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some(comment) = line.strip_prefix("--") else {
+                break;
+            };
+
+            let Some(directive) = comment.trim().strip_prefix("ssm:") else {
+                continue;
+            };
+
+            let (name, args) = directive
+                .split_once(char::is_whitespace)
+                .unwrap_or((directive, ""));
+
+            match name {
+                "no-transaction" => directives.no_transaction = true,
+                "env" => directives.envs.extend(
+                    args.split(',')
+                        .map(str::trim)
+                        .filter(|env| !env.is_empty())
+                        .map(str::to_string)
+                ),
+                "tags" => directives.tags.extend(
+                    args.split(',')
+                        .map(str::trim)
+                        .filter(|tag| !tag.is_empty())
+                        .map(str::to_string)
+                ),
+                "requires" => directives.requires.extend(
+                    args.split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                ),
+                "requires-surreal" if !args.trim().is_empty() => directives.requires_surreal = Some(args.trim().to_string()),
+                "kind" => directives.data = args.trim().eq_ignore_ascii_case("data"),
+                "batch" => directives.batch_size = args.trim().parse().ok().filter(|size| *size > 0),
+                _ => (),
+            }
+        }
+
+        directives
+    }
+
+    /// Returns whether the file has one of the tags, every file matching when none is given.
+    pub fn has_any_tag(&self, tags: &[String]) -> bool {
+        tags.is_empty() || self.tags.iter().any(|tag| tags.contains(tag))
+    }
+
+    /// Returns whether the file is applied in the environment, if any.
+    pub fn applies_to(&self, env: Option<&str>) -> bool {
+        self.envs.is_empty() || env.is_some_and(|env| self.envs.iter().any(|file_env| file_env == env))
+    }
+}
+
+/// Splits a migration file into its up and down sections, marked by `-- +up` and `-- +down` lines (e.g. to keep
+/// the rollback of a migration in the same file, instead of a `.down.surql` file).
+///
+/// The up section runs until the `-- +down` line (the whole file when there is none), keeping its header and
+/// the line numbers of its statements.
+pub(crate) fn split_sections(content: &str) -> (&str, Option<&str>) {
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        if is_down_marker(line) {
+            return (&content[..offset], Some(&content[offset + line.len()..]));
+        }
+
+        offset += line.len();
+    }
+
+    (content, None)
+}
+
+/// Returns whether a line is the `-- +down` marker starting the down section of a migration file.
+pub(crate) fn is_down_marker(line: &str) -> bool {
+    line.trim()
+        .strip_prefix("--")
+        .is_some_and(|marker| marker.trim().eq_ignore_ascii_case("+down"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_sections, Directives};
+
+    #[test]
+    fn it_parses_header_directives() {
+        let directives = Directives::parse("
+            -- Create the users table.
+            -- ssm:no-transaction
+
+            DEFINE TABLE users SCHEMAFULL;
+        ");
+
+        assert!(directives.no_transaction);
+    }
+
+    #[test]
+    fn it_parses_the_environments_of_the_file() {
+        let directives = Directives::parse("-- ssm:env prod, staging\nDEFINE TABLE audit;");
+
+        assert_eq!(directives.envs, ["prod", "staging"]);
+        assert!(directives.applies_to(Some("staging")));
+        assert!(!directives.applies_to(Some("dev")));
+        assert!(!directives.applies_to(None));
+        assert!(Directives::default().applies_to(None));
+    }
+
+    #[test]
+    fn it_parses_the_tags_of_the_file() {
+        let directives = Directives::parse("-- ssm:tags auth, critical\nDEFINE TABLE sessions;");
+
+        assert_eq!(directives.tags, ["auth", "critical"]);
+        assert!(directives.has_any_tag(&["billing".to_string(), "auth".to_string()]));
+        assert!(!directives.has_any_tag(&["billing".to_string()]));
+        assert!(Directives::default().has_any_tag(&[]));
+    }
+
+    #[test]
+    fn it_parses_the_required_surrealdb_version() {
+        let directives = Directives::parse("-- ssm:requires-surreal >=2.0, <3\nDEFINE TABLE users;");
+
+        assert_eq!(directives.requires_surreal.as_deref(), Some(">=2.0, <3"));
+        assert_eq!(Directives::parse("-- ssm:requires-surreal\n").requires_surreal, None);
+    }
+
+    #[test]
+    fn it_parses_the_data_migrations() {
+        let directives = Directives::parse("-- ssm:kind data\n-- ssm:batch 500\nUPDATE users SET active = true;");
+
+        assert!(directives.data);
+        assert_eq!(directives.batch_size, Some(500));
+        assert!(!Directives::parse("-- ssm:kind schema\n-- ssm:batch 0\n").data);
+        assert_eq!(Directives::parse("-- ssm:batch 0\n").batch_size, None);
+    }
+
+    #[test]
+    fn it_ignores_directives_after_the_header() {
+        let directives = Directives::parse("
+            DEFINE TABLE users SCHEMAFULL;
+            -- ssm:no-transaction
+        ");
+
+        assert_eq!(directives, Directives::default());
+    }
+
+    #[test]
+    fn it_splits_the_up_and_down_sections() {
+        let content = "-- ssm:no-transaction\n-- +up\nDEFINE TABLE users;\n\n-- +down\nREMOVE TABLE users;\n";
+
+        assert_eq!(
+            split_sections(content),
+            ("-- ssm:no-transaction\n-- +up\nDEFINE TABLE users;\n\n", Some("REMOVE TABLE users;\n"))
+        );
+        assert!(Directives::parse(split_sections(content).0).no_transaction);
+        assert_eq!(split_sections("DEFINE TABLE users;"), ("DEFINE TABLE users;", None));
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+/// What happens during a migration run, reported to the `Migrator::on_event` callbacks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationEvent {
+    /// The migration files to apply during the run (or that would be applied, in dry run mode), in order.
+    Discovered { filenames: Vec<String> },
+    /// A migration file already applied by a previous run, or for other environments (`-- ssm:env`).
+    Skipped { filename: String },
+    /// A migration file is about to be applied.
+    Started { filename: String },
+    /// A batch of a data migration (`-- ssm:kind data`) has been committed, with the batches and the records done
+    /// so far, including the ones of a previous run it resumed.
+    Progress { filename: String, batches: u64, rows: u64 },
+    /// A migration file has been applied and recorded.
+    Applied { filename: String, duration: Duration },
+    /// A migration file failed to be applied, aborting the run.
+    Failed { filename: String, error: String },
+}
+
+/// A callback registered with `Migrator::on_event`.
+pub(crate) type EventHandler<'a> = Box<dyn Fn(&MigrationEvent) + Send + Sync + 'a>;
+
+/// Reports an event to the callbacks, in order of registration, and records its metrics with the `metrics` feature.
+pub(crate) fn emit(handlers: &[EventHandler<'_>], event: MigrationEvent) {
+    #[cfg(feature = "metrics")]
+    crate::metrics::record(&event);
+
+    for handler in handlers {
+        handler(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Migrator, VecSource};
+
+    use super::MigrationEvent;
+
+    #[tokio::test]
+    async fn it_reports_the_events_of_a_run() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let events: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let run = |files: Vec<(&str, &str)>| {
+            Migrator::new(&db)
+                .source(VecSource::new(files))
+                .on_event(|event| {
+                    let event = match event {
+                        MigrationEvent::Discovered { filenames } => format!("discovered {}", filenames.join(",")),
+                        MigrationEvent::Skipped { filename } => format!("skipped {}", filename),
+                        MigrationEvent::Started { filename } => format!("started {}", filename),
+                        MigrationEvent::Progress { filename, .. } => format!("progress {}", filename),
+                        MigrationEvent::Applied { filename, .. } => format!("applied {}", filename),
+                        MigrationEvent::Failed { filename, .. } => format!("failed {}", filename),
+                    };
+                    events.lock().unwrap().push(event);
+                })
+        };
+
+        run(vec![("001_users.surql", "DEFINE TABLE users;")]).run().await.unwrap();
+        assert!(
+            run(vec![("001_users.surql", "DEFINE TABLE users;"), ("002_posts.surql", "DEFINE TABLE posts; THROW 'broken';")])
+                .run()
+                .await
+                .is_err()
+        );
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "discovered 001_users.surql",
+                "started 001_users.surql",
+                "applied 001_users.surql",
+                "discovered 002_posts.surql",
+                "skipped 001_users.surql",
+                "started 002_posts.surql",
+                "failed 002_posts.surql",
+            ]
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use std::{future::Future, pin::Pin};
+
+use surrealdb::{Connection, Surreal};
+
+use crate::Error;
+
+/// The future returned by a hook of the `Migrator`, e.g. `Box::pin(async move { ...; Ok(()) })`.
+pub type HookFuture<'h> = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'h>>;
+
+/// A hook called with the name of a migration file (`before_each`, `after_each`).
+pub(crate) type EachHook<'a, C> = Box<dyn for<'h> Fn(&'h Surreal<C>, &'h str) -> HookFuture<'h> + Send + Sync + 'a>;
+
+/// A hook called with the names of the migration files of a run (`before_all`, `after_all`).
+pub(crate) type AllHook<'a, C> = Box<dyn for<'h> Fn(&'h Surreal<C>, &'h [String]) -> HookFuture<'h> + Send + Sync + 'a>;
+
+/// The hooks registered on a `Migrator`, called in order of registration.
+pub(crate) struct Hooks<'a, C: Connection> {
+    pub before_all: Vec<AllHook<'a, C>>,
+    pub before_each: Vec<EachHook<'a, C>>,
+    pub after_each: Vec<EachHook<'a, C>>,
+    pub after_all: Vec<AllHook<'a, C>>,
+}
+
+impl<'a, C: Connection> Hooks<'a, C> {
+    pub fn new() -> Self {
+        Hooks {
+            before_all: vec![],
+            before_each: vec![],
+            after_each: vec![],
+            after_all: vec![],
+        }
+    }
+}
+
+/// Calls hooks with the name of a migration file, stopping at the first failure.
+pub(crate) async fn run_each<C: Connection>(hooks: &[EachHook<'_, C>], db: &Surreal<C>, filename: &str) -> Result<(), Error> {
+    for hook in hooks {
+        hook(db, filename).await?;
+    }
+
+    Ok(())
+}
+
+/// Calls hooks with the names of the migration files of a run, stopping at the first failure.
+pub(crate) async fn run_all<C: Connection>(hooks: &[AllHook<'_, C>], db: &Surreal<C>, filenames: &[String]) -> Result<(), Error> {
+    for hook in hooks {
+        hook(db, filenames).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use regex::{Captures, Regex};
+
+/// Rewrites the `DEFINE TABLE`, `DEFINE FIELD` and `DEFINE INDEX` statements of a migration file without `IF NOT
+/// EXISTS` or `OVERWRITE` into `DEFINE ... IF NOT EXISTS`, so a file applied to a database where some of them
+/// already exist (e.g. a previous attempt applied partially) does not fail. Only the statements starting a line or
+/// following a `;` are rewritten, and the lines are kept as they are, for the errors to point at the file.
+pub(crate) fn make_idempotent(content: &str) -> String {
+    let define = Regex::new(r"(?im)(^|;)(\s*DEFINE\s+(?:TABLE|FIELD|INDEX))(\s+)([^\s;]+)").expect("Failed to build the regexp");
+
+    define
+        .replace_all(content, |captures: &Captures| {
+            let next = &captures[4];
+            if next.eq_ignore_ascii_case("IF") || next.eq_ignore_ascii_case("OVERWRITE") {
+                return captures[0].to_string();
+            }
+
+            format!("{}{} IF NOT EXISTS{}{}", &captures[1], &captures[2], &captures[3], next)
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::make_idempotent;
+
+    #[test]
+    fn it_makes_the_definitions_idempotent() {
+        assert_eq!(
+            make_idempotent("DEFINE TABLE users SCHEMAFULL;\ndefine field\n  email ON users TYPE string; DEFINE INDEX email ON users FIELDS email UNIQUE;"),
+            "DEFINE TABLE IF NOT EXISTS users SCHEMAFULL;\ndefine field IF NOT EXISTS\n  email ON users TYPE string; DEFINE INDEX IF NOT EXISTS email ON users FIELDS email UNIQUE;"
+        );
+        assert_eq!(
+            make_idempotent("DEFINE TABLE IF NOT EXISTS users;\nDEFINE FIELD OVERWRITE email ON users;\nDEFINE EVENT log ON users THEN {};"),
+            "DEFINE TABLE IF NOT EXISTS users;\nDEFINE FIELD OVERWRITE email ON users;\nDEFINE EVENT log ON users THEN {};"
+        );
+        assert_eq!(make_idempotent("-- DEFINE TABLE users;\nCREATE note SET text = 'DEFINE TABLE x';"), "-- DEFINE TABLE users;\nCREATE note SET text = 'DEFINE TABLE x';");
+    }
+}
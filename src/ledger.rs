@@ -0,0 +1,165 @@
+use std::io;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, Surreal};
+use tracing::info;
+
+use crate::Error;
+
+/// The version of the format of the ledgers written by `MigrationLedger::to_json`.
+pub const LEDGER_FORMAT_VERSION: u32 = 1;
+
+/// A record of the migrations table of a `MigrationLedger`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct LedgerEntry {
+    pub filename: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum_algorithm: Option<String>,
+    pub applied_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_time_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applied_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_version: Option<String>,
+    #[serde(default)]
+    pub repeatable: bool,
+    #[serde(default)]
+    pub out_of_order: bool,
+    #[serde(default)]
+    pub baseline: bool,
+    #[serde(default)]
+    pub skipped: bool,
+}
+
+/// The records of the migrations table of a database, exported by `Migrator::export_ledger` and restored by
+/// `Migrator::import_ledger` on another instance, e.g. along with a snapshot of the database promoted from staging
+/// to production, so the migrations already in the snapshot are not applied again.
+///
+/// ```ignore
+/// fs::write("ledger.json", Migrator::new(&staging).export_ledger().await?.to_json())?;
+///
+/// let ledger = MigrationLedger::from_json(&fs::read_to_string("ledger.json")?)?;
+/// Migrator::new(&production).import_ledger(&ledger).await?;
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct MigrationLedger {
+    pub version: u32,
+    /// The records in the order they were applied.
+    pub migrations: Vec<LedgerEntry>,
+}
+
+impl MigrationLedger {
+    /// Returns the ledger as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize the ledger")
+    }
+
+    /// Reads a ledger written by `to_json`, failing with an `Error::IO` of kind `InvalidData` when it is not one.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let ledger: MigrationLedger = serde_json::from_str(json).map_err(io::Error::from)?;
+        if ledger.version != LEDGER_FORMAT_VERSION {
+            return Err(Error::IO(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported ledger version {} (expected {}).", ledger.version, LEDGER_FORMAT_VERSION),
+            )));
+        }
+
+        Ok(ledger)
+    }
+}
+
+pub(crate) async fn run_export_ledger<C: Connection>(db: &Surreal<C>, table: &str) -> Result<MigrationLedger, Error> {
+    let migrations = db
+        .query(format!(
+            "SELECT filename, checksum, checksum_algorithm, created_at AS applied_at, execution_time_ms, applied_by, \
+            tool_version, repeatable = true AS repeatable, out_of_order = true AS out_of_order, baseline = true AS baseline, \
+            skipped = true AS skipped FROM {} ORDER BY applied_at ASC, filename ASC;",
+            table
+        ))
+        .await?
+        .check()?
+        .take::<Vec<LedgerEntry>>(0)?;
+
+    Ok(MigrationLedger { version: LEDGER_FORMAT_VERSION, migrations })
+}
+
+/// Replaces the records of the migrations table with the ones of a ledger, in a single transaction.
+pub(crate) async fn run_import_ledger<C: Connection>(
+    db: &Surreal<C>,
+    table: &str,
+    ledger: &MigrationLedger,
+) -> Result<(), Error> {
+    // The `created_at` field given is kept (see `Migrator::setup_migration_table`).
+    let sql = format!(
+        r#"
+            BEGIN TRANSACTION;
+            DELETE {table};
+            FOR $entry IN $entries {{
+                CREATE {table} SET filename=$entry.filename, checksum=$entry.checksum,
+                    checksum_algorithm=$entry.checksum_algorithm, created_at=<datetime>$entry.applied_at,
+                    execution_time_ms=$entry.execution_time_ms, applied_by=$entry.applied_by,
+                    tool_version=$entry.tool_version, repeatable=$entry.repeatable, out_of_order=$entry.out_of_order,
+                    baseline=$entry.baseline, skipped=$entry.skipped;
+            }};
+            COMMIT TRANSACTION;
+        "#,
+        table = table
+    );
+
+    let _ = db
+        .query(sql)
+        .bind(("entries", ledger.migrations.clone()))
+        .await?
+        .check()?;
+
+    info!(records = ledger.migrations.len(), "Migrations ledger imported");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Migrator, VecSource};
+
+    use super::MigrationLedger;
+
+    #[tokio::test]
+    async fn it_restores_the_ledger_on_another_database() {
+        let source = VecSource::new([
+            ("001_users.surql", "DEFINE TABLE users;"),
+            ("002_posts.surql", "DEFINE TABLE posts;"),
+            ("R__views.surql", "DEFINE TABLE views;"),
+        ]);
+
+        let staging = Surreal::new::<Mem>(()).await.unwrap();
+        staging.use_ns("test").use_db("staging").await.unwrap();
+        Migrator::new(&staging).source(&source).run().await.unwrap();
+
+        let ledger = Migrator::new(&staging).export_ledger().await.unwrap();
+        let ledger = MigrationLedger::from_json(&ledger.to_json()).unwrap();
+        assert_eq!(ledger.migrations.len(), 3);
+
+        let production = Surreal::new::<Mem>(()).await.unwrap();
+        production.use_ns("test").use_db("production").await.unwrap();
+        Migrator::new(&production)
+            .source(VecSource::new([("001_old.surql", "DEFINE TABLE old;")]))
+            .run()
+            .await
+            .unwrap();
+
+        Migrator::new(&production).import_ledger(&ledger).await.unwrap();
+        assert_eq!(Migrator::new(&production).export_ledger().await.unwrap(), ledger);
+
+        // Nothing is applied again.
+        let report = Migrator::new(&production).source(&source).run().await.unwrap();
+        assert!(report.applied.is_empty());
+    }
+}
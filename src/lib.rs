@@ -1,37 +1,286 @@
 extern crate chrono;
 
-use std::fmt;
+use std::{cmp::Ordering, path::PathBuf, time::Duration};
 use chrono::prelude::*;
 
-use regex::Regex;
-use serde::Deserialize;
-
-use surrealdb::{engine::remote::ws::Client, Surreal};
-use tokio::{fs::{read_dir, File}, io::AsyncReadExt};
-
-#[derive(Deserialize, PartialEq, Debug, Clone)]
+use serde::{Deserialize, Serialize};
+
+use surrealdb::{Connection, Surreal};
+
+mod checksum;
+mod clean;
+mod compat;
+mod data;
+mod diff;
+mod directives;
+mod events;
+mod hooks;
+mod idempotent;
+mod ledger;
+mod lint;
+mod lock;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod migrator;
+mod new;
+mod pattern;
+mod plan;
+mod policy;
+mod preflight;
+mod progress;
+mod prune;
+mod rehearsal;
+mod repair;
+mod reset;
+mod retry;
+mod rollback;
+mod seed;
+mod shadow;
+mod source;
+mod squash;
+mod statements;
+mod status;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod vars;
+mod verify;
+mod warnings;
+
+#[doc(hidden)]
+pub use include_dir;
+
+pub use checksum::ChecksumAlgorithm;
+pub use data::DEFAULT_DATA_BATCH_SIZE;
+pub use diff::{ChangedDefinition, SchemaDefinition, SchemaDiff, SchemaKind};
+pub use directives::Directives;
+pub use events::MigrationEvent;
+pub use hooks::HookFuture;
+pub use ledger::{LedgerEntry, MigrationLedger, LEDGER_FORMAT_VERSION};
+pub use lint::{lint, LintConfig, LintIssue, LintRule, LintSeverity};
+pub use lock::{MigrationLock, DEFAULT_LOCK_TIMEOUT};
+pub use migrator::{ApplyTarget, ExecutedMigration, FailedMigration, MigrationReport, Migrator, DEFAULT_TABLE, NAMESPACE_DATABASE};
+pub use new::{new_migration, new_migration_with_body, new_migration_with_template, MigrationTemplate, NamingScheme, NewMigration, DEFAULT_TEMPLATES_DIR, DOWN_TEMPLATE_FILE, TEMPLATE_FILE};
+pub use pattern::{FilePattern, DEFAULT_EXTENSION};
+pub use plan::{MigrationPlan, PlannedMigration, PLAN_FORMAT_VERSION};
+pub use policy::{Policy, PolicyAction};
+pub use rehearsal::{AssertionReport, FailedAssertion, DEFAULT_ASSERTIONS_DIR};
+pub use repair::RepairReport;
+pub use retry::{RetryPolicy, DEFAULT_RETRY_DELAY};
+pub use rollback::{down_filename, RollbackTarget};
+pub use seed::{SeedReport, DEFAULT_SEEDS_TABLE};
+pub use source::{EmbeddedSource, FsSource, MigrationReader, MigrationSource, MultiSource, VecSource, DEFAULT_STREAM_THRESHOLD, IGNORE_FILE, NAMESPACE_DIR};
+pub use squash::{SquashReport, DEFAULT_ARCHIVE_DIR};
+pub use statements::{parse_statements, Statement};
+pub use status::{AppliedMigration, MigrationState, MigrationStatus};
+pub use verify::{ChangedMigration, VerifyReport};
+pub use warnings::{MigrationWarning, WarningKind};
+
+/// A record of the migrations table: an applied migration file. Ordered by version (the number prefix of the
+/// filename), then by filename.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
 pub struct Migration {
     filename: String,
+    checksum: Option<String>,
+    /// The algorithm of the checksum (e.g. `sha256`), none for the records predating it (SHA-256).
+    checksum_algorithm: Option<String>,
     created_at: DateTime<Utc>,
+    /// How long the execution of the file took, in milliseconds.
+    execution_time_ms: Option<u64>,
+    /// Who applied the file (`<user>@<hostname>`).
+    applied_by: Option<String>,
+    /// The version of `surrealdb-simple-migration` that applied the file.
+    tool_version: Option<String>,
+    /// Whether the file is for other environments, recorded without being executed.
+    skipped: Option<bool>,
 }
 
-#[derive(Debug)]
-pub enum Error {
-    IO(std::io::Error),
-    Surreal(surrealdb::Error),
-    ForbiddenUpdate(String),
-    ForbiddenRemoval(String),
+/// A migration file read from the migration directory.
+#[derive(Debug, Clone)]
+struct MigrationFile {
+    filename: String,
+    content: String,
+    /// The SHA-256 checksum of the whole file.
+    checksum: String,
+    /// The whole file, including its down section, to compute its checksum with another algorithm.
+    raw_content: String,
+    /// The checksums of the files too large to be read at once (see `FsSource::stream_threshold`), computed while
+    /// streaming them: only their header is held in `content` and `raw_content`, their statements are read again
+    /// when they are applied.
+    streamed_checksums: Option<Vec<(ChecksumAlgorithm, String)>>,
 }
 
-impl From<std::io::Error> for Error {
-    fn from(err: std::io::Error) -> Self {
-        Error::IO(err)
+impl MigrationFile {
+    /// Returns the checksum of the whole file computed with an algorithm.
+    fn checksum_with(&self, algorithm: ChecksumAlgorithm) -> String {
+        if let Some(ref checksums) = self.streamed_checksums {
+            if let Some((_, checksum)) = checksums.iter().find(|(streamed, _)| *streamed == algorithm) {
+                return checksum.clone();
+            }
+        }
+
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => self.checksum.clone(),
+            #[allow(unreachable_patterns)]
+            _ => algorithm.digest(&self.raw_content),
+        }
+    }
+
+    /// Returns the checksum of the file computed with the algorithm of its record, to compare with the
+    /// recorded one. An unknown algorithm (e.g. `blake3` without the `blake3` feature) falls back to SHA-256.
+    fn checksum_for(&self, migration: &Migration) -> String {
+        let algorithm = migration
+            .checksum_algorithm
+            .as_deref()
+            .and_then(|name| name.parse().ok())
+            .unwrap_or_default();
+
+        self.checksum_with(algorithm)
+    }
+
+    /// Returns whether the file is too large to be read at once, so applied by streaming its statements.
+    fn is_streamed(&self) -> bool {
+        self.streamed_checksums.is_some()
+    }
+
+    /// Returns whether the file is the one named by a `-- ssm:requires` directive, with or without its extension.
+    fn is_named(&self, name: &str) -> bool {
+        self.filename == name || self.filename.rsplit_once('.').is_some_and(|(stem, _)| stem == name)
     }
+
+    /// Returns whether the file is the one recorded, a record without checksum matching any file.
+    fn matches(&self, migration: &Migration) -> bool {
+        migration
+            .checksum
+            .as_ref()
+            .is_none_or(|checksum| *checksum == self.checksum_for(migration))
+    }
+}
+
+/// A migration file that has not been applied yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingMigration {
+    pub filename: String,
+    pub checksum: String,
+    /// The SQL of the file, its `${VAR}` placeholders substituted.
+    pub content: String,
+    /// The path of the file, when read from the filesystem.
+    pub path: Option<PathBuf>,
+}
+
+/// The errors of the library. Each variant has a stable numeric `code`, and new variants may be added.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("IO error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("Surreal error: {0}")]
+    Surreal(#[from] surrealdb::Error),
+    /// An applied migration file has been updated after its migration.
+    #[error("Forbidden: The migration file '{filename}' has been updated after its migration.")]
+    ChecksumMismatch { filename: String, expected: String, actual: String },
+    /// Applied migration files are no longer in the migration directory.
+    #[error("Some migration files are missing - migrations failed: {}", filenames.join(", "))]
+    MissingMigration { filenames: Vec<String> },
+    /// A pending migration file has a lower version than the last applied one.
+    #[error("The migration file '{filename}' appears before the last migration file '{last_applied}'.")]
+    OutOfOrder { filename: String, last_applied: String },
+    /// Two migration files share the same version.
+    #[error("The migration files '{first}' and '{second}' share the same version.")]
+    DuplicateVersion { first: String, second: String },
+    /// The `.down.surql` file of a migration to roll back is missing.
+    #[error("Missing down migration file '{down_filename}' for '{filename}'.")]
+    MissingDownMigration { filename: String, down_filename: String },
+    /// Migration files to squash have not been applied yet.
+    #[error("The migration files must be applied before being squashed: {}", filenames.join(", "))]
+    NotApplied { filenames: Vec<String> },
+    /// A migration file opting out of transactions, or streamed as too large to be read at once, cannot be applied
+    /// in a single transaction.
+    #[error("The migration file '{filename}' is not transactional and cannot be applied in a single transaction.")]
+    NotTransactional { filename: String },
+    /// A migration file requires (`-- ssm:requires`) a file that is not in the migration directory.
+    #[error("The migration file '{filename}' requires '{requires}', which is not a migration file.")]
+    MissingDependency { filename: String, requires: String },
+    /// Migration files cannot be ordered as they require each other, directly or not.
+    #[error("The requirements of the migration files form a cycle: {}", filenames.join(", "))]
+    DependencyCycle { filenames: Vec<String> },
+    /// The migrations that would be applied differ from the expected plan (see `Migrator::expected_plan`).
+    #[error("The pending migrations differ from the approved plan: {}", filenames.join(", "))]
+    PlanMismatch { filenames: Vec<String> },
+    /// The migrations are locked by another migrator.
+    #[error("The migrations are locked by '{owner}' until {expires_at}.")]
+    LockHeld { owner: String, expires_at: DateTime<Utc> },
+    /// A name (of a table, a migration or an environment) that cannot be used.
+    #[error("{message}")]
+    InvalidName { name: String, message: String },
+    /// A migration file is not valid SurrealQL.
+    #[error("Parse error in {filename} (line {line}): {message}")]
+    ParseError { filename: String, line: usize, message: String },
+    /// A statement of a migration file failed on the database.
+    #[error("Error in {filename}, statement {statement} (line {line}): {message}\n{sql}")]
+    StatementFailed { filename: String, statement: usize, line: usize, sql: String, message: String },
+    /// A `${VAR}` placeholder of a migration file has no value.
+    #[error("The variable '{name}' is not defined.")]
+    UndefinedVariable { name: String },
+    /// The migrations (or a single migration file, when `filename` is set) did not complete in time.
+    #[error("{} did not complete within {duration:?}.", filename.as_ref().map_or("The migrations".to_string(), |filename| format!("The migration file '{}'", filename)))]
+    Timeout { filename: Option<String>, duration: Duration },
+    /// A migration file requires (`-- ssm:requires-surreal`) a version of SurrealDB the server does not match.
+    #[error("The migration file '{filename}' requires SurrealDB {requirement}, but the server runs {version}.")]
+    IncompatibleServer { filename: String, requirement: String, version: String },
+    /// The user signed in is not allowed an operation the migrations need (e.g. `DEFINE TABLE`), found before
+    /// applying them (see `Migrator::preflight`).
+    #[error("The user is not allowed to {operation} in the database, which the migrations need: {message}")]
+    InsufficientPermissions { operation: String, message: String },
+    /// The migration history failed when replayed into an empty shadow database (see `Migrator::shadow_database`),
+    /// so nothing was applied to the database.
+    #[error("The migrations do not apply to the empty shadow database '{database}': {source}")]
+    ShadowFailed { database: String, source: Box<Error> },
 }
 
-impl From<surrealdb::Error> for Error {
-    fn from(err: surrealdb::Error) -> Self {
-        Error::Surreal(err)
+impl Migration {
+    /// The name of the migration file (e.g. `004_i18n_table.surql`).
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// The number prefix of the filename (e.g. `004`), empty for the repeatable files.
+    pub fn version(&self) -> &str {
+        version(&self.filename)
+    }
+
+    /// The checksum of the file when it was applied, none for the records predating the checksums.
+    pub fn checksum(&self) -> Option<&str> {
+        self.checksum.as_deref()
+    }
+
+    /// The algorithm of the checksum (e.g. `sha256`), none for the records predating it (SHA-256).
+    pub fn checksum_algorithm(&self) -> Option<&str> {
+        self.checksum_algorithm.as_deref()
+    }
+
+    /// When the file was applied (the `created_at` field of the record).
+    pub fn applied_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    /// How long the execution of the file took, in milliseconds, when recorded.
+    pub fn execution_time_ms(&self) -> Option<u64> {
+        self.execution_time_ms
+    }
+
+    /// Who applied the file (`<user>@<hostname>`), when recorded.
+    pub fn applied_by(&self) -> Option<&str> {
+        self.applied_by.as_deref()
+    }
+
+    /// The version of `surrealdb-simple-migration` that applied the file, when recorded.
+    pub fn tool_version(&self) -> Option<&str> {
+        self.tool_version.as_deref()
+    }
+
+    /// Whether the file is for other environments, recorded without being executed.
+    pub fn is_skipped(&self) -> bool {
+        self.skipped.unwrap_or(false)
     }
 }
 
@@ -41,156 +290,227 @@ impl PartialEq<String> for Migration {
     }
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl Ord for Migration {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_versions(version(&self.filename), version(&other.filename))
+            .then_with(|| self.filename.cmp(&other.filename))
+            // The same file recorded twice, ordered consistently with `Eq`.
+            .then_with(|| self.created_at.cmp(&other.created_at))
+            .then_with(|| self.checksum.cmp(&other.checksum))
+            .then_with(|| self.checksum_algorithm.cmp(&other.checksum_algorithm))
+            .then_with(|| self.execution_time_ms.cmp(&other.execution_time_ms))
+            .then_with(|| self.applied_by.cmp(&other.applied_by))
+            .then_with(|| self.tool_version.cmp(&other.tool_version))
+            .then_with(|| self.skipped.cmp(&other.skipped))
+    }
+}
+
+impl PartialOrd for Migration {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Error {
+    /// Returns the stable numeric code of the error, to handle it programmatically (e.g. in logs or metrics).
+    /// The codes of the existing variants never change.
+    pub fn code(&self) -> u32 {
         match *self {
-            Error::IO(ref err) => write!(f, "IO error: {}", err),
-            Error::Surreal(ref err) => write!(f, "Surreal error: {}", err),
-            Error::ForbiddenUpdate(ref err) => write!(f, "Forbidden update: {}", err),
-            Error::ForbiddenRemoval(ref err) => write!(f, "Forbidden removal: {}", err),
+            Error::IO(_) => 1,
+            Error::Surreal(_) => 2,
+            Error::ChecksumMismatch { .. } => 10,
+            Error::MissingMigration { .. } => 11,
+            Error::OutOfOrder { .. } => 12,
+            Error::DuplicateVersion { .. } => 13,
+            Error::MissingDownMigration { .. } => 14,
+            Error::NotApplied { .. } => 15,
+            Error::NotTransactional { .. } => 16,
+            Error::MissingDependency { .. } => 17,
+            Error::DependencyCycle { .. } => 18,
+            Error::PlanMismatch { .. } => 19,
+            Error::LockHeld { .. } => 20,
+            Error::ShadowFailed { .. } => 21,
+            Error::InvalidName { .. } => 30,
+            Error::ParseError { .. } => 40,
+            Error::StatementFailed { .. } => 41,
+            Error::UndefinedVariable { .. } => 42,
+            Error::Timeout { .. } => 50,
+            Error::IncompatibleServer { .. } => 60,
+            Error::InsufficientPermissions { .. } => 61,
         }
     }
-}
 
-impl std::error::Error for Error {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    /// Returns the migration file the error is about, if it is about a single one (e.g. to annotate the file in a
+    /// pull request).
+    pub fn filename(&self) -> Option<&str> {
         match *self {
-            Error::IO(ref err) => Some(err),
-            Error::Surreal(ref err) => Some(err),
-            Error::ForbiddenUpdate(_) => None,
-            Error::ForbiddenRemoval(_) => None,
+            Error::ChecksumMismatch { ref filename, .. }
+            | Error::OutOfOrder { ref filename, .. }
+            | Error::MissingDownMigration { ref filename, .. }
+            | Error::NotTransactional { ref filename }
+            | Error::MissingDependency { ref filename, .. }
+            | Error::ParseError { ref filename, .. }
+            | Error::StatementFailed { ref filename, .. }
+            | Error::IncompatibleServer { ref filename, .. } => Some(filename),
+            Error::DuplicateVersion { ref second, .. } => Some(second),
+            Error::Timeout { ref filename, .. } => filename.as_deref(),
+            Error::ShadowFailed { ref source, .. } => source.filename(),
+            _ => None,
         }
     }
 
+    /// Returns the line of the migration file the error is about, when known.
+    pub fn line(&self) -> Option<usize> {
+        match *self {
+            Error::ParseError { line, .. } | Error::StatementFailed { line, .. } => Some(line),
+            Error::ShadowFailed { ref source, .. } => source.line(),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the error may not happen again when retrying the failed operation, such as a dropped
+    /// connection or a transaction conflict.
+    pub fn is_transient(&self) -> bool {
+        match *self {
+            Error::IO(ref err) => matches!(
+                err.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::TimedOut
+            ),
+            Error::Surreal(surrealdb::Error::Api(ref err)) => match *err {
+                #[cfg(feature = "protocol-ws")]
+                surrealdb::error::Api::Ws(_) => true,
+                #[cfg(feature = "protocol-http")]
+                surrealdb::error::Api::Http(_) => true,
+                surrealdb::error::Api::ConnectionUninitialised => true,
+                _ => false,
+            },
+            // E.g. "Failed to commit transaction due to a read or write conflict. This transaction can be retried".
+            Error::Surreal(ref err) => err.to_string().contains("can be retried"),
+            _ => false,
+        }
+    }
 }
 
-pub async fn migrate(db: &Surreal<Client>, migration_dir_path: &str) -> Result<(), Error> {
-    setup_migration_table(db).await?;
-    run_migration_files(db, migration_dir_path).await?;
+/// Applies the pending migration files of a directory, using the default `Migrator` options.
+pub async fn migrate<C: Connection>(db: &Surreal<C>, migration_dir_path: &str) -> Result<MigrationReport, Error> {
+    Migrator::new(db)
+        .dir(migration_dir_path)
+        .run()
+        .await
+}
 
-    Ok(())
+/// Resolves the pending migrations and validates the migration directory against the migrations table
+/// (checksums, ordering and missing files), without touching the database.
+pub async fn plan<C: Connection>(db: &Surreal<C>, migration_dir_path: &str) -> Result<Vec<PendingMigration>, Error> {
+    Migrator::new(db)
+        .dir(migration_dir_path)
+        .plan()
+        .await
 }
 
-async fn setup_migration_table(db: &Surreal<Client>) -> Result<(), surrealdb::Error> {
-    let sql = r#"
-        DEFINE TABLE IF NOT EXISTS migrations SCHEMAFULL;
-        DEFINE FIELD IF NOT EXISTS filename ON TABLE migrations TYPE string;
-        DEFINE FIELD IF NOT EXISTS created_at ON TABLE migrations TYPE datetime VALUE time::now();
-    "#;
+/// Compares the migration directory with the migrations table, without applying anything.
+pub async fn status<C: Connection>(db: &Surreal<C>, migration_dir_path: &str) -> Result<Vec<MigrationStatus>, Error> {
+    Migrator::new(db)
+        .dir(migration_dir_path)
+        .status()
+        .await
+}
 
-    let _ = db
-        .query(sql)
-        .await?
-        .check()?;
+/// Checks the applied migration files are unchanged and still present, and the pending ones correctly ordered,
+/// without applying anything.
+pub async fn verify<C: Connection>(db: &Surreal<C>, migration_dir_path: &str) -> Result<VerifyReport, Error> {
+    Migrator::new(db)
+        .dir(migration_dir_path)
+        .verify()
+        .await
+}
 
-    Ok(())
+/// Rolls back applied migrations in reverse order by executing their paired `.down.surql` files,
+/// then removes them from the migrations table.
+pub async fn rollback<C: Connection>(db: &Surreal<C>, migration_dir_path: &str, target: RollbackTarget) -> Result<(), Error> {
+    Migrator::new(db)
+        .dir(migration_dir_path)
+        .rollback(target)
+        .await
+}
+
+/// Removes the resources (tables, fields, indexes, events, functions, params and analyzers) defined by the
+/// applied migration files of a directory, then the migrations table.
+pub async fn reset<C: Connection>(db: &Surreal<C>, migration_dir_path: &str) -> Result<(), Error> {
+    Migrator::new(db)
+        .dir(migration_dir_path)
+        .reset()
+        .await
+}
+
+/// Removes the migrations lock whoever holds it (e.g. a lock left by a crashed migrator).
+pub async fn force_unlock<C: Connection>(db: &Surreal<C>) -> Result<(), Error> {
+    Migrator::new(db)
+        .force_unlock()
+        .await
 }
 
-async fn run_migration_files(db: &Surreal<Client>, migration_dir_path: &str) -> Result<(), Error> {
-    // Get the files already processed.
+/// Returns the migrations already applied, ordered by application date.
+async fn get_migrations<C: Connection>(db: &Surreal<C>, table: &str) -> Result<Vec<Migration>, Error> {
     let migrations = db
-        .query("SELECT * FROM migrations ORDER BY created_at ASC;")
+        .query(format!("SELECT * FROM {} WHERE repeatable != true ORDER BY created_at ASC;", table))
         .await?
         .check()?
         .take::<Vec<Migration>>(0)?;
-    let mut remaining_migrations: Vec<Migration> = migrations.clone();
 
-    println!("Migrated files: {:#?}", migrations);
+    Ok(migrations)
+}
 
-    // Get the surql migration files to execute.
-    let mut dir = read_dir(migration_dir_path).await?;
-    let mut entries: Vec<String> = vec![];
+/// Returns the repeatable migrations already applied, with the checksum of their last application.
+async fn get_repeatable_migrations<C: Connection>(db: &Surreal<C>, table: &str) -> Result<Vec<Migration>, Error> {
+    let migrations = db
+        .query(format!("SELECT * FROM {} WHERE repeatable = true ORDER BY filename ASC;", table))
+        .await?
+        .check()?
+        .take::<Vec<Migration>>(0)?;
 
-    // Filter the files that fit the migration pattern.
-    while let Some(dir_entry) = dir.next_entry().await? {
-        let filename = dir_entry.path().to_str().unwrap().to_string().replace((migration_dir_path.to_owned() + "/").as_str(), "");
-        let pattern = r"^[0-9]+[a-zA-Z_0-9]{0,}\.surql$";
-        let regex = Regex::new(&pattern).expect("Failed to build the regexp");
-        if regex.is_match(&filename) {
-            entries.push(filename);
-        }
-    }
+    Ok(migrations)
+}
 
-    // Sort the entries (by their number prefix).
-    entries.sort(); // TODO: Check how the strings are sorted.
-
-    // Process migration files.
-    println!("Migration files: {:#?}", entries);
-
-    let last_migration = migrations.last();
-
-    // Checker - check for forbidden updates and removals.
-    for entry in entries {
-        // Get the file descriptor.
-        let mut file = File::open(migration_dir_path.to_owned() + "/" + &entry).await?;
-
-        // Check if the file has already been migrated.
-        let migrated = migrations
-            .iter()
-            .any(|migration: &Migration| migration == &entry);
-
-        // If migrated, check that the last update date is anterior to the created_at.
-        if migrated {
-            let updated_at: DateTime<Utc> = File::metadata(&file)
-                .await?
-                .modified()?
-                .into();
-
-            // Ensure the file has not been updated after the last migration.
-            if updated_at > last_migration.unwrap().created_at {
-                println!("[X] Forbidden: The migration file '{}' has been updated after the last migration.", entry);
-                return Err(
-                    Error::ForbiddenUpdate(
-                        format!("Forbidden: The migration file '{}' has been updated after the last migration.", entry)
-                    )
-                );
-            }
+/// Returns the applied migration with the highest version.
+fn last_applied_migration(migrations: &[Migration]) -> Option<&Migration> {
+    migrations
+        .iter()
+        .max_by(|a, b| compare_versions(version(&a.filename), version(&b.filename)))
+}
 
-            println!("[V] File already migrated: {}", entry);
-        } else {
-            // TODO: Check that the new migration file appears after the last migration file.
-            let mut migration_content: String = String::new();
-            file.read_to_string(&mut migration_content).await?;
-
-            // When the last migration file is created after the current file, it should fail.
-            if last_migration != None && last_migration.unwrap().created_at > DateTime::<Utc>::from(File::metadata(&file).await?.modified()?) {
-                println!("[X] The migration file '{}' appears before the last migration file '{}'.", &entry, last_migration.unwrap().filename);
-
-                return Err(
-                    Error::ForbiddenUpdate(
-                        format!("The migration file '{}' appears before the last migration file '{}'.", &entry, last_migration.unwrap().filename)
-                    )
-                );
-            }
+/// Returns whether a new migration file has a lower version than the last applied migration
+/// (e.g. a migration merged from another branch after a higher one has been applied).
+fn is_out_of_order(entry: &MigrationFile, last_migration: Option<&Migration>) -> bool {
+    match last_migration {
+        Some(last_migration) => compare_versions(version(&entry.filename), version(&last_migration.filename)).is_lt(),
+        None => false,
+    }
+}
 
-            // Migrate the file.
-            let _ = db.query(migration_content).await?;
-            let _ = db
-                .query("CREATE migrations SET filename=$filename;")
-                .bind(("filename", entry.clone()))
-                .await?
-                .check()?;
+/// Returns the number prefix of a migration filename (e.g. `004` for `004_i18n_table.surql`).
+fn version(filename: &str) -> &str {
+    let end = filename
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(filename.len());
 
-            println!("[V] File successfuly migrated: {}", &entry);
-        }
+    &filename[..end]
+}
 
-        // Update the migrations list.
-        let position = remaining_migrations.iter().position(|migration| { *migration.filename == entry });
-        if let Some(pos) = position {
-            remaining_migrations.remove(pos);
-        }
-    }
+/// Compares two number prefixes numerically, ignoring their zero padding.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
 
-    if remaining_migrations.len() > 0 {
-        println!("[X] Some migration files are missing - migrations failed: {:?}", remaining_migrations);
-        return Err(
-            Error::ForbiddenRemoval(
-                format!("Some migration files are missing - migrations failed: {:?}", remaining_migrations)
-            )
-        )
-    }
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
 
-    Ok(())
+/// Returns the hex encoded SHA-256 checksum of a migration file content.
+fn checksum(content: &str) -> String {
+    ChecksumAlgorithm::Sha256.digest(content)
 }
 
 #[cfg(test)]
@@ -218,6 +538,77 @@ mod tests {
 
         let _ = tokio::fs::remove_dir_all("test/migrations").await;
         let _ = db.query("DELETE migrations;").await.expect("Failed to delete migrations table.");
+        let _ = db.query("DELETE migrations_lock;").await.expect("Failed to delete migrations lock table.");
+    }
+
+    #[test]
+    fn it_computes_hex_sha256_checksums() {
+        assert_eq!(
+            super::checksum("DEFINE TABLE users SCHEMAFULL;"),
+            super::checksum("DEFINE TABLE users SCHEMAFULL;")
+        );
+        assert_eq!(
+            super::checksum(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn it_orders_the_migrations_by_version() {
+        let migration = |filename: &str| super::Migration {
+            filename: filename.to_string(),
+            checksum: Some("abc".to_string()),
+            checksum_algorithm: Some("sha256".to_string()),
+            created_at: "2024-05-21T14:30:00Z".parse().unwrap(),
+            execution_time_ms: Some(12),
+            applied_by: None,
+            tool_version: None,
+            skipped: None,
+        };
+
+        let mut migrations = vec![migration("10_posts.surql"), migration("002_users.surql"), migration("1_init.surql")];
+        migrations.sort();
+        assert_eq!(
+            migrations.iter().map(super::Migration::filename).collect::<Vec<_>>(),
+            ["1_init.surql", "002_users.surql", "10_posts.surql"]
+        );
+        assert_eq!(migrations[1].version(), "002");
+        assert_eq!(migrations[1].checksum(), Some("abc"));
+
+        let json = serde_json::to_value(&migrations[0]).unwrap();
+        assert_eq!(json["filename"], "1_init.surql");
+        assert_eq!(json["created_at"], "2024-05-21T14:30:00Z");
+    }
+
+    #[test]
+    fn it_describes_errors_with_their_fields_and_code() {
+        let err = super::Error::StatementFailed {
+            filename: "003_comments.surql".to_string(),
+            statement: 2,
+            line: 14,
+            sql: "DEFINE FIELD text ON comment;".to_string(),
+            message: "The table does not exist".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Error in 003_comments.surql, statement 2 (line 14): The table does not exist\nDEFINE FIELD text ON comment;"
+        );
+        assert_eq!(err.code(), 41);
+        assert_eq!(err.filename(), Some("003_comments.surql"));
+        assert_eq!(err.line(), Some(14));
+
+        let err = super::Error::Timeout { filename: None, duration: std::time::Duration::from_secs(5) };
+        assert_eq!(err.to_string(), "The migrations did not complete within 5s.");
+        assert_eq!(err.code(), 50);
+    }
+
+    #[test]
+    fn it_compares_versions_numerically() {
+        assert_eq!(super::version("004_i18n_table.surql"), "004");
+        assert_eq!(super::version("12.surql"), "12");
+        assert!(super::compare_versions("004", "4").is_eq());
+        assert!(super::compare_versions("10", "002").is_gt());
+        assert!(super::compare_versions("001", "002").is_lt());
     }
 
     #[tokio::test]
@@ -281,6 +672,7 @@ mod tests {
 
         // Assert
         assert!(result.is_ok());
+        assert_eq!(result.unwrap().applied.len(), 4);
 
         // 2. When migration files are already processed, it should skip them.
         // Act - Run the migration again.
@@ -288,6 +680,9 @@ mod tests {
 
         // Assert
         assert!(result.is_ok());
+        let report = result.unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(report.skipped.len(), 4);
 
         // 3. When new migration files are added, it should process them.
         // Arrange - Add a new migration file.
@@ -318,8 +713,15 @@ mod tests {
         assert!(res.is_err());
 
         // 5. When a migrated file is removed, it should return an error.
-        // Arrange - Reset the migrations, migrate the files again and remove one file.
-        let _ = db.query("DELETE migrations;").await;
+        // Arrange - Reset the migrations and the tables they define, migrate the files again and remove one file.
+        let _ = db
+            .query("DELETE migrations;")
+            .query("REMOVE TABLE IF EXISTS users; REMOVE TABLE IF EXISTS user; REMOVE TABLE IF EXISTS posts;")
+            .query("REMOVE TABLE IF EXISTS comments; REMOVE TABLE IF EXISTS i18n; REMOVE TABLE IF EXISTS likes;")
+            .await
+            .expect("Failed to reset the migrations.")
+            .check()
+            .expect("Failed to reset the migrations.");
         super::migrate(&db, migration_dir_path).await.expect("Failed to migrate the files.");
         tokio::fs::remove_file(migration_dir_path.to_owned() + "/001_create_user_table.surql").await.unwrap();
 
@@ -338,5 +740,7 @@ mod tests {
         db.query("REMOVE TABLE posts;").await.unwrap();
         db.query("REMOVE TABLE comments;").await.unwrap();
         db.query("REMOVE TABLE likes;").await.unwrap();
+        db.query("REMOVE TABLE i18n;").await.unwrap();
+        db.query("REMOVE TABLE user;").await.unwrap();
     }
 }
\ No newline at end of file
@@ -0,0 +1,458 @@
+use std::{collections::HashMap, fmt};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    compare_versions,
+    directives::split_sections,
+    down_filename,
+    source::read_migration_files,
+    statements::{is_terminated, parse_statements},
+    version, Error, FilePattern, MigrationSource,
+};
+
+/// A check of the `lint` of the migration files.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LintRule {
+    /// The file has no statements. (default: error)
+    EmptyFile,
+    /// The last statement of the file is not terminated by a `;`. (default: warning)
+    MissingSemicolon,
+    /// A `REMOVE TABLE` statement without `IF EXISTS`. (default: warning)
+    UnguardedRemoveTable,
+    /// A `DEFINE` statement without `IF NOT EXISTS` or `OVERWRITE`, failing when applied twice. (default: off)
+    NonIdempotentDefine,
+    /// A `.surql` file not named `<number>_<snake_case_name>.surql` or `R__<name>.surql` (with an optional
+    /// `.down` suffix). (default: warning)
+    Naming,
+    /// Two numbered migration files share the same version. (default: error)
+    DuplicateVersion,
+    /// A `-- ssm:requires` directive names a missing file, or the files require each other. (default: error)
+    UnresolvedRequirement,
+    /// A statement the SurrealQL parser rejects. The statements with `${VAR}` placeholders, only known when the
+    /// migrations are applied, are not parsed. (default: error)
+    InvalidStatement,
+    /// A numbered migration file without a `.down` file or a `-- +down` section, to roll it back. (default: off)
+    MissingDownFile,
+    /// A migration file failing when replayed into an in-memory database (e.g. an unknown function or a value of
+    /// the wrong type), only checked in deep mode. (default: error)
+    ReplayError,
+}
+
+impl LintRule {
+    /// The severity of the rule when it is not configured.
+    pub fn default_severity(&self) -> LintSeverity {
+        match *self {
+            LintRule::EmptyFile => LintSeverity::Error,
+            LintRule::MissingSemicolon => LintSeverity::Warning,
+            LintRule::UnguardedRemoveTable => LintSeverity::Warning,
+            LintRule::NonIdempotentDefine => LintSeverity::Off,
+            LintRule::Naming => LintSeverity::Warning,
+            LintRule::DuplicateVersion => LintSeverity::Error,
+            LintRule::UnresolvedRequirement => LintSeverity::Error,
+            LintRule::InvalidStatement => LintSeverity::Error,
+            LintRule::MissingDownFile => LintSeverity::Off,
+            LintRule::ReplayError => LintSeverity::Error,
+        }
+    }
+}
+
+impl fmt::Display for LintRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LintRule::EmptyFile => write!(f, "empty_file"),
+            LintRule::MissingSemicolon => write!(f, "missing_semicolon"),
+            LintRule::UnguardedRemoveTable => write!(f, "unguarded_remove_table"),
+            LintRule::NonIdempotentDefine => write!(f, "non_idempotent_define"),
+            LintRule::Naming => write!(f, "naming"),
+            LintRule::DuplicateVersion => write!(f, "duplicate_version"),
+            LintRule::UnresolvedRequirement => write!(f, "unresolved_requirement"),
+            LintRule::InvalidStatement => write!(f, "invalid_statement"),
+            LintRule::MissingDownFile => write!(f, "missing_down_file"),
+            LintRule::ReplayError => write!(f, "replay_error"),
+        }
+    }
+}
+
+/// How a lint issue is reported.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    /// The rule is not checked.
+    Off,
+    /// The issue is reported, without failing the lint.
+    Warning,
+    /// The issue fails the lint.
+    Error,
+}
+
+impl fmt::Display for LintSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LintSeverity::Off => write!(f, "off"),
+            LintSeverity::Warning => write!(f, "warning"),
+            LintSeverity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// The severity of the lint rules, overriding their default one.
+///
+/// ```ignore
+/// let config = LintConfig::new()
+///     .rule(LintRule::NonIdempotentDefine, LintSeverity::Error)
+///     .rule(LintRule::Naming, LintSeverity::Off);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    severities: HashMap<LintRule, LintSeverity>,
+    pattern: FilePattern,
+    #[cfg(feature = "kv-mem")]
+    deep: bool,
+    #[cfg(feature = "kv-mem")]
+    vars: HashMap<String, String>,
+}
+
+impl LintConfig {
+    pub fn new() -> Self {
+        LintConfig::default()
+    }
+
+    /// Sets the severity of a rule.
+    pub fn rule(mut self, rule: LintRule, severity: LintSeverity) -> Self {
+        self.severities.insert(rule, severity);
+        self
+    }
+
+    /// Which files are migration files, to lint the files with one of its extensions, except the ignored ones.
+    /// (default: `.surql` files)
+    pub fn file_pattern(mut self, pattern: FilePattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Whether the migration files are also replayed into an in-memory database, to find the errors only the
+    /// database reports (e.g. an unknown function or a value of the wrong type). The replay stops at the first
+    /// failing file. Requires the `kv-mem` feature. (default: false)
+    #[cfg(feature = "kv-mem")]
+    pub fn deep(mut self, deep: bool) -> Self {
+        self.deep = deep;
+        self
+    }
+
+    /// The values of the `${VAR}` placeholders of the migration files replayed in deep mode.
+    #[cfg(feature = "kv-mem")]
+    pub fn vars(mut self, vars: HashMap<String, String>) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    /// Returns the severity of a rule.
+    pub fn severity(&self, rule: LintRule) -> LintSeverity {
+        self.severities
+            .get(&rule)
+            .copied()
+            .unwrap_or_else(|| rule.default_severity())
+    }
+}
+
+/// An issue found in a migration file by the lint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub filename: String,
+    /// The line of the statement causing the issue, if any.
+    pub line: Option<usize>,
+    pub rule: LintRule,
+    pub severity: LintSeverity,
+    pub message: String,
+}
+
+/// Statically checks the migration files, repeatable migration files and down files of a source (the `.surql`
+/// files, or the ones with an extension of the configured pattern) without touching the database, returning the
+/// issues found, sorted by filename.
+pub async fn lint(source: &dyn MigrationSource, config: &LintConfig) -> Result<Vec<LintIssue>, Error> {
+    let mut filenames: Vec<String> = source
+        .list()
+        .await?
+        .into_iter()
+        .filter(|filename| config.pattern.has_extension(filename) && !config.pattern.is_ignored(filename))
+        .collect();
+    filenames.sort();
+
+    let mut issues: Vec<LintIssue> = vec![];
+    let mut migrations: Vec<(String, bool)> = vec![];
+    for filename in &filenames {
+        if let Some(content) = source.read(filename).await? {
+            issues.extend(lint_file(filename, &content, config));
+
+            if config.pattern.is_migration(filename) {
+                migrations.push((filename.clone(), split_sections(&content).1.is_some()));
+            }
+        }
+    }
+
+    let mut report = |filename: &str, rule: LintRule, message: String| {
+        let severity = config.severity(rule);
+        if severity != LintSeverity::Off {
+            issues.push(LintIssue { filename: filename.to_string(), line: None, rule, severity, message });
+        }
+    };
+
+    migrations.sort_by(|(a, _), (b, _)| compare_versions(version(a), version(b)).then_with(|| a.cmp(b)));
+    for pair in migrations.windows(2) {
+        if compare_versions(version(&pair[0].0), version(&pair[1].0)).is_eq() {
+            report(&pair[1].0, LintRule::DuplicateVersion, format!("The file has the same version as {}.", pair[0].0));
+        }
+    }
+
+    if config.severity(LintRule::UnresolvedRequirement) != LintSeverity::Off {
+        // The files sharing a version are already reported.
+        match read_migration_files(source, &config.pattern).await {
+            Ok(_) | Err(Error::DuplicateVersion { .. }) => (),
+            Err(Error::MissingDependency { filename, requires }) => {
+                report(&filename, LintRule::UnresolvedRequirement, format!("The required file {} does not exist.", requires));
+            },
+            Err(Error::DependencyCycle { filenames }) => {
+                report(
+                    &filenames[0],
+                    LintRule::UnresolvedRequirement,
+                    format!("The files require each other: {}.", filenames.join(", ")),
+                );
+            },
+            Err(e) => return Err(e),
+        }
+    }
+
+    for (filename, has_down_section) in &migrations {
+        if !has_down_section && !filenames.contains(&down_filename(filename)) {
+            report(filename, LintRule::MissingDownFile, format!("The file has no {} file or -- +down section.", down_filename(filename)));
+        }
+    }
+
+    #[cfg(feature = "kv-mem")]
+    if config.deep && config.severity(LintRule::ReplayError) != LintSeverity::Off {
+        issues.extend(replay(source, config).await?);
+    }
+
+    issues.sort_by(|a, b| a.filename.cmp(&b.filename));
+
+    Ok(issues)
+}
+
+/// Applies the migration files to a fresh in-memory database, reporting the first one failing.
+#[cfg(feature = "kv-mem")]
+async fn replay(source: &dyn MigrationSource, config: &LintConfig) -> Result<Option<LintIssue>, Error> {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::Migrator;
+
+    let shadow = Surreal::new::<Mem>(()).await?;
+    shadow.use_ns("ssm").use_db("lint").await?;
+
+    let result = Migrator::new(&shadow)
+        .source(source)
+        .file_pattern(config.pattern.clone())
+        .allow_out_of_order(true)
+        .vars(config.vars.clone())
+        .run()
+        .await;
+
+    match result {
+        Ok(_) => Ok(None),
+        // Already reported by the other rules.
+        Err(Error::DuplicateVersion { .. } | Error::MissingDependency { .. } | Error::DependencyCycle { .. }) => Ok(None),
+        Err(e) => match e.filename() {
+            Some(filename) => Ok(Some(LintIssue {
+                filename: filename.to_string(),
+                line: e.line(),
+                rule: LintRule::ReplayError,
+                severity: config.severity(LintRule::ReplayError),
+                message: e.to_string(),
+            })),
+            None => Err(e),
+        },
+    }
+}
+
+/// Checks a migration file against the enabled rules.
+fn lint_file(filename: &str, content: &str, config: &LintConfig) -> Vec<LintIssue> {
+    let remove_table = Regex::new(r"(?i)^REMOVE\s+TABLE\s").expect("Failed to build the regexp");
+    let guarded_remove_table = Regex::new(r"(?i)^REMOVE\s+TABLE\s+IF\s+EXISTS\s").expect("Failed to build the regexp");
+    let define = Regex::new(r"(?i)^DEFINE\s+[a-zA-Z]+\s").expect("Failed to build the regexp");
+    let idempotent_define = Regex::new(r"(?i)^DEFINE\s+[a-zA-Z]+\s+(IF\s+NOT\s+EXISTS|OVERWRITE)\s").expect("Failed to build the regexp");
+
+    let mut issues: Vec<LintIssue> = vec![];
+    let mut report = |rule: LintRule, line: Option<usize>, message: String| {
+        let severity = config.severity(rule);
+        if severity != LintSeverity::Off {
+            issues.push(LintIssue { filename: filename.to_string(), line, rule, severity, message });
+        }
+    };
+
+    if !follows_naming(filename, &config.pattern) {
+        report(
+            LintRule::Naming,
+            None,
+            "The file should be named <number>_<snake_case_name>.surql or R__<name>.surql.".to_string()
+        );
+    }
+
+    let statements = parse_statements(content);
+    let Some(last) = statements.last() else {
+        report(LintRule::EmptyFile, None, "The file has no statements.".to_string());
+        return issues;
+    };
+
+    if !is_terminated(content) {
+        report(
+            LintRule::MissingSemicolon,
+            Some(last.line),
+            "The last statement is not terminated by a semicolon.".to_string()
+        );
+    }
+
+    for statement in &statements {
+        if !statement.sql.contains("${") {
+            if let Err(e) = surrealdb::sql::parse(&statement.sql) {
+                let message = e.to_string();
+                report(
+                    LintRule::InvalidStatement,
+                    Some(statement.line),
+                    format!("The statement does not parse: {}", message.lines().next().unwrap_or_default().trim())
+                );
+            }
+        }
+
+        if remove_table.is_match(&statement.sql) && !guarded_remove_table.is_match(&statement.sql) {
+            report(
+                LintRule::UnguardedRemoveTable,
+                Some(statement.line),
+                "REMOVE TABLE without IF EXISTS fails when the table does not exist.".to_string()
+            );
+        }
+
+        if define.is_match(&statement.sql) && !idempotent_define.is_match(&statement.sql) {
+            report(
+                LintRule::NonIdempotentDefine,
+                Some(statement.line),
+                "DEFINE without IF NOT EXISTS or OVERWRITE fails when applied twice.".to_string()
+            );
+        }
+    }
+
+    issues
+}
+
+/// Returns whether a file is named `<number>_<snake_case_name>` (or matches the custom regex of the pattern) or
+/// `R__<name>`, with an optional `.down` suffix.
+fn follows_naming(filename: &str, pattern: &FilePattern) -> bool {
+    let filename = match filename.rsplit_once('.') {
+        Some((name, extension)) => format!("{}.{}", name.strip_suffix(".down").unwrap_or(name), extension),
+        None => filename.to_string(),
+    };
+
+    if pattern.is_repeatable(&filename) {
+        return true;
+    }
+
+    let snake_case = Regex::new(r"^[0-9]+_[a-z0-9_]+\.[^.]+$").expect("Failed to build the regexp");
+
+    pattern.is_migration(&filename) && (pattern.has_migration_regex() || snake_case.is_match(&filename))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::VecSource;
+
+    use super::{lint, lint_file, LintConfig, LintRule, LintSeverity};
+
+    fn rules(filename: &str, content: &str, config: &LintConfig) -> Vec<(LintRule, Option<usize>)> {
+        lint_file(filename, content, config)
+            .into_iter()
+            .map(|issue| (issue.rule, issue.line))
+            .collect()
+    }
+
+    #[test]
+    fn it_lints_migration_files() {
+        let config = LintConfig::new();
+
+        assert_eq!(rules("001_init.surql", "DEFINE TABLE users;\nREMOVE TABLE IF EXISTS posts;", &config), vec![]);
+        assert_eq!(rules("002_empty.surql", "-- Nothing yet.\n", &config), vec![(LintRule::EmptyFile, None)]);
+        assert_eq!(
+            rules("3-Users.surql", "DEFINE TABLE users;\nREMOVE TABLE posts", &config),
+            vec![
+                (LintRule::Naming, None),
+                (LintRule::MissingSemicolon, Some(2)),
+                (LintRule::UnguardedRemoveTable, Some(2)),
+            ]
+        );
+
+        let config = LintConfig::new()
+            .rule(LintRule::NonIdempotentDefine, LintSeverity::Error)
+            .rule(LintRule::UnguardedRemoveTable, LintSeverity::Off);
+        assert_eq!(
+            rules("004_users.surql", "DEFINE TABLE users;\nDEFINE FIELD IF NOT EXISTS name ON users;\nREMOVE TABLE posts;", &config),
+            vec![(LintRule::NonIdempotentDefine, Some(1))]
+        );
+    }
+
+    #[test]
+    fn it_reports_the_statements_not_parsing() {
+        let config = LintConfig::new();
+
+        assert_eq!(
+            rules("001_users.surql", "DEFINE TABLE users;\nDEFINE FIELD name ON users TYPE;\nDEFINE TABLE ${TABLE};", &config),
+            vec![(LintRule::InvalidStatement, Some(2))]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_checks_the_files_together() {
+        let source = VecSource::new([
+            ("001_users.surql", "DEFINE TABLE users;\n-- +down\nREMOVE TABLE users;\n"),
+            ("002_posts.surql", "-- ssm:requires 004_tags\nDEFINE TABLE posts;"),
+            ("002_comments.surql", "DEFINE TABLE comments;"),
+            ("002_comments.down.surql", "REMOVE TABLE comments;"),
+            ("003_likes.surql", "DEFINE TABLE likes;"),
+        ]);
+        let config = LintConfig::new().rule(LintRule::MissingDownFile, LintSeverity::Error);
+
+        let issues: Vec<(String, LintRule)> = lint(&source, &config)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|issue| (issue.filename, issue.rule))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                ("002_posts.surql".to_string(), LintRule::DuplicateVersion),
+                ("002_posts.surql".to_string(), LintRule::MissingDownFile),
+                ("003_likes.surql".to_string(), LintRule::MissingDownFile),
+            ]
+        );
+
+        let source = VecSource::new([("001_users.surql", "-- ssm:requires 004_tags\nDEFINE TABLE users;")]);
+        let issues = lint(&source, &LintConfig::new()).await.unwrap();
+        assert_eq!(issues[0].rule, LintRule::UnresolvedRequirement);
+    }
+
+    #[cfg(feature = "kv-mem")]
+    #[tokio::test]
+    async fn it_replays_the_files_in_deep_mode() {
+        let source = VecSource::new([
+            ("001_users.surql", "DEFINE TABLE users SCHEMAFULL;\nDEFINE FIELD age ON users TYPE int;"),
+            ("002_admin.surql", "CREATE users:admin SET age = fn::age();"),
+            ("003_posts.surql", "DEFINE TABLE posts;"),
+        ]);
+
+        assert!(lint(&source, &LintConfig::new()).await.unwrap().is_empty());
+
+        let issues = lint(&source, &LintConfig::new().deep(true)).await.unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].filename.as_str(), issues[0].rule), ("002_admin.surql", LintRule::ReplayError));
+    }
+}
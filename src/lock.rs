@@ -0,0 +1,149 @@
+use std::{process, time::Duration};
+
+use chrono::prelude::*;
+use serde::Deserialize;
+use surrealdb::{Connection, Surreal};
+use tokio::{task::JoinHandle, time::{sleep, Instant}};
+use tracing::{debug, error, info};
+
+use crate::Error;
+
+/// How long to wait for a lock held by another migrator before giving up.
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a lock stays valid without being renewed by its owner.
+const LEASE_SECONDS: u64 = 30;
+
+/// How often the owner of a lock renews its lease.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often to retry acquiring a lock held by another migrator.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize, Debug)]
+struct LockRecord {
+    owner: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// An advisory lock stored in the `<migrations table>_lock` table (e.g. `migrations_lock`),
+/// preventing concurrent migration runs.
+///
+/// The lock is a lease renewed in the background while held: if its owner dies, the lock
+/// expires by itself after `LEASE_SECONDS`.
+pub struct MigrationLock<C: Connection> {
+    db: Surreal<C>,
+    lock_table: String,
+    owner: String,
+    heartbeat: JoinHandle<()>,
+}
+
+impl<C: Connection> MigrationLock<C> {
+    /// Acquires the lock, waiting up to `timeout` for another migrator to release it.
+    pub async fn acquire(db: &Surreal<C>, table: &str, timeout: Duration) -> Result<Self, Error> {
+        let lock_table = lock_table(table);
+        let owner = format!("{}-{}", process::id(), Utc::now().timestamp_millis());
+        let sql = format!(
+            r#"
+                BEGIN TRANSACTION;
+                DELETE {lock_table}:lock WHERE expires_at < time::now();
+                CREATE {lock_table}:lock SET owner=$owner, acquired_at=time::now(), expires_at=time::now() + {lease}s;
+                COMMIT TRANSACTION;
+            "#,
+            lock_table = lock_table,
+            lease = LEASE_SECONDS
+        );
+        let started_at = Instant::now();
+
+        loop {
+            let result = db
+                .query(sql.as_str())
+                .bind(("owner", owner.clone()))
+                .await?
+                .check();
+
+            let err = match result {
+                Ok(_) => break,
+                Err(err) => err,
+            };
+
+            // The creation failed: either the lock is held by another migrator, or it is an actual error.
+            let lock: Option<LockRecord> = db.select((lock_table.as_str(), "lock")).await?;
+            let Some(lock) = lock else {
+                return Err(err.into());
+            };
+
+            if started_at.elapsed() >= timeout {
+                error!(owner = %lock.owner, expires_at = %lock.expires_at, "The migrations are locked by another migrator");
+                return Err(
+                    Error::LockHeld {
+                        owner: lock.owner,
+                        expires_at: lock.expires_at,
+                    }
+                );
+            }
+
+            info!(owner = %lock.owner, "Waiting for the migrations lock to be released");
+            sleep(RETRY_INTERVAL).await;
+        }
+
+        debug!(lock_table = %lock_table, owner = %owner, "Migrations lock acquired");
+
+        let heartbeat = tokio::spawn({
+            let db = db.clone();
+            let owner = owner.clone();
+            let sql = format!(
+                "UPDATE {}:lock SET expires_at=time::now() + {}s WHERE owner=$owner;",
+                lock_table,
+                LEASE_SECONDS
+            );
+
+            async move {
+                loop {
+                    sleep(HEARTBEAT_INTERVAL).await;
+                    let _ = db
+                        .query(sql.as_str())
+                        .bind(("owner", owner.clone()))
+                        .await;
+                }
+            }
+        });
+
+        Ok(MigrationLock { db: db.clone(), lock_table, owner, heartbeat })
+    }
+
+    /// Removes the lock whoever holds it (e.g. a lock left by a crashed migrator).
+    pub async fn force_unlock(db: &Surreal<C>, table: &str) -> Result<(), Error> {
+        let _ = db
+            .query(format!("DELETE {}:lock;", lock_table(table)))
+            .await?
+            .check()?;
+
+        Ok(())
+    }
+
+    /// Releases the lock, if it is still held by this migrator.
+    pub async fn release(self) -> Result<(), Error> {
+        self.heartbeat.abort();
+
+        let _ = self.db
+            .query(format!("DELETE {}:lock WHERE owner=$owner;", self.lock_table))
+            .bind(("owner", self.owner.clone()))
+            .await?
+            .check()?;
+
+        Ok(())
+    }
+}
+
+impl<C: Connection> Drop for MigrationLock<C> {
+    fn drop(&mut self) {
+        // Stop renewing the lease, so an unreleased lock expires by itself.
+        self.heartbeat.abort();
+    }
+}
+
+/// Returns the name of the table holding the lock of a migrations table.
+pub(crate) fn lock_table(table: &str) -> String {
+    format!("{}_lock", table)
+}
@@ -1,9 +1,29 @@
-use std::env;
+use std::{collections::{BTreeMap, HashMap}, env, fs, path::{Path, PathBuf}, process::ExitCode, time::Duration};
 
-use surrealdb::{engine::remote::ws::Ws, Surreal};
-use surrealdb_simple_migration::migrate;
+use surrealdb::{engine::any::Any, Surreal};
+use surrealdb_simple_migration::{
+    lint, new_migration_with_template, ApplyTarget, ChecksumAlgorithm, Error, FilePattern, FsSource, LintConfig, LintIssue,
+    LintRule, LintSeverity, MigrationEvent, MigrationLedger, MigrationPlan, MigrationTemplate, MigrationState, Migrator, MultiSource, NamingScheme, NewMigration, PolicyAction, RetryPolicy,
+    RollbackTarget, SquashReport, DEFAULT_ASSERTIONS_DIR, DEFAULT_LOCK_TIMEOUT, DEFAULT_RETRY_DELAY, DEFAULT_TABLE, DEFAULT_TEMPLATES_DIR, NAMESPACE_DATABASE, NAMESPACE_DIR,
+};
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use tokio_util::sync::CancellationToken;
+use futures::{stream, StreamExt};
+use tracing::{info, info_span, Instrument, Level};
+
+mod cli;
+
+use cli::{
+    auth::{AuthLevel, Credentials},
+    config::Config,
+    doctor::DoctorOptions,
+    endpoint::{Endpoint, TlsOptions},
+    exit,
+    hooks::ShellHooks,
+    output::{self, Marker, OutputFormat, Verbosity},
+};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -15,21 +35,59 @@ struct Cli {
     #[command(subcommand)]
     command: Commands,
 
-    /// The host of the SurrealDB instance. (default: "http://localhost:8000")
+    /// The host of the SurrealDB instance, whose scheme selects the engine: "ws://", "wss://", "http://",
+    /// "https://", "memory" or "rocksdb://<path>", WebSocket without scheme. (default: "0.0.0.0:8000")
     #[arg(short = 'H', long, global = true)]
     host: Option<String>,
 
-    /// The path for the migration files. (default: "./")
+    /// The PEM file of the certificate authorities to trust, in addition to the public ones, for a "wss://" or
+    /// "https://" host (e.g. an instance with a certificate of a private authority).
+    #[arg(long, global = true)]
+    ca_cert: Option<PathBuf>,
+
+    /// The PEM file of the client certificate and its private key, for a "wss://" or "https://" host requiring
+    /// mutual TLS.
+    #[arg(long, global = true)]
+    client_cert: Option<PathBuf>,
+
+    /// Accept any certificate of a "wss://" or "https://" host. Only to debug a connection, never in production.
+    #[arg(long, global = true)]
+    insecure_skip_verify: bool,
+
+    /// The path for the migration files, repeated to merge the migration files of several directories into a
+    /// single plan (e.g. "-p auth/migrations -p billing/migrations"), the new files being created in the first
+    /// one. (default: "./")
     #[arg(short, long, global = true)]
-    path: Option<String>,
+    path: Vec<String>,
+
+    /// Leave the migration files matching a gitignore-style pattern out of the migrations (e.g. "*_wip.surql"),
+    /// in addition to the ones listed in the `.ssmignore` file of the migration directory. Can be repeated.
+    #[arg(long, global = true)]
+    skip: Vec<String>,
+
+    /// Also read the migration files of the subdirectories of the path (e.g. "2024/", "modules/auth/"), ordered
+    /// by their number prefix whatever their directory.
+    #[arg(long, global = true)]
+    recursive: bool,
 
     /// The namespace used on the surrealdb instance. (default: "default")
     #[arg(short, long, global = true)]
     namespace: Option<String>,
 
-    /// The database used on the surrealdb instance. (default: "dev")
-    #[arg(short, long, global = true)]
-    database: Option<String>,
+    /// The database used on the surrealdb instance, or a comma-separated list of databases to run the command on
+    /// one after the other (e.g. "tenant_a,tenant_b"). (default: "dev")
+    #[arg(short, long, global = true, value_delimiter = ',')]
+    database: Vec<String>,
+
+    /// Run `apply` and `up` on up to N of the databases concurrently, each one on its own connection, their output
+    /// lines starting with `[<namespace>/<database>]`. (default: 1, one after the other)
+    #[arg(long, value_name = "N", global = true)]
+    parallel: Option<usize>,
+
+    /// The table tracking the applied migrations, e.g. "_ssm_migrations" when another application or migration
+    /// tool already uses the "migrations" table of the database. (default: "migrations")
+    #[arg(long, global = true)]
+    table_name: Option<String>,
 
     /// The username for the user used on the surrealdb instance.
     #[arg(short = 'U', long, global = true)]
@@ -38,106 +96,1863 @@ struct Cli {
     /// The password for the user used on the surrealdb instance.
     #[arg(short = 'P', long, global = true)]
     password: Option<String>,
+
+    /// A command printing the password of the user, run when the password is not given otherwise (e.g. "op read
+    /// op://vault/surrealdb/password", or a lookup in the OS keychain), so it is not kept in the environment or
+    /// the shell history.
+    #[arg(long, global = true, conflicts_with = "password")]
+    password_command: Option<String>,
+
+    /// A token (JWT) to authenticate with, instead of a username and a password.
+    #[arg(long, global = true, conflicts_with_all = ["username", "password", "password_command", "access"])]
+    token: Option<String>,
+
+    /// Sign in as a record user with this access method of the database (a scope, before SurrealDB 2), for the
+    /// deployments only exposing those. `--auth-level` is then not used.
+    #[arg(long, visible_alias = "scope", global = true)]
+    access: Option<String>,
+
+    /// A parameter of the record access method (e.g. "email=admin@example.com"), repeatable. The username and the
+    /// password, when given, are the `username` and `password` parameters.
+    #[arg(long, visible_alias = "auth-params", value_name = "KEY=VALUE", value_parser = cli::auth::parse_auth_param, global = true)]
+    auth_param: Vec<(String, String)>,
+
+    /// The algorithm of the recorded checksums of the migration files, "sha256" or "blake3" (with the `blake3`
+    /// feature). The records of another algorithm are verified with it, then upgraded. (default: "sha256")
+    #[arg(long, global = true)]
+    checksum_algorithm: Option<ChecksumAlgorithm>,
+
+    /// The level of the user used on the surrealdb instance. (default: "root")
+    #[arg(long, value_enum, global = true)]
+    auth_level: Option<AuthLevel>,
+
+    /// The configuration file. (default: "ssm.toml", when it exists)
+    #[arg(short, long, global = true)]
+    config: Option<String>,
+
+    /// The file to read the `SSM_*` environment variables from. (default: ".env", when it exists)
+    #[arg(long, global = true)]
+    env_file: Option<String>,
+
+    /// The profile of the configuration file to use (e.g. "staging" for `[profiles.staging]`).
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// The environment the migrations are applied to, the migration files restricted to other environments with
+    /// a `-- ssm:env` directive being recorded as skipped. (default: the profile, if any)
+    #[arg(long, global = true)]
+    environment: Option<String>,
+
+    /// How many seconds to wait for a concurrent migration run to release the migrations lock. (default: 60)
+    #[arg(long, global = true)]
+    lock_timeout: Option<u64>,
+
+    /// How many seconds applying the pending migrations may take before failing with the exit code 10.
+    #[arg(long, global = true)]
+    timeout: Option<u64>,
+
+    /// How many seconds applying a single migration file may take before failing with the exit code 10 (e.g. a
+    /// stalled `DEFINE INDEX` or data backfill).
+    #[arg(long, global = true)]
+    migration_timeout: Option<u64>,
+
+    /// How many times the connection, the sign in and the queries failing with a transient error (e.g. a dropped
+    /// connection) are retried, with an exponential backoff. (default: 0)
+    #[arg(long, global = true)]
+    retries: Option<u32>,
+
+    /// How many milliseconds to wait before the first retry, doubled for each of the next ones. (default: 500)
+    #[arg(long, global = true)]
+    retry_delay: Option<u64>,
+
+    /// The value of a `${KEY}` placeholder of the migration files, as `KEY=VALUE` (can be repeated). Takes
+    /// precedence over the environment variables and the `[vars]` of the configuration file.
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var, global = true)]
+    vars: Vec<(String, String)>,
+
+    /// Apply new migration files with a lower version than the last applied one instead of failing,
+    /// recording them as applied out of order.
+    #[arg(long, global = true)]
+    allow_out_of_order: bool,
+
+    /// Define the namespace and the database when they do not exist before applying the migrations (e.g. on a
+    /// fresh instance started in strict mode), instead of failing.
+    #[arg(long, global = true)]
+    create_ns_db: bool,
+
+    /// Run the command on the namespace migrations instead, the files of the `namespace/` subdirectory of the
+    /// migration directories (e.g. `DEFINE DATABASE`, `DEFINE USER ... ON NAMESPACE`, `DEFINE ACCESS ... ON
+    /// NAMESPACE`), once per namespace of the targets. They are tracked in the `namespace_<table>` table of the
+    /// `ssm_namespace` database of the namespace, defined when missing, so sign in as a root or namespace user.
+    #[arg(long, global = true)]
+    namespace_scope: bool,
+
+    /// Skip the check that the user can define tables, and create and delete records, made in the scratch
+    /// `<table>_preflight` table before applying the first pending migration.
+    #[arg(long, global = true)]
+    no_preflight: bool,
+
+    /// Rewrite the `DEFINE TABLE`, `DEFINE FIELD` and `DEFINE INDEX` statements without `IF NOT EXISTS` or
+    /// `OVERWRITE` into `DEFINE ... IF NOT EXISTS` when applying the migrations, e.g. on a database where a previous
+    /// attempt was partially applied.
+    #[arg(long, global = true)]
+    idempotent: bool,
+
+    /// Continue a migration file opting out of transactions (`-- ssm:no-transaction`) or streamed from the statement
+    /// that failed in the previous run, instead of executing it again from the start, when the statements executed
+    /// before the failure have not changed since.
+    #[arg(long, global = true)]
+    resume: bool,
+
+    /// Also store the exact SQL executed for each migration file in the `<table>_audit` table (e.g.
+    /// "migrations_audit"), keeping a record of what ran even if the files are rewritten afterwards.
+    #[arg(long, global = true)]
+    audit: bool,
+
+    /// Skip the confirmation of the destructive commands (`reset`, `rollback`, `down`, `redo`, `squash`, `clean`,
+    /// `ledger import`, `test`), required in non-interactive environments. Also makes `prune` remove the records it
+    /// lists.
+    #[arg(short, long, global = true)]
+    yes: bool,
+
+    /// Export the database to a timestamped `.surql` file of this directory before the destructive commands
+    /// (`reset`, `rollback`, `down`, `redo`), so it can be restored with `surreal import`. The command is not run
+    /// when the export fails.
+    #[arg(long, global = true)]
+    backup_dir: Option<PathBuf>,
+
+    /// Disable the colors of the output and the syntax highlighting of the SQL (also disabled by the `NO_COLOR`
+    /// environment variable, or when the output is not a terminal).
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// How the failures and warnings are printed: "github" prints them as GitHub Actions workflow commands,
+    /// annotating the migration files on the pull requests (e.g. `verify` or `lint` in CI). (default: "text")
+    #[arg(long, global = true, value_enum)]
+    output: Option<OutputFormat>,
+
+    /// Print debug logs, including the SQL executed for each migration file.
+    #[arg(short, long, global = true, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Only print the errors.
+    #[arg(short, long, global = true)]
+    quiet: bool,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Apply all migrations.
-    Apply,
+    Apply {
+        /// Print the migrations that would be applied and their SQL, without executing them.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Print the SQL of `--dry-run` directly instead of through the `PAGER` (default: "less -FRX").
+        #[arg(long)]
+        no_pager: bool,
+
+        /// Only apply the pending migrations up to this version included (e.g. "004").
+        #[arg(long, conflicts_with = "count")]
+        to: Option<String>,
+
+        /// Only apply the next N pending migrations.
+        #[arg(long)]
+        count: Option<usize>,
+
+        /// Apply every pending migration in a single transaction, so either all of them are applied or none is.
+        #[arg(long, conflicts_with = "continue_on_error")]
+        single_transaction: bool,
+
+        /// Attempt every pending migration even when some fail, then report the failed ones, instead of stopping
+        /// at the first failure.
+        #[arg(long)]
+        continue_on_error: bool,
+
+        /// Apply up to N consecutive pending migrations in a single query, instead of a round trip per migration.
+        #[arg(long, value_name = "N", conflicts_with = "single_transaction")]
+        batch_size: Option<usize>,
+
+        /// Refuse to apply anything unless the pending migrations and their checksums are exactly the ones of a
+        /// plan written by `plan --output-file`.
+        #[arg(long, value_name = "FILE", conflicts_with = "dry_run")]
+        plan: Option<String>,
 
-    /// Remove all migrations from migrations table and delete the database in order to remove the effect of the migrations.
-    Reset,
+        /// Record the pending migrations (up to `--to`, or the next `--count`) as applied with their checksum,
+        /// without executing them, e.g. on a database migrated by hand or restored from a snapshot of another
+        /// environment.
+        #[arg(long, conflicts_with_all = ["dry_run", "plan", "single_transaction", "continue_on_error", "batch_size"])]
+        fake: bool,
+
+        /// First replay the whole migration history into this scratch database of the namespace, defined for the
+        /// run and removed afterwards, and apply nothing unless it succeeds, to catch the migrations only applying
+        /// to the existing database. The database must not exist.
+        #[arg(long, value_name = "DATABASE", conflicts_with_all = ["dry_run", "fake"])]
+        shadow: Option<String>,
+
+        /// Only apply the pending migrations tagged with one of these comma-separated tags (`-- ssm:tags auth`),
+        /// e.g. the ones of a single module. The others stay pending.
+        #[arg(long, value_delimiter = ',', conflicts_with = "fake")]
+        tags: Vec<String>,
+    },
+
+    /// List the pending migrations and their checksums, without applying them.
+    Plan {
+        /// Also write the plan as JSON to this file, to be reviewed then applied with `apply --plan <file>`.
+        #[arg(long, value_name = "FILE")]
+        output_file: Option<String>,
+    },
+
+    /// Create the next numbered migration file in the migration directory.
+    New {
+        /// The name of the migration (e.g. "create_users_table").
+        name: String,
+
+        /// Also create the paired `.down.surql` file.
+        #[arg(long)]
+        down: bool,
+
+        /// Prefix the file with the current UTC date and time (e.g. "20240521143000") instead of the next number.
+        #[arg(long)]
+        timestamp: bool,
+
+        /// Fill the file with the statements bringing the database in line with the migrations (see `diff`), and
+        /// its `.down.surql` file, with `--down`, with the statements reverting them. To review before applying.
+        #[arg(long)]
+        from_diff: bool,
+
+        /// The directory of the `migration.surql.tmpl` and `migration.down.surql.tmpl` templates of the new files,
+        /// with `{{name}}`, `{{filename}}`, `{{version}}`, `{{date}}`, `{{author}}` and `{{body}}` placeholders.
+        /// The missing templates are the default ones. (default: "./templates")
+        #[arg(long)]
+        templates_dir: Option<String>,
+    },
+
+    /// Apply exactly the next N pending migrations, like `apply --count N`.
+    Up {
+        /// The number of migrations to apply.
+        #[arg(default_value_t = 1)]
+        count: usize,
+    },
+
+    /// Roll back exactly the last N applied migrations, like `rollback --steps N`.
+    Down {
+        /// The number of migrations to roll back.
+        #[arg(default_value_t = 1)]
+        count: usize,
+    },
+
+    /// Roll back the last N applied migrations, then apply them again, e.g. while iterating on the newest
+    /// migration in development.
+    Redo {
+        /// The number of migrations to redo.
+        #[arg(long, default_value_t = 1)]
+        last: usize,
+    },
+
+    /// Roll back applied migrations using their paired `.down.surql` files. (default: the last migration)
+    Rollback {
+        /// The number of migrations to roll back.
+        #[arg(long, conflicts_with = "to")]
+        steps: Option<usize>,
+
+        /// Roll back every migration applied after this version (e.g. "004").
+        #[arg(long)]
+        to: Option<String>,
+    },
+
+    /// Show which migrations are applied, pending, changed or missing.
+    Status {
+        /// Only show the migrations tagged with one of these comma-separated tags (`-- ssm:tags auth`).
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+    },
+
+    /// List the applied migrations in the order they were applied, with when, how long, by whom and with which
+    /// checksum, followed by the pending ones.
+    #[command(alias = "list")]
+    History,
+
+    /// Check that the applied migrations are unchanged and still present, and that the pending ones are
+    /// correctly ordered, without applying anything.
+    ///
+    /// Exits with 2 when a migration changed, 3 when a migration is missing and 5 when a pending
+    /// migration is out of order.
+    Verify,
+
+    /// Compare the schema resulting from the migration files (replayed into an in-memory database) with the
+    /// schema of the database, listing the tables, fields, indexes, events, functions, params and analyzers
+    /// missing, unexpected or defined differently.
+    ///
+    /// Exits with 8 when the schemas differ.
+    Diff,
+
+    /// Statically check the migration files, without connecting to the database: empty files, missing trailing
+    /// semicolons, `REMOVE TABLE` without `IF EXISTS`, `DEFINE` without `IF NOT EXISTS` or `OVERWRITE` and
+    /// filenames not following the naming convention. The severity of each rule is set in the `[lint]` table of
+    /// the configuration file.
+    ///
+    /// Exits with 9 when an issue has the `error` severity.
+    Lint {
+        /// Also replay the migration files into an in-memory database, reporting the first one failing (e.g. an
+        /// unknown function or a value of the wrong type).
+        #[arg(long)]
+        deep: bool,
+    },
+
+    /// Validate the migration files without connecting to the database, e.g. in CI or a pre-commit hook: their
+    /// naming, the versions shared by several files, the `-- ssm:requires` directives, the statements not parsing
+    /// and, when required, the missing down files. The rules are errors unless the `[lint]` table of the
+    /// configuration file sets their severity.
+    ///
+    /// Exits with 9 when an issue has the `error` severity.
+    Check {
+        /// Require a `.down.surql` file or a `-- +down` section for each numbered migration file.
+        #[arg(long)]
+        require_down: bool,
+
+        /// Also replay the migration files into an in-memory database, reporting the first one failing.
+        #[arg(long)]
+        deep: bool,
+    },
+
+    /// Diagnose the setup: the naming of the migration files, the connection, the authentication, then for each
+    /// database its existence, the permission to write (with a temporary record) and the consistency of the
+    /// migrations table, printing how to fix each failing check.
+    ///
+    /// Exits with 1 when a check fails.
+    Doctor,
+
+    /// Record the migrations up to a version as applied without executing them, to adopt the migrations on a
+    /// database that already has their schema.
+    Baseline {
+        /// The last version to record as applied (e.g. "004").
+        #[arg(long)]
+        to: String,
+    },
+
+    /// Collapse the applied migrations up to a version into a single baseline file (e.g. "004_squashed.surql"),
+    /// recorded as applied in their place, and move the original files to the `.archive` subdirectory of the
+    /// migration directory.
+    Squash {
+        /// The last version to squash (e.g. "004").
+        #[arg(long)]
+        to: String,
+
+        /// Print the files that would be squashed, without squashing them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Reconcile the migrations table with the migration files: update the stored checksums to match the current
+    /// files, remove the records of missing files (e.g. rolled back manually) and the duplicated records.
+    Repair {
+        /// Print the changes that would be made, without making them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// List the records of the migrations whose file no longer exists, and remove them with `--yes`, e.g. after
+    /// deleting a migration file on purpose.
+    Prune,
+
+    /// Apply the new or changed seed files, after the migrations: the files of the seeds directory, then the
+    /// ones of its `<env>` subdirectory.
+    Seed {
+        /// The environment whose seed files are also applied (e.g. "dev" for `seeds/dev/*.surql`).
+        #[arg(long)]
+        env: Option<String>,
+
+        /// The directory of the seed files. (default: "./seeds")
+        #[arg(long)]
+        dir: Option<String>,
+    },
+
+    /// Apply the pending migrations, then again whenever a migration file is saved in the migration directory,
+    /// until interrupted with Ctrl-C. For local development.
+    Watch,
+
+    /// Print the completion script of a shell, completing the profile names of the configuration file, e.g.
+    /// `ssm completions bash > /etc/bash_completion.d/ssm`.
+    Completions {
+        /// The shell to complete the commands of.
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+
+    /// Remove the migrations lock left by a migration run that did not release it (e.g. a crashed process).
+    ForceUnlock,
+
+    /// Export or import the records of the migrations table, e.g. to promote a snapshot of a database to another
+    /// environment along with its migration state.
+    Ledger {
+        #[command(subcommand)]
+        command: LedgerCommands,
+    },
+
+    /// Remove the tables, fields, indexes, events, functions, params and analyzers defined by the applied
+    /// migrations, and the migrations table.
+    Reset {
+        /// Remove the whole database instead, including the data unrelated to the migrations.
+        #[arg(long)]
+        force_drop_database: bool,
+    },
+
+    /// Remove every table (with its records), function, param and analyzer of the database, except the migrations
+    /// table and the tables tracking it, e.g. to bring a local database back to empty without removing it. The
+    /// applied migrations stay recorded: use `reset` to apply them again.
+    Clean,
+
+    /// Rehearse the pending migrations, e.g. on a staging clone of the production database: export the database,
+    /// apply the pending migrations, run the assertion files (`tests/NNN_assert.surql`, each passing when its last
+    /// statement returns true), then restore the export, whatever the outcome.
+    Test {
+        /// The directory of the assertion files. (default: "./tests")
+        #[arg(long)]
+        dir: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum LedgerCommands {
+    /// Write the records of the migrations table as JSON, in the order they were applied.
+    Export {
+        /// Write the ledger to this file instead of the standard output.
+        #[arg(long, value_name = "FILE")]
+        output_file: Option<String>,
+    },
+
+    /// Replace the records of the migrations table with the ones of a ledger written by `ledger export`, without
+    /// executing any migration file.
+    Import {
+        /// The ledger written by `ledger export`.
+        file: String,
+    },
 }
 
 #[tokio::main]
-async fn main() {
-    let args = Cli::parse();
+async fn main() -> ExitCode {
+    let args = match Cli::try_parse() {
+        Ok(args) => args,
+        Err(e) => {
+            // The help and the version are not failures.
+            let _ = e.print();
+            return if e.use_stderr() { ExitCode::from(exit::FAILURE) } else { ExitCode::SUCCESS };
+        },
+    };
+
+    if let Commands::Completions { shell } = args.command {
+        print_completions(shell, args.config.as_deref());
+        return ExitCode::SUCCESS;
+    }
 
-    println!("{:#?}", args);
+    let verbosity = if args.verbose {
+        Verbosity::Verbose
+    } else if args.quiet {
+        Verbosity::Quiet
+    } else {
+        Verbosity::Normal
+    };
+    output::init(verbosity, args.no_color);
+
+    let level = match verbosity {
+        Verbosity::Verbose => Level::DEBUG,
+        Verbosity::Quiet => Level::ERROR,
+        Verbosity::Normal => Level::INFO,
+    };
+
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false)
+        .init();
+
+    if let Err(e) = cli::dotenv::load(args.env_file.as_deref()) {
+        output::error(e);
+        return ExitCode::from(exit::FAILURE);
+    }
+
+    // The options are read from the CLI, then the environment (and `.env` file), then the configuration file.
+    let settings = match Config::load(args.config.as_deref()).and_then(|config| config.settings(args.profile.as_deref())) {
+        Ok(settings) => settings,
+        Err(e) => {
+            output::error(e);
+            return ExitCode::from(exit::FAILURE);
+        },
+    };
 
     let host = args
         .host
-        .unwrap_or_else(
-            || env::var("SSM_HOST")
-                .unwrap_or_else(|_| "0.0.0.0:8000".to_string())
-        );
-
-    let path = args
-        .path
-        .unwrap_or_else(
-            || env::var("SSM_PATH")
-                .unwrap_or_else(|_| "./".to_string())
-        );
+        .or_else(|| env::var("SSM_HOST").ok())
+        .or(settings.host)
+        .unwrap_or_else(|| "0.0.0.0:8000".to_string());
+
+    let paths: Vec<String> = if args.path.is_empty() {
+        env::var("SSM_PATH")
+            .map(|paths| paths.split(',').map(|path| path.trim().to_string()).collect())
+            .ok()
+            .or(settings.path.map(|path| vec![path]))
+            .unwrap_or_else(|| vec!["./".to_string()])
+    } else {
+        args.path
+    };
+
+    let paths: Vec<String> = if args.namespace_scope {
+        paths
+            .iter()
+            .map(|path| Path::new(path).join(NAMESPACE_DIR).to_string_lossy().into_owned())
+            .collect()
+    } else {
+        paths
+    };
+
+    output::init_format(args.output.or(settings.output).unwrap_or_default(), paths.iter().map(PathBuf::from).collect());
 
     let namespace = args
         .namespace
-        .unwrap_or_else(
-            || env::var("SSM_NAMESPACE")
-                .unwrap_or_else(|_| "default".to_string())
-        );
+        .or_else(|| env::var("SSM_NAMESPACE").ok())
+        .or(settings.namespace)
+        .unwrap_or_else(|| "default".to_string());
 
-    let database = args
-        .database
-        .unwrap_or_else(
-            || env::var("SSM_DATABASE")
-                .unwrap_or_else(|_| "dev".to_string())
-        );
+    // The databases of the CLI (or the environment) take precedence over the `[[targets]]` of the configuration file.
+    let databases: Vec<String> = if args.database.is_empty() {
+        env::var("SSM_DATABASE")
+            .map(|databases| databases.split(',').map(|database| database.trim().to_string()).collect())
+            .unwrap_or_default()
+    } else {
+        args.database
+    };
 
-    println!("Using:\n Host: {}\n Path: {} \n Namespace: {} \n Database: {}", host, path, namespace, database);
+    let targets: Vec<(String, String)> = if !databases.is_empty() {
+        databases
+            .into_iter()
+            .map(|database| (namespace.clone(), database))
+            .collect()
+    } else if !settings.targets.is_empty() {
+        settings
+            .targets
+            .into_iter()
+            .map(|target| (target.namespace.unwrap_or_else(|| namespace.clone()), target.database))
+            .collect()
+    } else {
+        vec![(namespace.clone(), settings.database.unwrap_or_else(|| "dev".to_string()))]
+    };
 
+    // The namespace migrations are applied once per namespace, in its namespace database.
+    let targets: Vec<(String, String)> = if args.namespace_scope {
+        targets.into_iter().fold(vec![], |mut namespaces, (namespace, _)| {
+            if !namespaces.iter().any(|(known, _)| *known == namespace) {
+                namespaces.push((namespace, NAMESPACE_DATABASE.to_string()));
+            }
+            namespaces
+        })
+    } else {
+        targets
+    };
+
+    let auth_level = args
+        .auth_level
+        .map(Ok)
+        .or_else(|| env::var("SSM_AUTH_LEVEL").ok().map(|level| AuthLevel::from_str(&level, true)));
+
+    let auth_level = match auth_level {
+        Some(Ok(level)) => level,
+        Some(Err(e)) => {
+            output::error(format!("Invalid SSM_AUTH_LEVEL: {}", e));
+            return ExitCode::from(exit::FAILURE);
+        },
+        None => settings.auth_level.unwrap_or_default(),
+    };
+
+    let table = args
+        .table_name
+        .or_else(|| env::var("SSM_TABLE_NAME").ok())
+        .or(settings.table)
+        .unwrap_or_else(|| DEFAULT_TABLE.to_string());
+    let table = if args.namespace_scope { format!("namespace_{}", table) } else { table };
+
+    let checksum_algorithm = args
+        .checksum_algorithm
+        .map(Ok)
+        .or_else(|| env::var("SSM_CHECKSUM_ALGORITHM").ok().map(|algorithm| algorithm.parse::<ChecksumAlgorithm>()));
+
+    let checksum_algorithm = match checksum_algorithm {
+        Some(Ok(algorithm)) => algorithm,
+        Some(Err(e)) => {
+            output::error(format!("Invalid SSM_CHECKSUM_ALGORITHM: {}", e));
+            return ExitCode::from(exit::FAILURE);
+        },
+        None => settings.checksum_algorithm.unwrap_or_default(),
+    };
+
+    // `--allow-out-of-order` takes precedence over the `[policy]` of the configuration file.
+    let mut policy = settings.policy.unwrap_or_default();
+    if args.allow_out_of_order {
+        policy.out_of_order = PolicyAction::Warn;
+    }
+
+    let environment = args
+        .environment
+        .clone()
+        .or_else(|| env::var("SSM_ENVIRONMENT").ok())
+        .or_else(|| args.profile.clone());
+
+    // The variables of the CLI override the ones of the configuration file.
+    let mut vars = settings.vars;
+    vars.extend(args.vars);
+
+    let lock_timeout = args
+        .lock_timeout
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_LOCK_TIMEOUT);
+
+    let retry = RetryPolicy::new(
+        args.retries.unwrap_or(0),
+        args.retry_delay.map(Duration::from_millis).unwrap_or(DEFAULT_RETRY_DELAY)
+    );
+
+    let mut pattern = FilePattern::new().ignore(&args.skip);
+    if let Some(extensions) = settings.extensions {
+        pattern = pattern.extensions(extensions);
+    }
+    if let Some(migration_pattern) = settings.migration_pattern {
+        match Regex::new(&migration_pattern) {
+            Ok(regex) => pattern = pattern.migration_regex(regex),
+            Err(e) => {
+                output::error(format!("Invalid migration_pattern in the configuration file: {}", e));
+                return ExitCode::from(exit::FAILURE);
+            },
+        }
+    }
+
+    let recursive = args.recursive || settings.recursive.unwrap_or(false);
+    let source = MultiSource::new(paths.iter().map(|path| FsSource::new(path).recursive(recursive)));
+
+    let backup_dir = args.backup_dir.or(settings.backup_dir);
+    let hooks = settings.hooks;
+
+    let templates_dir = match args.command {
+        Commands::New { ref templates_dir, .. } => templates_dir.clone(),
+        _ => None,
+    }
+    .or(settings.templates_dir)
+    .unwrap_or_else(|| DEFAULT_TEMPLATES_DIR.to_string());
+
+    // Commands that do not need a database connection.
+    if let Commands::New { name, down, timestamp, from_diff: false, .. } = &args.command {
+        let scheme = if *timestamp { NamingScheme::Timestamp } else { NamingScheme::Sequential };
+        let result = match MigrationTemplate::load(&templates_dir).await {
+            Ok(template) => {
+                let template = if *down { template.down_body("") } else { template };
+                new_migration_with_template(Path::new(&paths[0]), &source, name, scheme, &template, &pattern).await
+            },
+            Err(e) => Err(e),
+        };
+        return print_created(result);
+    }
+
+    if let Commands::Lint { deep } = args.command {
+        let config = settings
+            .lint
+            .into_iter()
+            .fold(LintConfig::new().file_pattern(pattern.clone()), |config, (rule, severity)| config.rule(rule, severity))
+            .deep(deep)
+            .vars(vars);
+        return print_lint(lint(&source, &config).await);
+    }
+
+    if let Commands::Check { require_down, deep } = args.command {
+        let config = check_config(pattern.clone(), &settings.lint, require_down).deep(deep).vars(vars);
+        return print_lint(lint(&source, &config).await);
+    }
+
+    let single_database = matches!(
+        args.command,
+        Commands::New { from_diff: true, .. }
+            | Commands::Watch
+            | Commands::Squash { .. }
+            | Commands::Plan { output_file: Some(_) }
+            | Commands::Apply { plan: Some(_), .. }
+            | Commands::Ledger { .. }
+    );
+    if single_database && targets.len() > 1 {
+        output::error("This command can only be run on a single database.");
+        return ExitCode::from(exit::FAILURE);
+    }
+
+    info!(%host, paths = %paths.join(","), %table, "Using");
+
+    let token = args
+        .token
+        .or_else(|| env::var("SSM_TOKEN").ok());
+
+    let endpoint = match Endpoint::parse(&host) {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            output::error(e);
+            return ExitCode::from(exit::FAILURE);
+        },
+    };
+
+    let tls = TlsOptions {
+        ca_cert: args.ca_cert.or(settings.ca_cert),
+        client_cert: args.client_cert.or(settings.client_cert),
+        insecure_skip_verify: args.insecure_skip_verify || settings.insecure_skip_verify.unwrap_or(false),
+    };
+    let config = match tls.config(&endpoint) {
+        Ok(config) => config,
+        Err(e) => {
+            output::error(e);
+            return ExitCode::from(exit::FAILURE);
+        },
+    };
+
+    // The password of the CLI, or else its command, takes precedence over the environment, then the command of the
+    // configuration file.
     let username = args
         .username
-        .unwrap_or_else(|| env::var("SSM_USERNAME")
-            .expect("You must provide a username (using -U or --user or SSM_USERNAME env var) in order to modify the database.")
-        );
-
+        .or_else(|| env::var("SSM_USERNAME").ok())
+        .or(settings.username);
     let password = args
         .password
-        .unwrap_or_else(|| env::var("SSM_PASSWORD")
-            .expect("You must provide a password (using -P or --password or SSM_PASSWORD env var) in order to modify the database.")
-        );
-
-    let db = Surreal::new::<Ws>(host).await.unwrap();
-
-    db.signin(surrealdb::opt::auth::Root {
-        username: &username,
-        password: &password,
-    }).await.expect("Failed to sign in.");
-    
-    db
-        .use_ns(&namespace)
-        .use_db(&database)
-        .await
-        .expect(format!("Failed to use namespace {} with database {}.", namespace, database).as_str());
-    
-    match args.command {
-        Commands::Apply => {
-            let result = migrate(&db, path.as_str()).await;
-            match result {
-                Ok(_) => (),
-                Err(e) => eprintln!("Failed to apply migrations: {:?}", e),
-            }
-        },
-        Commands::Reset => {
+        .or_else(|| args.password_command.is_none().then(|| env::var("SSM_PASSWORD").ok()).flatten());
+    let password_command = args
+        .password_command
+        .or_else(|| env::var("SSM_PASSWORD_COMMAND").ok())
+        .or(settings.password_command);
+
+    let access = args
+        .access
+        .or_else(|| env::var("SSM_ACCESS").ok())
+        .or(settings.access);
+
+    // The parameters of the CLI take precedence over the environment, then the configuration file.
+    let mut auth_params: BTreeMap<String, String> = settings.auth_params.into_iter().collect();
+    if let Ok(params) = env::var("SSM_AUTH_PARAMS") {
+        for param in params.split(',').filter(|param| !param.trim().is_empty()) {
+            match cli::auth::parse_auth_param(param) {
+                Ok((key, value)) => auth_params.insert(key, value),
+                Err(e) => {
+                    output::error(format!("Invalid SSM_AUTH_PARAMS: {}", e));
+                    return ExitCode::from(exit::FAILURE);
+                },
+            };
+        }
+    }
+    auth_params.extend(args.auth_param);
+
+    // The embedded databases have no users to sign in as, and the password command only runs when needed.
+    let credentials = if endpoint.embedded {
+        Ok(None)
+    } else {
+        match (token, access) {
+            (Some(token), _) => Ok(Credentials::Token(token)),
+            (None, Some(access)) => {
+                let password = match (&username, password, password_command) {
+                    (_, Some(password), _) => Ok(Some(password)),
+                    (Some(_), None, Some(command)) => cli::auth::password_from_command(&command).map(Some),
+                    _ => Ok(None),
+                };
+
+                password.map(|password| {
+                    let mut params = auth_params;
+                    for (key, value) in [("username", username), ("password", password)] {
+                        if let Some(value) = value {
+                            params.entry(key.to_string()).or_insert(value);
+                        }
+                    }
+
+                    Credentials::Record { access, params }
+                })
+            },
+            (None, None) => match (username, password, password_command) {
+                (None, _, _) => Err(
+                    "You must provide a username (using -U or --user or SSM_USERNAME env var) or a token in order to modify the database."
+                        .to_string()
+                ),
+                (Some(username), Some(password), _) => Ok(Credentials::User { username, password }),
+                (Some(username), None, Some(command)) => {
+                    cli::auth::password_from_command(&command).map(|password| Credentials::User { username, password })
+                },
+                (Some(_), None, None) => Err(
+                    "You must provide a password (using -P or --password or SSM_PASSWORD env var, or a --password-command) or a token in order to modify the database."
+                        .to_string()
+                ),
+            },
+        }
+        .map(Some)
+    };
+
+    // The doctor reports the connection and authentication failures as failing checks.
+    if let Commands::Doctor = args.command {
+        return cli::doctor::doctor(DoctorOptions {
+            host: &host,
+            endpoint: &endpoint,
+            config,
+            credentials,
+            auth_level,
+            targets: &targets,
+            table: &table,
+            source: &source,
+            pattern: &pattern,
+        })
+        .await;
+    }
+
+    let db = match retry.run(|_| async { Ok(cli::endpoint::connect(&endpoint, config.clone()).await?) }).await {
+        Ok(db) => db,
+        Err(e) => {
+            output::failure(format!("Failed to connect to the database: {:?}", e));
+            return ExitCode::from(exit::FAILURE);
+        },
+    };
+
+    let credentials = match credentials {
+        Ok(credentials) => credentials,
+        Err(message) => {
+            output::error(message);
+            return ExitCode::from(exit::FAILURE);
+        },
+    };
+
+    // The targets of `apply` and `up` may run concurrently, each one on its own connection as the namespace and
+    // database in use are per connection. The other commands print reports or ask for confirmations.
+    let mut parallel = match args.command {
+        Commands::Apply { dry_run: false, .. } | Commands::Up { .. } => args.parallel.or(settings.parallel).unwrap_or(1).max(1),
+        _ => 1,
+    };
+    if parallel > 1 && endpoint.embedded {
+        output::warning("--parallel is ignored with an embedded database, the targets are processed one after the other.");
+        parallel = 1;
+    }
+
+    let db = &db;
+    let credentials = credentials.as_ref();
+    let (source, pattern, table, vars, hooks, endpoint, config, retry) = (&source, &pattern, &table, &vars, &hooks, &endpoint, &config, &retry);
+    let (host, paths, templates_dir, backup_dir, environment) = (&host, &paths, &templates_dir, backup_dir.as_deref(), &environment);
+    let (audit, create_ns_db, preflight, idempotent, resume) = (args.audit, args.create_ns_db || args.namespace_scope, !args.no_preflight, args.idempotent, args.resume);
+    let (yes, no_color) = (args.yes, args.no_color);
+    let (timeout, migration_timeout, command) = (args.timeout, args.migration_timeout, &args.command);
+    let multiple = targets.len() > 1;
+
+    let results: Vec<(String, ExitCode)> = stream::iter(&targets)
+        .map(|(namespace, database)| async move {
+            let target = format!("{}/{}", namespace, database);
+            if multiple && parallel == 1 {
+                output::heading(&target);
+            }
+
+            info!(%namespace, %database, "Using");
+
+            let run = async {
+                let connection;
+                let db = if parallel > 1 {
+                    match retry.run(|_| async { Ok(cli::endpoint::connect(endpoint, config.clone()).await?) }).await {
+                        Ok(own) => {
+                            connection = own;
+                            &connection
+                        },
+                        Err(e) => {
+                            output::failure(format!("Failed to connect to the database: {:?}", e));
+                            return ExitCode::from(exit::FAILURE);
+                        },
+                    }
+                } else {
+                    db
+                };
+
+                if let Err(e) = use_target(db, credentials, auth_level, namespace, database, retry).await {
+                    output::failure(e);
+                    return ExitCode::from(exit::FAILURE);
+                }
+
+                let mut migrator = Migrator::new(db)
+                    .source(source)
+                    .file_pattern(pattern.clone())
+                    .table(table.as_str())
+                    .policy(policy)
+                    .checksum_algorithm(checksum_algorithm)
+                    .audit(audit)
+                    .create_ns_db(create_ns_db)
+                    .preflight(preflight)
+                    .idempotent(idempotent)
+                    .resume(resume)
+                    .lock_timeout(lock_timeout)
+                    .retries(retry.retries)
+                    .retry_delay(retry.delay)
+                    .vars(vars.clone());
+
+                if let Some(environment) = environment {
+                    migrator = migrator.environment(environment.as_str());
+                }
+                if let Some(timeout) = timeout {
+                    migrator = migrator.timeout(Duration::from_secs(timeout));
+                }
+                if let Some(migration_timeout) = migration_timeout {
+                    migrator = migrator.migration_timeout(Duration::from_secs(migration_timeout));
+                }
+
+                let context = Context {
+                    host,
+                    paths,
+                    source,
+                    recursive,
+                    namespace,
+                    database,
+                    table,
+                    yes,
+                    no_color,
+                    pattern,
+                    templates_dir,
+                    backup_dir,
+                    hooks,
+                };
+
+                execute(command, db, migrator, &context).await
+            };
+
+            let code = if parallel > 1 {
+                output::with_target(target.clone(), run.instrument(info_span!("target", %namespace, %database))).await
+            } else {
+                run.await
+            };
+
+            (target, code)
+        })
+        .buffered(parallel)
+        .collect()
+        .await;
+
+    if multiple {
+        output::heading("Summary");
+        for (target, code) in &results {
+            if *code == ExitCode::SUCCESS {
+                output::success(target);
+            } else {
+                output::item(Marker::Failure, format!("{} (failed)", target));
+            }
+        }
+
+        let failed = results.iter().filter(|(_, code)| *code != ExitCode::SUCCESS).count();
+        output::info(format!("{} succeeded, {} failed.", results.len() - failed, failed));
+    }
+
+    // Report the first failure, once every target has been processed.
+    results
+        .into_iter()
+        .map(|(_, code)| code)
+        .find(|code| *code != ExitCode::SUCCESS)
+        .unwrap_or(ExitCode::SUCCESS)
+}
+
+/// Signs in, unless the database is embedded, and uses the namespace and database of a target.
+async fn use_target(
+    db: &Surreal<Any>,
+    credentials: Option<&Credentials>,
+    auth_level: AuthLevel,
+    namespace: &str,
+    database: &str,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    if let Some(credentials) = credentials {
+        let result = retry
+            .run(|_| async {
+                match *credentials {
+                    Credentials::User { ref username, ref password } => {
+                        cli::auth::signin(db, auth_level, namespace, database, username, password).await?
+                    },
+                    Credentials::Record { ref access, ref params } => {
+                        cli::auth::signin_record(db, namespace, database, access, params).await?
+                    },
+                    Credentials::Token(ref token) => cli::auth::authenticate(db, token.clone()).await?,
+                }
+
+                Ok::<(), Error>(())
+            })
+            .await;
+
+        if let Err(e) = result {
+            return Err(format!("Failed to sign in: {:?}", e));
+        }
+    }
+
+    if let Err(e) = db.use_ns(namespace).use_db(database).await {
+        return Err(format!("Failed to use namespace {} with database {}: {:?}", namespace, database, e));
+    }
+
+    Ok(())
+}
+
+/// The options of a command run on a target.
+struct Context<'a> {
+    host: &'a str,
+    /// The directories of the migration files, the new files being created in the first one.
+    paths: &'a [String],
+    /// The migration files of the directories.
+    source: &'a MultiSource<FsSource>,
+    /// Whether the migration files of the subdirectories are read too.
+    recursive: bool,
+    namespace: &'a str,
+    database: &'a str,
+    table: &'a str,
+    /// Skip the confirmation of the destructive commands.
+    yes: bool,
+    /// Disable the syntax highlighting of the SQL.
+    no_color: bool,
+    /// Which files are migration files.
+    pattern: &'a FilePattern,
+    /// The directory of the templates of the new migration files.
+    templates_dir: &'a str,
+    /// The directory of the exports made before the destructive commands.
+    backup_dir: Option<&'a Path>,
+    /// The shell commands run around `apply`.
+    hooks: &'a ShellHooks,
+}
+
+/// Runs a command on the database in use, returning its exit code.
+async fn execute(command: &Commands, db: &Surreal<Any>, migrator: Migrator<'_, Any>, context: &Context<'_>) -> ExitCode {
+    match command {
+        Commands::Apply { dry_run: true, no_pager, to, count, tags, .. } => {
+            let result = migrator.target(apply_target(to.clone(), *count)).tags(tags.clone()).plan().await;
+            match result {
+                Ok(pending) if pending.is_empty() => {
+                    output::info("No pending migrations.");
+                    ExitCode::SUCCESS
+                },
+                Ok(pending) => {
+                    let color = cli::preview::use_color(context.no_color);
+                    let mut output = String::new();
+                    for migration in pending {
+                        let header = format!("-- {} (checksum: {})", migration.filename, migration.checksum);
+                        let sql = if color { cli::preview::highlight(&migration.content) } else { migration.content };
+                        output.push_str(&format!("{}\n{}\n\n", cli::preview::header(&header, color), sql));
+                    }
+
+                    cli::preview::page(&output, *no_pager);
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to plan migrations", e),
+            }
+        },
+        Commands::Apply { fake: true, to, count, .. } => {
+            let result = migrator.target(apply_target(to.clone(), *count)).fake().await;
+            match result {
+                Ok(faked) if faked.is_empty() => {
+                    output::info("No pending migrations.");
+                    ExitCode::SUCCESS
+                },
+                Ok(faked) => {
+                    for filename in faked {
+                        output::success(format!("{} (recorded as applied, not executed)", filename));
+                    }
+
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to fake migrations", e),
+            }
+        },
+        Commands::Apply { dry_run: false, to, count, single_transaction, continue_on_error, batch_size, plan, shadow, tags, .. } => {
+            let mut migrator = migrator
+                .tags(tags.clone())
+                .single_transaction(*single_transaction)
+                .continue_on_error(*continue_on_error)
+                .batch_size(batch_size.unwrap_or(1));
+
+            if let Some(shadow) = shadow {
+                migrator = migrator.shadow_database(shadow.as_str());
+            }
+
+            if let Some(plan) = plan {
+                match fs::read_to_string(plan).map_err(Error::from).and_then(|json| MigrationPlan::from_json(&json)) {
+                    Ok(plan) => migrator = migrator.expected_plan(plan),
+                    Err(e) => return exit::fail(&format!("Failed to read the plan {}", plan), e),
+                }
+            }
+
+            apply(migrator, apply_target(to.clone(), *count), context).await
+        },
+        Commands::Plan { output_file } => {
+            let result = migrator.plan().await;
+            match result {
+                Ok(pending) => {
+                    if pending.is_empty() {
+                        output::info("No pending migrations.");
+                    }
+                    for migration in &pending {
+                        output::item(Marker::Pending, format!("{} (checksum {})", migration.filename, migration.checksum));
+                    }
+
+                    if let Some(output_file) = output_file {
+                        if let Err(e) = fs::write(output_file, MigrationPlan::new(&pending).to_json()) {
+                            return exit::fail(&format!("Failed to write the plan to {}", output_file), e.into());
+                        }
+                        output::success(format!("Plan written to {}.", output_file));
+                    }
+
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to plan migrations", e),
+            }
+        },
+        Commands::Up { count } => apply(migrator, ApplyTarget::Count(*count), context).await,
+        Commands::Rollback { steps, to } => {
+            let target = match to {
+                Some(to) => RollbackTarget::To(to.clone()),
+                None => RollbackTarget::Steps(steps.unwrap_or(1)),
+            };
+
+            roll_back(db, migrator, target, context).await
+        },
+        Commands::Down { count } => roll_back(db, migrator, RollbackTarget::Steps(*count), context).await,
+        Commands::Redo { last } => {
+            if let Some(code) = refused(&format!("roll back and apply again the last {} migration(s)", last), context) {
+                return code;
+            }
+            if let Some(code) = backed_up(db, context).await {
+                return code;
+            }
+
+            let result = migrator.redo(*last).await;
+            match result {
+                Ok(report) => {
+                    for migration in &report.applied {
+                        output::success(format!("{} (applied again in {:?})", migration.filename, migration.duration));
+                    }
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to redo migrations", e),
+            }
+        },
+        Commands::Status { tags } => {
+            let result = migrator.tags(tags.clone()).status().await;
+            match result {
+                Ok(statuses) => {
+                    for migration in statuses {
+                        let applied_at = migration
+                            .applied_at
+                            .map(|applied_at| applied_at.to_rfc3339())
+                            .unwrap_or_else(|| "-".to_string());
+
+                        match migration.state {
+                            MigrationState::Applied => match migration.applied_by {
+                                Some(applied_by) => output::success(format!("{} (applied at {} by {})", migration.filename, applied_at, applied_by)),
+                                None => output::success(format!("{} (applied at {})", migration.filename, applied_at)),
+                            },
+                            MigrationState::Pending => output::item(Marker::Pending, format!("{} (pending)", migration.filename)),
+                            MigrationState::OutOfOrder => output::item(Marker::Failure, format!("{} (pending, out of order)", migration.filename)),
+                            MigrationState::Changed => output::item(Marker::Failure, format!("{} (changed since applied at {})", migration.filename, applied_at)),
+                            MigrationState::Missing => output::item(Marker::Failure, format!("{} (missing, applied at {})", migration.filename, applied_at)),
+                            MigrationState::Skipped => output::item(Marker::Skipped, format!("{} (for other environments, skipped at {})", migration.filename, applied_at)),
+                        }
+                    }
+
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to get migrations status", e),
+            }
+        },
+        Commands::History => {
+            let result = migrator.history().await;
+            match result {
+                Ok(history) => {
+                    for migration in history {
+                        let checksum = migration
+                            .checksum
+                            .map(|checksum| checksum.chars().take(12).collect::<String>())
+                            .unwrap_or_else(|| "-".to_string());
+
+                        let Some(applied_at) = migration.applied_at else {
+                            output::item(Marker::Pending, format!("{} (pending, checksum {})", migration.filename, checksum));
+                            continue;
+                        };
+
+                        let duration = migration
+                            .execution_time_ms
+                            .map(|execution_time_ms| format!("{}ms", execution_time_ms))
+                            .unwrap_or_else(|| "-".to_string());
+                        let applied_by = migration.applied_by.unwrap_or_else(|| "-".to_string());
+                        let tool_version = migration.tool_version.unwrap_or_else(|| "-".to_string());
+
+                        // Changed or missing since applied.
+                        let marker = match migration.state {
+                            MigrationState::Applied => Marker::Success,
+                            MigrationState::Skipped => Marker::Skipped,
+                            _ => Marker::Failure,
+                        };
+
+                        output::item(marker, format!(
+                            "{} (applied at {} in {} by {} with v{}, checksum {})",
+                            migration.filename,
+                            applied_at.to_rfc3339(),
+                            duration,
+                            applied_by,
+                            tool_version,
+                            checksum
+                        ));
+                    }
+
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to get the migrations history", e),
+            }
+        },
+        Commands::Verify => {
+            let result = migrator.verify().await;
+            match result {
+                Ok(report) => {
+                    let deviations = report
+                        .changed
+                        .iter()
+                        .map(|migration| (&migration.filename, "changed since it was applied", exit::FORBIDDEN_UPDATE, report.policy.modified))
+                        .chain(report.missing.iter().map(|filename| (filename, "applied but missing", exit::MISSING_MIGRATION, report.policy.missing)))
+                        .chain(report.out_of_order.iter().map(|filename| {
+                            (filename, "pending but before the last applied migration", exit::OUT_OF_ORDER, report.policy.out_of_order)
+                        }));
+
+                    let mut exit_code: Option<u8> = None;
+                    for (filename, problem, code, action) in deviations {
+                        match action {
+                            PolicyAction::Ignore => continue,
+                            PolicyAction::Warn => {
+                                output::warning_in(Some(filename), format!("{} ({})", filename, problem));
+                                continue;
+                            },
+                            PolicyAction::Error => output::failure_in(Some(filename), None, format!("{} ({})", filename, problem)),
+                        }
+
+                        // Report the first class of problem found.
+                        exit_code.get_or_insert(code);
+                    }
+
+                    match exit_code {
+                        Some(code) => ExitCode::from(code),
+                        None => {
+                            output::success(format!("{} migration file(s) verified.", report.verified));
+                            ExitCode::SUCCESS
+                        },
+                    }
+                },
+                Err(e) => exit::fail("Failed to verify migrations", e),
+            }
+        },
+        Commands::Diff => {
+            let result = migrator.diff().await;
+            match result {
+                Ok(diff) if diff.is_empty() => {
+                    output::success("The schema of the database matches the migrations.");
+                    ExitCode::SUCCESS
+                },
+                Ok(diff) => {
+                    for definition in &diff.missing {
+                        output::item(Marker::Added, format!("{} (missing from the database)", definition));
+                        output::detail(&definition.statement);
+                    }
+                    for definition in &diff.unexpected {
+                        output::item(Marker::Skipped, format!("{} (not defined by the migrations)", definition));
+                        output::detail(&definition.statement);
+                    }
+                    for changed in &diff.changed {
+                        output::item(Marker::Changed, format!("{} (defined differently)", changed.expected));
+                        output::detail(format!("expected: {}", changed.expected.statement));
+                        output::detail(format!("actual:   {}", changed.actual.statement));
+                    }
+
+                    ExitCode::from(exit::SCHEMA_DRIFT)
+                },
+                Err(e) => exit::fail("Failed to compare the schemas", e),
+            }
+        },
+        Commands::Baseline { to } => {
+            let result = migrator.baseline(to).await;
+            match result {
+                Ok(recorded) if recorded.is_empty() => {
+                    output::info("No migrations to record.");
+                    ExitCode::SUCCESS
+                },
+                Ok(recorded) => {
+                    for filename in recorded {
+                        output::success(format!("{} (recorded as applied)", filename));
+                    }
+
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to baseline migrations", e),
+            }
+        },
+        Commands::Squash { to, dry_run } => {
+            if !*dry_run {
+                if let Some(code) = refused(&format!("squash the migrations up to {}", to), context) {
+                    return code;
+                }
+            }
+
+            let result = migrator.dry_run(*dry_run).squash(&context.paths[0], to).await;
+            if let Ok(SquashReport { stopped_at: Some(ref filename), .. }) = result {
+                output::warning(format!("The squash stops before {}, whose directives only apply to it.", filename));
+            }
+            match result {
+                Ok(report) if report.is_empty() => {
+                    output::info("Nothing to squash.");
+                    ExitCode::SUCCESS
+                },
+                Ok(report) => {
+                    let squash = if report.dry_run { "Would squash" } else { "Squashed" };
+                    let baseline = report.baseline.unwrap_or_default();
+
+                    for filename in &report.squashed {
+                        output::info(format!("{} {} into {}", squash, filename, baseline));
+                    }
+                    if report.dry_run {
+                        output::info(format!("The original files would be moved to {}", report.archive_dir.display()));
+                    } else {
+                        output::success(format!("{} created, the original files are in {}", baseline, report.archive_dir.display()));
+                    }
+
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to squash the migrations", e),
+            }
+        },
+        Commands::Repair { dry_run } => {
+            let result = migrator.dry_run(*dry_run).repair().await;
+            match result {
+                Ok(report) if report.is_empty() => {
+                    output::info("Nothing to repair.");
+                    ExitCode::SUCCESS
+                },
+                Ok(report) => {
+                    let (update, remove) = if report.dry_run {
+                        ("Would update", "Would remove")
+                    } else {
+                        ("Updated", "Removed")
+                    };
+
+                    for filename in report.updated_checksums {
+                        output::info(format!("{} the checksum of {}", update, filename));
+                    }
+                    for filename in report.removed {
+                        output::info(format!("{} the record of the missing file {}", remove, filename));
+                    }
+                    for filename in report.deduplicated {
+                        output::info(format!("{} the duplicated records of {}", remove, filename));
+                    }
+
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to repair the migrations table", e),
+            }
+        },
+        Commands::Prune => {
+            let result = migrator.dry_run(!context.yes).prune().await;
+            match result {
+                Ok(orphaned) if orphaned.is_empty() => {
+                    output::info("No record to prune.");
+                    ExitCode::SUCCESS
+                },
+                Ok(orphaned) if !context.yes => {
+                    for filename in &orphaned {
+                        output::item(Marker::Pending, format!("{} (file deleted)", filename));
+                    }
+                    output::info(format!("Run `ssm prune --yes` to remove these {} record(s).", orphaned.len()));
+                    ExitCode::SUCCESS
+                },
+                Ok(orphaned) => {
+                    for filename in &orphaned {
+                        output::success(format!("{} (record removed)", filename));
+                    }
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to prune the migrations table", e),
+            }
+        },
+        Commands::Seed { env: seed_env, dir } => {
+            let dir = dir
+                .clone()
+                .or_else(|| env::var("SSM_SEEDS_PATH").ok())
+                .unwrap_or_else(|| "./seeds".to_string());
+
+            let result = migrator.seeds_dir(dir).seed(seed_env.as_deref()).await;
+            match result {
+                Ok(report) => {
+                    for filename in &report.applied {
+                        output::success(format!("{} (applied)", filename));
+                    }
+
+                    output::info(format!("{} seed file(s) applied, {} unchanged.", report.applied.len(), report.skipped.len()));
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to apply the seed files", e),
+            }
+        },
+        Commands::New { name, down, timestamp, from_diff: true, .. } => {
+            let diff = match migrator.diff().await {
+                Ok(diff) if diff.is_empty() => {
+                    output::info("The schema of the database matches the migrations, no migration to generate.");
+                    return ExitCode::SUCCESS;
+                },
+                Ok(diff) => diff,
+                Err(e) => return exit::fail("Failed to compare the schemas", e),
+            };
+
+            let mut template = match MigrationTemplate::load(context.templates_dir).await {
+                Ok(template) => template.body(format!("{}\n", diff.to_statements())),
+                Err(e) => return exit::fail("Failed to read the templates", e),
+            };
+            if *down {
+                template = template.down_body(format!("{}\n", diff.reversed().to_statements()));
+            }
+
+            let scheme = if *timestamp { NamingScheme::Timestamp } else { NamingScheme::Sequential };
+            print_created(new_migration_with_template(Path::new(&context.paths[0]), context.source, name, scheme, &template, context.pattern).await)
+        },
+        Commands::New { from_diff: false, .. } | Commands::Lint { .. } | Commands::Check { .. } | Commands::Doctor | Commands::Completions { .. } => {
+            unreachable!("handled before connecting to the database")
+        },
+        Commands::Watch => {
+            let result = cli::watch::watch(&migrator, context.paths, context.recursive, context.pattern).await;
+            match result {
+                Ok(_) => ExitCode::SUCCESS,
+                Err(e) => exit::fail("Failed to watch the migration directory", e),
+            }
+        },
+        Commands::Ledger { command: LedgerCommands::Export { output_file } } => {
+            let ledger = match migrator.export_ledger().await {
+                Ok(ledger) => ledger,
+                Err(e) => return exit::fail("Failed to export the migrations ledger", e),
+            };
+
+            match output_file {
+                Some(output_file) => {
+                    if let Err(e) = fs::write(output_file, ledger.to_json()) {
+                        return exit::fail(&format!("Failed to write the ledger to {}", output_file), e.into());
+                    }
+                    output::success(format!("{} record(s) written to {}.", ledger.migrations.len(), output_file));
+                },
+                None => println!("{}", ledger.to_json()),
+            }
+
+            ExitCode::SUCCESS
+        },
+        Commands::Ledger { command: LedgerCommands::Import { file } } => {
+            let ledger = match fs::read_to_string(file).map_err(Error::from).and_then(|json| MigrationLedger::from_json(&json)) {
+                Ok(ledger) => ledger,
+                Err(e) => return exit::fail(&format!("Failed to read the ledger {}", file), e),
+            };
+
+            if let Some(code) = refused(&format!("replace the migrations table with the {} record(s) of {}", ledger.migrations.len(), file), context) {
+                return code;
+            }
+
+            let result = migrator.import_ledger(&ledger).await;
+            match result {
+                Ok(count) => {
+                    output::success(format!("{} record(s) imported.", count));
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to import the migrations ledger", e),
+            }
+        },
+        Commands::ForceUnlock => {
+            let result = migrator.force_unlock().await;
+            match result {
+                Ok(_) => {
+                    output::success("Migrations lock successfully removed.");
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to remove the migrations lock", e),
+            }
+        },
+        Commands::Reset { force_drop_database: false } => {
+            if let Some(code) = refused("remove the resources defined by the migrations and the migrations table", context) {
+                return code;
+            }
+            if let Some(code) = backed_up(db, context).await {
+                return code;
+            }
+
+            let result = migrator.reset().await;
+            match result {
+                Ok(_) => {
+                    output::success("Migrated resources and migrations table successfully removed.");
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to reset migrations", e),
+            }
+        },
+        Commands::Reset { force_drop_database: true } => {
+            if let Some(code) = refused("remove the whole database", context) {
+                return code;
+            }
+            if let Some(code) = backed_up(db, context).await {
+                return code;
+            }
+
             let result = db
-                .query("DELETE FROM migrations")
+                .query(format!("DELETE FROM {};", context.table))
                 .await;
 
             if let Err(e) = result {
-                return eprintln!("Failed to reset migrations table: {:?}", e);
+                return exit::fail("Failed to reset migrations table", e.into());
             }
 
             let result = db
-                .query(format!("REMOVE DATABASE {};", &database).as_str())
+                .query(format!("REMOVE DATABASE {};", context.database).as_str())
                 .await;
 
             if let Err(e) = result {
-                return eprintln!("Failed to remove database: {:?}", e);
+                return exit::fail("Failed to remove database", e.into());
+            }
+
+            output::success("Migrations table and database successfully removed.");
+            ExitCode::SUCCESS
+        },
+        Commands::Clean => {
+            if let Some(code) = refused("remove every table, function, param and analyzer except the migrations table", context) {
+                return code;
+            }
+            if let Some(code) = backed_up(db, context).await {
+                return code;
             }
 
-            return println!("Migrations table and database successfully removed.");
+            let result = migrator.clean().await;
+            match result {
+                Ok(removed) => {
+                    for definition in &removed {
+                        output::item(Marker::Success, format!("Removed {}", definition));
+                    }
+                    output::success(format!("{} resource(s) removed, the migrations table is kept.", removed.len()));
+                    ExitCode::SUCCESS
+                },
+                Err(e) => exit::fail("Failed to clean the database", e),
+            }
+        },
+        Commands::Test { dir } => {
+            let dir = dir.clone().unwrap_or_else(|| DEFAULT_ASSERTIONS_DIR.to_string());
+            rehearse(db, migrator.assertions_dir(dir), context).await
+        },
+    }
+}
+
+/// Applies the pending migrations of the target, stopping between two files on Ctrl-C, and runs the hooks of the
+/// configuration file around them.
+async fn apply(migrator: Migrator<'_, Any>, target: ApplyTarget, context: &Context<'_>) -> ExitCode {
+    let mut vars = vec![
+        ("SSM_HOST", context.host.to_string()),
+        ("SSM_NAMESPACE", context.namespace.to_string()),
+        ("SSM_DATABASE", context.database.to_string()),
+    ];
+
+    if let Some(ref command) = context.hooks.before_apply {
+        if let Err(e) = cli::hooks::run("before_apply", command, &vars) {
+            output::failure(format!("{} No migration has been applied.", e));
+            return ExitCode::from(exit::FAILURE);
         }
     }
 
-    ()
+    // Ctrl-C stops the run once the migration file being applied is recorded.
+    let token = CancellationToken::new();
+    let interrupt = tokio::spawn({
+        let token = token.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                output::warning("Interrupted: stopping once the current migration file is applied.");
+                token.cancel();
+            }
+        }
+    });
+
+    let result = migrator
+        .target(target)
+        .cancellation(token)
+        .on_event(|event| {
+            if let MigrationEvent::Progress { filename, batches, rows } = event {
+                output::detail(format!("{}: batch {} committed, {} record(s) so far", filename, batches, rows));
+            }
+        })
+        .run()
+        .await;
+    interrupt.abort();
+
+    let (hook, applied, error) = match result {
+        Ok(ref report) if report.cancelled => ("on_failure", report.applied.len(), Some("Cancelled".to_string())),
+        Ok(ref report) if !report.failed.is_empty() => {
+            let errors: Vec<String> = report
+                .failed
+                .iter()
+                .map(|migration| format!("{}: {}", migration.filename, migration.error))
+                .collect();
+            ("on_failure", report.applied.len(), Some(errors.join("\n")))
+        },
+        Ok(ref report) => ("after_apply", report.applied.len(), None),
+        Err(ref e) => ("on_failure", 0, Some(e.to_string())),
+    };
+
+    if let Ok(ref report) = result {
+        for warning in &report.warnings {
+            output::warning_in(Some(&warning.filename), format!("{}: {} [{}]", warning.filename, warning.message, warning.kind));
+        }
+    }
+
+    let mut code = match result {
+        Ok(report) if report.cancelled => {
+            output::warning(format!("Cancelled: {} migration(s) applied, the next ones are still pending.", report.applied.len()));
+            ExitCode::from(exit::FAILURE)
+        },
+        Ok(report) if !report.failed.is_empty() => {
+            for migration in &report.failed {
+                output::failure_in(
+                    Some(&migration.filename),
+                    None,
+                    format!("{}: {} (error code {})", migration.filename, migration.error, migration.code)
+                );
+            }
+            output::info(format!("{} migration(s) applied, {} failed.", report.applied.len(), report.failed.len()));
+            ExitCode::from(exit::FAILURE)
+        },
+        Ok(report) => {
+            for migration in &report.applied {
+                if let Some(rows) = migration.rows {
+                    output::success(format!("{} ({} record(s) migrated)", migration.filename, rows));
+                }
+            }
+            output::success(format!(
+                "{} migration(s) applied, {} already applied, in {:?}.",
+                report.applied.len(),
+                report.skipped.len(),
+                report.duration
+            ));
+            ExitCode::SUCCESS
+        },
+        Err(e @ Error::MissingMigration { .. }) => {
+            let code = exit::fail("Failed to apply migrations", e);
+            output::detail("Restore the files (e.g. with git), or run `ssm prune` if they have been deleted on purpose.");
+            code
+        },
+        Err(e) => exit::fail("Failed to apply migrations", e),
+    };
+
+    let command = if hook == "after_apply" { &context.hooks.after_apply } else { &context.hooks.on_failure };
+    if let Some(command) = command {
+        vars.push(("SSM_APPLIED_COUNT", applied.to_string()));
+        if let Some(error) = error {
+            vars.push(("SSM_ERROR", error));
+        }
+
+        if let Err(e) = cli::hooks::run(hook, command, &vars) {
+            output::failure(e);
+            // The migrations are applied, but what follows them (e.g. a cache purge) did not happen.
+            if code == ExitCode::SUCCESS {
+                code = ExitCode::from(exit::FAILURE);
+            }
+        }
+    }
+
+    code
+}
+
+/// Rolls back the migrations of the target, once confirmed.
+async fn roll_back(db: &Surreal<Any>, migrator: Migrator<'_, Any>, target: RollbackTarget, context: &Context<'_>) -> ExitCode {
+    let action = match target {
+        RollbackTarget::To(ref to) => format!("roll back the migrations applied after {}", to),
+        RollbackTarget::Steps(steps) => format!("roll back the last {} migration(s)", steps),
+    };
+    if let Some(code) = refused(&action, context) {
+        return code;
+    }
+    if let Some(code) = backed_up(db, context).await {
+        return code;
+    }
+
+    let result = migrator.rollback(target).await;
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => exit::fail("Failed to roll back migrations", e),
+    }
+}
+
+/// Exports the database, applies the pending migrations and runs the assertion files, then restores the export
+/// (kept in `--backup-dir` when set, removed otherwise once restored).
+async fn rehearse(db: &Surreal<Any>, migrator: Migrator<'_, Any>, context: &Context<'_>) -> ExitCode {
+    if let Some(code) = refused("apply the pending migrations, then restore the database from an export", context) {
+        return code;
+    }
+
+    let dir = context.backup_dir.map(Path::to_path_buf).unwrap_or_else(env::temp_dir);
+    let export = match cli::backup::backup(db, &dir, context.namespace, context.database).await {
+        Ok(path) => {
+            output::info(format!("Database exported to {}.", path.display()));
+            path
+        },
+        Err(e) => return exit::fail("Failed to export the database, nothing has been done", e),
+    };
+
+    let mut code = match migrator.run().await {
+        Ok(report) if !report.failed.is_empty() => {
+            for migration in &report.failed {
+                output::failure_in(Some(&migration.filename), None, format!("{}: {} (error code {})", migration.filename, migration.error, migration.code));
+            }
+            ExitCode::from(exit::FAILURE)
+        },
+        Ok(report) => {
+            for migration in &report.applied {
+                output::success(format!("{} (applied)", migration.filename));
+            }
+            ExitCode::SUCCESS
+        },
+        Err(e) => exit::fail("Failed to apply migrations", e),
+    };
+
+    // The assertions are about the migrated database.
+    if code == ExitCode::SUCCESS {
+        code = match migrator.run_assertions().await {
+            Ok(report) => {
+                for filename in &report.passed {
+                    output::success(format!("{} (passed)", filename));
+                }
+                for assertion in &report.failed {
+                    output::failure_in(Some(&assertion.filename), None, format!("{}: {}", assertion.filename, assertion.message));
+                }
+
+                output::info(format!("{} assertion(s) passed, {} failed.", report.passed.len(), report.failed.len()));
+                if report.is_success() { ExitCode::SUCCESS } else { ExitCode::from(exit::ASSERTION_FAILED) }
+            },
+            Err(e) => exit::fail("Failed to run the assertion files", e),
+        };
+    }
+
+    if let Err(e) = migrator.restore(&export).await {
+        return exit::fail(&format!("Failed to restore the database, import {} with `surreal import`", export.display()), e);
+    }
+    output::success("Database restored from the export.");
+
+    if context.backup_dir.is_none() {
+        let _ = tokio::fs::remove_file(&export).await;
+    }
+
+    code
+}
+
+/// Asks for the confirmation of a destructive action on the target, returning the exit code when it is refused.
+fn refused(action: &str, context: &Context) -> Option<ExitCode> {
+    let action = format!("{} on database {}/{} of {}", action, context.namespace, context.database, context.host);
+
+    match cli::confirm::confirm(&action, context.database, context.yes) {
+        Ok(_) => None,
+        Err(e) => {
+            output::error(e);
+            Some(ExitCode::from(exit::FAILURE))
+        },
+    }
+}
+
+/// Exports the database before a destructive command when `--backup-dir` is set, returning the exit code when the
+/// export fails (the command is not run then).
+async fn backed_up(db: &Surreal<Any>, context: &Context<'_>) -> Option<ExitCode> {
+    let dir = context.backup_dir?;
+
+    match cli::backup::backup(db, dir, context.namespace, context.database).await {
+        Ok(path) => {
+            output::success(format!("Backup written to {}.", path.display()));
+            None
+        },
+        Err(e) => Some(exit::fail("Failed to back up the database, nothing has been done", e)),
+    }
+}
+
+/// Prints the files created by the `new` command.
+fn print_created(result: Result<NewMigration, Error>) -> ExitCode {
+    match result {
+        Ok(migration) => {
+            output::success(format!("Created: {}", migration.path.display()));
+            if let Some(down_path) = migration.down_path {
+                output::success(format!("Created: {}", down_path.display()));
+            }
+
+            ExitCode::SUCCESS
+        },
+        Err(e) => exit::fail("Failed to create the migration file", e),
+    }
+}
+
+/// Prints the completion script of a shell, with the profiles of the configuration file as the values of
+/// `--profile` (none when the file cannot be read).
+fn print_completions(shell: clap_complete::Shell, config: Option<&str>) {
+    let mut profiles: Vec<String> = Config::load(config)
+        .map(|config| config.profiles.into_keys().collect())
+        .unwrap_or_default();
+    profiles.sort();
+
+    let mut command = Cli::command();
+    if !profiles.is_empty() {
+        command = command.mut_arg("profile", |arg| arg.value_parser(profiles));
+    }
+
+    clap_complete::generate(shell, &mut command, "ssm", &mut std::io::stdout());
+}
+
+/// Returns the lint configuration of the `check` command: only the rules about the validity of the files are
+/// checked, as errors unless their severity is configured (the missing down files only when required).
+fn check_config(pattern: FilePattern, severities: &HashMap<LintRule, LintSeverity>, require_down: bool) -> LintConfig {
+    let mut config = LintConfig::new()
+        .file_pattern(pattern)
+        .rule(LintRule::MissingSemicolon, LintSeverity::Off)
+        .rule(LintRule::UnguardedRemoveTable, LintSeverity::Off)
+        .rule(LintRule::NonIdempotentDefine, LintSeverity::Off);
+
+    let rules = [
+        LintRule::EmptyFile,
+        LintRule::Naming,
+        LintRule::DuplicateVersion,
+        LintRule::UnresolvedRequirement,
+        LintRule::InvalidStatement,
+        LintRule::ReplayError,
+    ];
+    for rule in rules {
+        config = config.rule(rule, severities.get(&rule).copied().unwrap_or(LintSeverity::Error));
+    }
+
+    let down = if require_down { Some(LintSeverity::Error) } else { severities.get(&LintRule::MissingDownFile).copied() };
+    config.rule(LintRule::MissingDownFile, down.unwrap_or(LintSeverity::Off))
+}
+
+/// Prints the issues found by the `lint` and `check` commands.
+fn print_lint(result: Result<Vec<LintIssue>, Error>) -> ExitCode {
+    let issues = match result {
+        Ok(issues) => issues,
+        Err(e) => return exit::fail("Failed to lint the migration files", e),
+    };
+
+    for issue in &issues {
+        let location = match issue.line {
+            Some(line) => format!("{}:{}", issue.filename, line),
+            None => issue.filename.clone(),
+        };
+        let marker = if issue.severity == LintSeverity::Error { Marker::Failure } else { Marker::Warning };
+        output::item_in(marker, &issue.filename, issue.line, format!("[{}] {} {}: {}", issue.severity, location, issue.rule, issue.message));
+    }
+
+    let errors = issues.iter().filter(|issue| issue.severity == LintSeverity::Error).count();
+    output::info(format!("{} error(s), {} warning(s).", errors, issues.len() - errors));
+
+    if errors > 0 {
+        ExitCode::from(exit::LINT_FAILED)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Returns the pending migrations to apply, from the `--to` and `--count` options.
+fn apply_target(to: Option<String>, count: Option<usize>) -> ApplyTarget {
+    match (to, count) {
+        (Some(to), _) => ApplyTarget::To(to),
+        (None, Some(count)) => ApplyTarget::Count(count),
+        (None, None) => ApplyTarget::All,
+    }
+}
+
+/// Parses a `KEY=VALUE` variable of the `--var` option.
+fn parse_var(var: &str) -> Result<(String, String), String> {
+    match var.split_once('=') {
+        Some((key, value)) => Ok((key.to_string(), value.to_string())),
+        None => Err(format!("The variable '{}' must be written as KEY=VALUE.", var)),
+    }
 }
@@ -0,0 +1,27 @@
+//! The metrics of the migration runs, recorded through the `metrics` facade when the `metrics` feature is
+//! enabled, and exported by the recorder installed by the application (e.g. `metrics-exporter-prometheus`).
+
+use ::metrics::{counter, histogram};
+
+use crate::MigrationEvent;
+
+/// The counter of the applied migration files, labelled with their `filename`.
+pub const MIGRATIONS_APPLIED: &str = "ssm_migrations_applied_total";
+/// The counter of the migration files that failed to be applied, labelled with their `filename`.
+pub const MIGRATIONS_FAILED: &str = "ssm_migrations_failed_total";
+/// The histogram of how long the migration files took to apply, in seconds, labelled with their `filename`.
+pub const MIGRATION_DURATION: &str = "ssm_migration_duration_seconds";
+
+/// Records the metrics of an event of a migration run.
+pub(crate) fn record(event: &MigrationEvent) {
+    match event {
+        MigrationEvent::Applied { filename, duration } => {
+            counter!(MIGRATIONS_APPLIED, "filename" => filename.clone()).increment(1);
+            histogram!(MIGRATION_DURATION, "filename" => filename.clone()).record(duration.as_secs_f64());
+        },
+        MigrationEvent::Failed { filename, .. } => {
+            counter!(MIGRATIONS_FAILED, "filename" => filename.clone()).increment(1);
+        },
+        _ => (),
+    }
+}
@@ -0,0 +1,2586 @@
+use std::{collections::HashMap, env, path::{Path, PathBuf}, time::{Duration, Instant}};
+
+use regex::Regex;
+#[cfg(feature = "kv-mem")]
+use surrealdb::engine::local::Mem;
+use surrealdb::{Connection, Response, Surreal};
+use tokio::{io::AsyncBufReadExt, time::timeout};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::{
+    clean::run_clean, compare_versions, compat::check_server_version, data::run_data_migration, directives::is_down_marker, preflight::check_permissions, progress::{define_progress_table, progress_table, read_failed_statement, record_failed_statement, skip_statements, statements_checksum}, events::{emit, EventHandler}, get_migrations, get_repeatable_migrations,
+    hooks::{run_all, run_each, Hooks}, idempotent::make_idempotent, lock::lock_table, is_out_of_order, last_applied_migration, ledger::{run_export_ledger, run_import_ledger}, prune::run_prune, rehearsal::{run_assertions, run_restore}, repair::run_repair, reset::run_reset, rollback::run_rollback, seed::run_seeds, shadow::{define_shadow_database, remove_shadow_database},
+    source::{read_migration_files, read_repeatable_files}, squash::{run_squash, SquashOptions}, statements::{parse_statements, Statement, StatementSplitter}, status::{read_applied, read_history, read_status},
+    vars::substitute, verify::run_verify, version, warnings::{check_modification_times, check_trailing_semicolons}, AppliedMigration, AssertionReport, ChecksumAlgorithm, Directives, Error, FilePattern, FsSource, HookFuture, Migration, MigrationEvent, MigrationFile, MigrationLedger, MigrationLock,
+    MigrationPlan, MigrationSource, MigrationStatus, MigrationWarning, MultiSource, PendingMigration, PlannedMigration, Policy, PolicyAction, RepairReport, RetryPolicy, RollbackTarget, SchemaDefinition, SeedReport,
+    SquashReport, VerifyReport, WarningKind, DEFAULT_ASSERTIONS_DIR, DEFAULT_DATA_BATCH_SIZE, DEFAULT_LOCK_TIMEOUT, DEFAULT_SEEDS_TABLE,
+};
+#[cfg(feature = "kv-mem")]
+use crate::{diff::{compare_schemas, read_schema}, SchemaDiff};
+
+/// The default name of the table tracking the applied migrations.
+pub const DEFAULT_TABLE: &str = "migrations";
+
+/// The database of each namespace tracking its namespace migrations (the files of `NAMESPACE_DIR`), as the tables
+/// of SurrealDB belong to a database.
+pub const NAMESPACE_DATABASE: &str = "ssm_namespace";
+
+/// The version recorded with each applied migration.
+const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How many statements of a streamed migration file are sent to the database at once.
+const STREAM_BATCH_SIZE: usize = 500;
+
+/// How many of the pending migrations should be applied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ApplyTarget {
+    /// Apply every pending migration.
+    #[default]
+    All,
+    /// Apply the pending migrations up to the given version included (e.g. `"004"`), and the pending files they
+    /// require (`-- ssm:requires`) whatever their version.
+    To(String),
+    /// Apply the next `n` pending migrations.
+    Count(usize),
+}
+
+impl ApplyTarget {
+    /// Keeps the pending migrations (in the order they are applied, each one after the files it requires) selected
+    /// by the target.
+    fn select(&self, pending: Vec<MigrationFile>) -> Vec<MigrationFile> {
+        match *self {
+            ApplyTarget::All => pending,
+            ApplyTarget::To(ref to) => {
+                let mut selected: Vec<bool> = pending
+                    .iter()
+                    .map(|entry| compare_versions(version(&entry.filename), version(to)).is_le())
+                    .collect();
+
+                // A required file comes before the files requiring it: walking back selects the requirements of the
+                // requirements too.
+                for index in (0..pending.len()).rev() {
+                    if !selected[index] {
+                        continue;
+                    }
+                    for name in Directives::parse(&pending[index].content).requires {
+                        if let Some(required) = pending.iter().position(|entry| entry.is_named(&name)) {
+                            selected[required] = true;
+                        }
+                    }
+                }
+
+                pending
+                    .into_iter()
+                    .zip(selected)
+                    .filter_map(|(entry, selected)| selected.then_some(entry))
+                    .collect()
+            },
+            ApplyTarget::Count(count) => pending
+                .into_iter()
+                .take(count)
+                .collect(),
+        }
+    }
+}
+
+/// How a migration file is applied and recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MigrationKind {
+    /// A numbered file, applied once.
+    Versioned,
+    /// A numbered file applied after a file with a higher version, with `allow_out_of_order`.
+    OutOfOrder,
+    /// A `R__` file, applied again whenever its content changes.
+    Repeatable,
+}
+
+/// A migration file applied during a migration run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutedMigration {
+    pub filename: String,
+    /// How long the execution of the file took.
+    pub duration: Duration,
+    /// The records the batches of a data migration (`-- ssm:kind data`) went through, `None` for the other files.
+    pub rows: Option<u64>,
+}
+
+/// A migration file that failed to be applied during a run in `continue_on_error` mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedMigration {
+    pub filename: String,
+    /// The `Error::code` of the failure.
+    pub code: u32,
+    /// The description of the failure.
+    pub error: String,
+}
+
+/// What has been done (or would be done, in dry run mode) by a migration run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// The migration files applied during the run.
+    pub applied: Vec<ExecutedMigration>,
+    /// The migration files that failed to be applied, in `continue_on_error` mode.
+    pub failed: Vec<FailedMigration>,
+    /// The migration files that would be applied, in dry run mode.
+    pub pending: Vec<String>,
+    /// The migration files already applied by a previous run.
+    pub skipped: Vec<String>,
+    /// The migration files for other environments (`-- ssm:env`), recorded as skipped without being executed
+    /// (or that would be, in dry run mode).
+    pub excluded: Vec<String>,
+    /// Whether the run was a dry run, in which case nothing has been executed.
+    pub dry_run: bool,
+    /// Whether the run was cancelled before applying every pending migration file.
+    pub cancelled: bool,
+    /// The non-fatal anomalies found by the run (e.g. a changed file allowed by the policy).
+    pub warnings: Vec<MigrationWarning>,
+    /// How long the whole run took.
+    pub duration: Duration,
+}
+
+/// Applies the migration files of a directory to a database.
+///
+/// ```ignore
+/// let report = Migrator::new(&db)
+///     .dir("./migrations")
+///     .table("migrations")
+///     .dry_run(false)
+///     .allow_out_of_order(false)
+///     .target(ApplyTarget::All)
+///     .run()
+///     .await?;
+/// ```
+pub struct Migrator<'a, C: Connection> {
+    db: &'a Surreal<C>,
+    source: Box<dyn MigrationSource + 'a>,
+    pattern: FilePattern,
+    table: String,
+    dry_run: bool,
+    policy: Policy,
+    lock_timeout: Duration,
+    timeout: Option<Duration>,
+    migration_timeout: Option<Duration>,
+    target: ApplyTarget,
+    applied_by: String,
+    vars: HashMap<String, String>,
+    hooks: Hooks<'a, C>,
+    events: Vec<EventHandler<'a>>,
+    seeds_dir: PathBuf,
+    seeds_table: String,
+    assertions_dir: PathBuf,
+    retry: RetryPolicy,
+    cancellation: Option<CancellationToken>,
+    single_transaction: bool,
+    continue_on_error: bool,
+    environment: Option<String>,
+    checksum_algorithm: ChecksumAlgorithm,
+    audit: bool,
+    create_ns_db: bool,
+    batch_size: usize,
+    expected_plan: Option<MigrationPlan>,
+    preflight: bool,
+    idempotent: bool,
+    resume: bool,
+    shadow_database: Option<String>,
+    tags: Vec<String>,
+}
+
+impl<'a, C: Connection> Migrator<'a, C> {
+    pub fn new(db: &'a Surreal<C>) -> Self {
+        Migrator {
+            db,
+            source: Box::new(FsSource::new("./")),
+            pattern: FilePattern::default(),
+            table: DEFAULT_TABLE.to_string(),
+            dry_run: false,
+            policy: Policy::default(),
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            timeout: None,
+            migration_timeout: None,
+            target: ApplyTarget::All,
+            applied_by: default_applied_by(),
+            vars: HashMap::new(),
+            hooks: Hooks::new(),
+            events: vec![],
+            seeds_dir: PathBuf::from("./seeds"),
+            seeds_table: DEFAULT_SEEDS_TABLE.to_string(),
+            assertions_dir: PathBuf::from(DEFAULT_ASSERTIONS_DIR),
+            retry: RetryPolicy::default(),
+            cancellation: None,
+            single_transaction: false,
+            continue_on_error: false,
+            environment: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            audit: false,
+            create_ns_db: false,
+            batch_size: 1,
+            expected_plan: None,
+            preflight: true,
+            idempotent: false,
+            resume: false,
+            shadow_database: None,
+            tags: vec![],
+        }
+    }
+
+    /// The directory of the migration files. (default: "./")
+    pub fn dir(self, dir: impl Into<PathBuf>) -> Self {
+        self.source(FsSource::new(dir))
+    }
+
+    /// Several directories of migration files (e.g. the schemas of several crates of a workspace), merged into
+    /// a single plan ordered by the number prefix of the files, instead of a single directory.
+    pub fn paths<P: Into<PathBuf>>(self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.source(MultiSource::new(paths.into_iter().map(FsSource::new)))
+    }
+
+    /// Where the migration files are read from, instead of a directory
+    /// (e.g. an `EmbeddedSource` built with `embed_migrations!`, or a `VecSource`).
+    pub fn source(mut self, source: impl MigrationSource + 'a) -> Self {
+        self.source = Box::new(source);
+        self
+    }
+
+    /// Which files of the source are migration files, e.g. to accept `.sql` files. (default: `.surql` files)
+    pub fn file_pattern(mut self, pattern: FilePattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// The table tracking the applied migrations. (default: "migrations")
+    pub fn table(mut self, table: impl Into<String>) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    /// Only resolve and validate the pending migrations, without executing them. (default: false)
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Apply new migration files with a lower version than the last applied one instead of failing, recording
+    /// them as applied out of order, with a warning: sets the `out_of_order` action of the `policy` to `Warn`
+    /// (or back to `Error`). (default: false)
+    pub fn allow_out_of_order(mut self, allow_out_of_order: bool) -> Self {
+        self.policy.out_of_order = if allow_out_of_order { PolicyAction::Warn } else { PolicyAction::Error };
+        self
+    }
+
+    /// Whether the files changed since their migration, the missing files and the out of order files fail the
+    /// runs, are logged as warnings, or are ignored. (default: every one fails the runs)
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// How long to wait for a concurrent migration run to release the migrations lock. (default: 60s)
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = lock_timeout;
+        self
+    }
+
+    /// How long applying the pending migrations (once the migrations lock is acquired) may take before failing
+    /// with `Error::Timeout`. (default: no timeout)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// How long applying a single migration file may take before failing with `Error::Timeout`, e.g. a
+    /// `DEFINE INDEX` or a data backfill stalling. (default: no timeout)
+    ///
+    /// The query is abandoned by the migrator, but may still be running on the database: as the file is applied
+    /// in a transaction, it is either fully applied and recorded or not at all.
+    pub fn migration_timeout(mut self, migration_timeout: Duration) -> Self {
+        self.migration_timeout = Some(migration_timeout);
+        self
+    }
+
+    /// Apply every pending migration file in a single transaction, so either all of them are applied or none
+    /// is, instead of one transaction per file. The files opting out of transactions with the `no-transaction`
+    /// directive are then refused, and the `migration_timeout` does not apply. (default: false)
+    pub fn single_transaction(mut self, single_transaction: bool) -> Self {
+        self.single_transaction = single_transaction;
+        self
+    }
+
+    /// Apply up to `batch_size` consecutive pending migration files in a single query, instead of a round trip per
+    /// file, e.g. many small files over a distant connection. Each batch is applied in a transaction recording its
+    /// files one by one; when it fails, its files are applied one at a time to report the failing one. The files
+    /// opting out of transactions and the streamed ones are applied on their own, and so is every file when
+    /// `before_each`/`after_each` hooks or a `migration_timeout` are set. (default: 1, no batching)
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Refuse to run (with `Error::PlanMismatch`) unless the migration files that would be applied, and their
+    /// checksums, are exactly the ones of a plan approved beforehand, e.g. reviewed after `plan` in CI. Checked
+    /// once the migrations lock is held, so nothing else can be applied in between. (default: none)
+    pub fn expected_plan(mut self, plan: MigrationPlan) -> Self {
+        self.expected_plan = Some(plan);
+        self
+    }
+
+    /// Attempt every pending migration file even when some fail, reporting the failed ones in
+    /// `MigrationReport::failed`, instead of aborting the run on the first failure. The files depending on a
+    /// failed one will likely fail too, and the failed files are then out of order. Ignored with
+    /// `single_transaction`. (default: false)
+    pub fn continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self
+    }
+
+    /// The environment the migrations are applied to (e.g. "prod"). The migration files restricted to other
+    /// environments with a `-- ssm:env prod,staging` directive are recorded as skipped without being executed,
+    /// rather than staying pending. (default: none, only the files without the directive are executed)
+    pub fn environment(mut self, environment: impl Into<String>) -> Self {
+        self.environment = Some(environment.into());
+        self
+    }
+
+    /// The algorithm of the checksums recorded for the migration files. The records with a checksum computed
+    /// with another algorithm are still verified with it, then upgraded on the next run. (default: SHA-256)
+    pub fn checksum_algorithm(mut self, checksum_algorithm: ChecksumAlgorithm) -> Self {
+        self.checksum_algorithm = checksum_algorithm;
+        self
+    }
+
+    /// Also store the exact SQL executed for each migration file (once its variables are substituted) in the
+    /// `<table>_audit` table (e.g. `migrations_audit`), in the transaction applying the file, keeping a record
+    /// of what ran even if the files are rewritten afterwards. (default: false)
+    pub fn audit(mut self, audit: bool) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Check the user can define a table, and create and delete records, before applying the first pending
+    /// migration, failing with `Error::InsufficientPermissions` rather than halfway through the migrations. It
+    /// probes a scratch `<table>_preflight` table, removed afterwards. (default: true)
+    pub fn preflight(mut self, preflight: bool) -> Self {
+        self.preflight = preflight;
+        self
+    }
+
+    /// Rewrite the `DEFINE TABLE`, `DEFINE FIELD` and `DEFINE INDEX` statements of the applied files without `IF
+    /// NOT EXISTS` or `OVERWRITE` into `DEFINE ... IF NOT EXISTS`, e.g. on a database where a previous attempt was
+    /// partially applied. The checksums stay the ones of the files. (default: false)
+    pub fn idempotent(mut self, idempotent: bool) -> Self {
+        self.idempotent = idempotent;
+        self
+    }
+
+    /// Continue the migration files opting out of transactions (or streamed) from the statement that failed in a
+    /// previous run, as recorded in the `<table>_progress` table, instead of executing them again from the start.
+    /// Only when the file has not changed since. (default: false)
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Before applying anything, replay the whole migration history into this scratch database of the namespace,
+    /// defined for the run and removed afterwards, to confirm the migrations still apply from zero (e.g. a file
+    /// relying on a change made by hand). When they do not, the run fails with `Error::ShadowFailed`. The
+    /// database must not exist. The replay switches the session of the handle, shared by its clones, to this
+    /// database, so the handle should not run other queries meanwhile; it is switched back afterwards, even when
+    /// the replay fails. (default: none)
+    pub fn shadow_database(mut self, database: impl Into<String>) -> Self {
+        self.shadow_database = Some(database.into());
+        self
+    }
+
+    /// Only apply (and list in `status`) the migration files having one of these tags (`-- ssm:tags auth,critical`),
+    /// e.g. the ones of a single module. The other pending files stay pending: applied later, the ones with a lower
+    /// version than a tagged file applied are out of order. (default: every file)
+    pub fn tags<T: Into<String>>(mut self, tags: impl IntoIterator<Item = T>) -> Self {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Define the namespace and the database used by the connection when they do not exist before applying the
+    /// migrations, e.g. on a fresh instance started in strict mode. (default: false)
+    pub fn create_ns_db(mut self, create_ns_db: bool) -> Self {
+        self.create_ns_db = create_ns_db;
+        self
+    }
+
+    /// Which of the pending migrations to apply. (default: `ApplyTarget::All`)
+    pub fn target(mut self, target: ApplyTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Who applies the migrations, as recorded in the migrations table. (default: "<user>@<hostname>")
+    pub fn applied_by(mut self, applied_by: impl Into<String>) -> Self {
+        self.applied_by = applied_by.into();
+        self
+    }
+
+    /// The values of the `${VAR}` placeholders of the migration files, taking precedence over the environment
+    /// variables.
+    pub fn vars(mut self, vars: HashMap<String, String>) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    /// Sets the value of a `${VAR}` placeholder of the migration files.
+    pub fn var(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.vars.insert(name.into(), value.into());
+        self
+    }
+
+    /// How many times the queries failing with a transient error (e.g. a dropped connection) are retried while
+    /// applying the migrations, with an exponential backoff. (default: 0)
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retry.retries = retries;
+        self
+    }
+
+    /// The delay before the first retry of a failed query, doubled for each of the next ones. (default: 500ms)
+    pub fn retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry.delay = retry_delay;
+        self
+    }
+
+    /// The directory of the seed files. (default: "./seeds")
+    pub fn seeds_dir(mut self, seeds_dir: impl Into<PathBuf>) -> Self {
+        self.seeds_dir = seeds_dir.into();
+        self
+    }
+
+    /// The table tracking the applied seed files. (default: "seeds")
+    pub fn seeds_table(mut self, seeds_table: impl Into<String>) -> Self {
+        self.seeds_table = seeds_table.into();
+        self
+    }
+
+    /// The directory of the assertion files checked by `run_assertions`. (default: "./tests")
+    pub fn assertions_dir(mut self, assertions_dir: impl Into<PathBuf>) -> Self {
+        self.assertions_dir = assertions_dir.into();
+        self
+    }
+
+    /// Stops the migration runs once the token is cancelled (e.g. when the application shuts down): the file being
+    /// applied is finished and recorded, and the next ones are left pending. The report of a cancelled run has
+    /// `cancelled` set, and the `after_all` hooks are not called.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Registers a hook called before applying the pending migrations, with their names (once the migrations
+    /// lock is acquired). An error aborts the run.
+    ///
+    /// ```ignore
+    /// Migrator::new(&db)
+    ///     .before_all(|db, filenames| Box::pin(async move {
+    ///         db.query("UPDATE maintenance:status SET enabled = true;").await?;
+    ///         Ok(())
+    ///     }))
+    /// ```
+    pub fn before_all<F>(mut self, hook: F) -> Self
+    where
+        F: for<'h> Fn(&'h Surreal<C>, &'h [String]) -> HookFuture<'h> + Send + Sync + 'a,
+    {
+        self.hooks.before_all.push(Box::new(hook));
+        self
+    }
+
+    /// Registers a hook called before applying each migration file, with its name. An error aborts the run.
+    pub fn before_each<F>(mut self, hook: F) -> Self
+    where
+        F: for<'h> Fn(&'h Surreal<C>, &'h str) -> HookFuture<'h> + Send + Sync + 'a,
+    {
+        self.hooks.before_each.push(Box::new(hook));
+        self
+    }
+
+    /// Registers a hook called after each migration file is successfully applied, with its name. An error aborts
+    /// the run.
+    pub fn after_each<F>(mut self, hook: F) -> Self
+    where
+        F: for<'h> Fn(&'h Surreal<C>, &'h str) -> HookFuture<'h> + Send + Sync + 'a,
+    {
+        self.hooks.after_each.push(Box::new(hook));
+        self
+    }
+
+    /// Registers a hook called after the pending migrations are successfully applied, with their names.
+    pub fn after_all<F>(mut self, hook: F) -> Self
+    where
+        F: for<'h> Fn(&'h Surreal<C>, &'h [String]) -> HookFuture<'h> + Send + Sync + 'a,
+    {
+        self.hooks.after_all.push(Box::new(hook));
+        self
+    }
+
+    /// Registers a callback called with the events of the migration runs (files discovered, skipped, started,
+    /// applied or failed), e.g. to drive a progress bar or record metrics.
+    ///
+    /// ```ignore
+    /// let (sender, receiver) = std::sync::mpsc::channel();
+    /// Migrator::new(&db)
+    ///     .on_event(move |event| { let _ = sender.send(event.clone()); })
+    /// ```
+    pub fn on_event<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&MigrationEvent) + Send + Sync + 'a,
+    {
+        self.events.push(Box::new(callback));
+        self
+    }
+
+    /// Applies the pending migrations (or only resolves them, in dry run mode).
+    pub async fn run(&self) -> Result<MigrationReport, Error> {
+        self.check_table_name()?;
+
+        let started_at = Instant::now();
+
+        if self.dry_run {
+            let migrations = get_migrations(self.db, &self.table).await?;
+            let entries = read_migration_files(self.source.as_ref(), &self.pattern).await?;
+            let mut warnings = vec![];
+            let pending = self.select_pending(check_migration_files(&migrations, &entries, &self.policy, &mut warnings)?);
+            check_trailing_semicolons(&pending, &mut warnings);
+            let (repeatable, unchanged) = self.resolve_repeatable_files().await?;
+            let (pending, excluded): (Vec<MigrationFile>, Vec<MigrationFile>) = pending
+                .into_iter()
+                .chain(repeatable)
+                .partition(|entry| self.applies(entry));
+            self.check_plan(&pending)?;
+            check_server_version(self.db, &self.retry, &pending).await?;
+
+            let report = MigrationReport {
+                pending: pending.into_iter().map(|entry| entry.filename).collect(),
+                excluded: excluded.into_iter().map(|entry| entry.filename).collect(),
+                skipped: migrations.into_iter().map(|migration| migration.filename).chain(unchanged).collect(),
+                dry_run: true,
+                duration: started_at.elapsed(),
+                warnings,
+                ..Default::default()
+            };
+            self.emit_discovered(&report.pending, &report.skipped);
+
+            return Ok(report);
+        }
+
+        if self.create_ns_db {
+            self.retry.run(|_| self.define_ns_db()).await?;
+        }
+        if let Some(ref shadow) = self.shadow_database {
+            self.replay_shadow(shadow).await?;
+        }
+        self.retry.run(|_| async { Ok(self.setup_migration_table().await?) }).await?;
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = match self.timeout {
+            Some(duration) => timeout(duration, self.run_migration_files())
+                .await
+                .unwrap_or_else(|_| {
+                    error!(timeout = ?duration, "The migrations did not complete in time");
+                    Err(Error::Timeout { filename: None, duration })
+                }),
+            None => self.run_migration_files().await,
+        };
+        lock.release().await?;
+
+        let mut report = result?;
+        report.duration = started_at.elapsed();
+
+        Ok(report)
+    }
+
+    /// Resolves the pending migrations and validates the migration directory against the migrations table
+    /// (checksums, ordering and missing files), without touching the database.
+    pub async fn plan(&self) -> Result<Vec<PendingMigration>, Error> {
+        self.check_table_name()?;
+
+        let migrations = get_migrations(self.db, &self.table).await?;
+        let entries = read_migration_files(self.source.as_ref(), &self.pattern).await?;
+
+        let pending = self.select_pending(check_migration_files(&migrations, &entries, &self.policy, &mut vec![])?);
+        let (repeatable, _) = self.resolve_repeatable_files().await?;
+
+        self.pending_migrations(pending.into_iter().chain(repeatable)).await
+    }
+
+    /// Returns the migration files not applied yet, in the order they would be applied: the versioned files
+    /// without record (including the out of order ones), then the new or changed repeatable files, leaving out
+    /// the files of other environments. Unlike `plan`, the migration directory is not validated against the
+    /// migrations table, e.g. to list them in the admin interface of an application.
+    pub async fn pending(&self) -> Result<Vec<PendingMigration>, Error> {
+        self.check_table_name()?;
+
+        let migrations = get_migrations(self.db, &self.table).await?;
+        let entries = read_migration_files(self.source.as_ref(), &self.pattern).await?;
+        let (repeatable, _) = self.resolve_repeatable_files().await?;
+
+        let pending = entries
+            .into_iter()
+            .filter(|entry| !migrations.iter().any(|migration| migration == &entry.filename));
+
+        self.pending_migrations(pending.chain(repeatable)).await
+    }
+
+    /// Returns the migrations applied to the database in the order they were applied, the repeatable ones
+    /// included, with their recorded checksum and execution metadata, and the path of their file when it is
+    /// still read from the filesystem.
+    pub async fn applied(&self) -> Result<Vec<AppliedMigration>, Error> {
+        self.check_table_name()?;
+
+        read_applied(self.db, self.source.as_ref(), &self.table).await
+    }
+
+    /// Returns the migration files to apply in this environment, with their SQL and the checksum they are
+    /// recorded with.
+    async fn pending_migrations(&self, entries: impl IntoIterator<Item = MigrationFile>) -> Result<Vec<PendingMigration>, Error> {
+        let mut pending: Vec<PendingMigration> = vec![];
+        for entry in entries.into_iter().filter(|entry| self.applies(entry)) {
+            pending.push(PendingMigration {
+                content: self.prepare(&entry.content)?,
+                checksum: entry.checksum_with(self.checksum_algorithm),
+                path: self.source.path(&entry.filename).await?,
+                filename: entry.filename,
+            });
+        }
+
+        Ok(pending)
+    }
+
+    /// Compares the schema resulting from every migration file, replayed into an in-memory database, with the
+    /// schema of the database (its tables, fields, indexes, events, functions, params and analyzers).
+    ///
+    /// The pending migrations show up as differences, so run it once the migrations are applied to detect the
+    /// changes made to the database by hand. Requires the `kv-mem` feature.
+    #[cfg(feature = "kv-mem")]
+    pub async fn diff(&self) -> Result<SchemaDiff, Error> {
+        self.check_table_name()?;
+
+        let expected = self.expected_schema().await?;
+        let actual = read_schema(self.db, &self.internal_tables()).await?;
+
+        Ok(compare_schemas(&expected, &actual))
+    }
+
+    /// Compares the migration directory with the migrations table, without applying anything. With `tags`, only
+    /// the files having one of them are listed.
+    pub async fn status(&self) -> Result<Vec<MigrationStatus>, Error> {
+        self.check_table_name()?;
+
+        let mut statuses = read_status(self.db, self.source.as_ref(), &self.table, &self.pattern).await?;
+        if !self.tags.is_empty() {
+            statuses.retain(|status| status.tags.iter().any(|tag| self.tags.contains(tag)));
+        }
+
+        Ok(statuses)
+    }
+
+    /// Checks the applied migration files are unchanged and still present, and the pending ones correctly ordered,
+    /// without applying anything nor taking the migrations lock (e.g. in the health check of an application). The
+    /// deviations are all reported, `VerifyReport::is_ok` telling whether the `policy` makes any of them an error.
+    pub async fn verify(&self) -> Result<VerifyReport, Error> {
+        self.check_table_name()?;
+
+        run_verify(self.db, self.source.as_ref(), &self.table, &self.pattern, self.policy).await
+    }
+
+    /// Returns the applied migrations in the order they were applied, with their execution metadata, followed
+    /// by the pending ones.
+    pub async fn history(&self) -> Result<Vec<MigrationStatus>, Error> {
+        self.check_table_name()?;
+
+        read_history(self.db, self.source.as_ref(), &self.table, &self.pattern).await
+    }
+
+    /// Rolls back applied migrations in reverse order by executing their paired `.down.surql` files,
+    /// then removes them from the migrations table.
+    pub async fn rollback(&self, target: RollbackTarget) -> Result<(), Error> {
+        self.check_table_name()?;
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = run_rollback(self.db, self.source.as_ref(), &self.table, target, &self.vars).await;
+        lock.release().await?;
+
+        result
+    }
+
+    /// Rolls back the last `steps` applied migrations, then applies them again (e.g. to iterate on the newest
+    /// migration in development, its file being read again).
+    pub async fn redo(self, steps: usize) -> Result<MigrationReport, Error> {
+        self.check_table_name()?;
+
+        let applied = get_migrations(self.db, &self.table).await?.len();
+        self.rollback(RollbackTarget::Steps(steps)).await?;
+
+        self.target(ApplyTarget::Count(steps.min(applied))).run().await
+    }
+
+    /// Records the pending migrations up to the given version included (e.g. `"004"`) as applied, without
+    /// executing them, to adopt the migrations on a database that already has their schema.
+    pub async fn baseline(&self, to: &str) -> Result<Vec<String>, Error> {
+        self.check_table_name()?;
+        self.setup_migration_table().await?;
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = self.record_pending(&ApplyTarget::To(to.to_string())).await;
+        lock.release().await?;
+
+        result
+    }
+
+    /// Records the pending migrations selected by the `target` as applied, with their checksum, without executing
+    /// them (or only returns them, in dry run mode), e.g. on a database migrated by hand or restored from a snapshot
+    /// of another environment. The repeatable files are left pending.
+    pub async fn fake(&self) -> Result<Vec<String>, Error> {
+        self.check_table_name()?;
+
+        if self.dry_run {
+            let (pending, _) = self.select_unexecuted(&self.target).await?;
+
+            return Ok(pending.into_iter().map(|entry| entry.filename).collect());
+        }
+
+        self.setup_migration_table().await?;
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = self.record_pending(&self.target).await;
+        lock.release().await?;
+
+        result
+    }
+
+    /// Collapses the applied migration files of a directory up to the given version included (e.g. `"004"`) into
+    /// a single baseline file (e.g. `004_squashed.surql`) recorded as applied in their place, and moves them with
+    /// their `.down` files to its `.archive` subdirectory (or only reports them, in dry run mode). The files are
+    /// read from the directory rather than the source, as they are rewritten.
+    pub async fn squash(&self, dir: impl AsRef<Path>, to: &str) -> Result<SquashReport, Error> {
+        self.check_table_name()?;
+
+        let options = SquashOptions {
+            dir: dir.as_ref(),
+            table: &self.table,
+            pattern: &self.pattern,
+            to,
+            applied_by: &self.applied_by,
+            algorithm: self.checksum_algorithm,
+            dry_run: self.dry_run,
+        };
+
+        if self.dry_run {
+            return run_squash(self.db, &options).await;
+        }
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = run_squash(self.db, &options).await;
+        lock.release().await?;
+
+        result
+    }
+
+    /// Reconciles the migrations table with the migration files: updates the stored checksums to match the
+    /// current files, removes the records of missing files and the duplicated records (or only reports them,
+    /// in dry run mode).
+    pub async fn repair(&self) -> Result<RepairReport, Error> {
+        self.check_table_name()?;
+
+        if self.dry_run {
+            return run_repair(self.db, self.source.as_ref(), &self.table, &self.pattern, self.checksum_algorithm, true).await;
+        }
+
+        self.setup_migration_table().await?;
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = run_repair(self.db, self.source.as_ref(), &self.table, &self.pattern, self.checksum_algorithm, false).await;
+        lock.release().await?;
+
+        result
+    }
+
+    /// Removes the records of the applied migrations whose file has been deleted on purpose, which otherwise make
+    /// the runs fail with `Error::MissingMigration`, returning their filenames (or only returns them, in dry run
+    /// mode).
+    pub async fn prune(&self) -> Result<Vec<String>, Error> {
+        self.check_table_name()?;
+
+        if self.dry_run {
+            return run_prune(self.db, self.source.as_ref(), &self.table, &self.pattern, true).await;
+        }
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = run_prune(self.db, self.source.as_ref(), &self.table, &self.pattern, false).await;
+        lock.release().await?;
+
+        result
+    }
+
+    /// Returns the records of the migrations table in the order they were applied, to restore them on another
+    /// instance with `import_ledger`.
+    pub async fn export_ledger(&self) -> Result<MigrationLedger, Error> {
+        self.check_table_name()?;
+
+        run_export_ledger(self.db, &self.table).await
+    }
+
+    /// Replaces the records of the migrations table with the ones of a ledger exported by `export_ledger` (e.g.
+    /// after restoring a snapshot of the database exported along with it), returning how many were imported (or
+    /// only returns it, in dry run mode). The migration files are not read nor executed.
+    pub async fn import_ledger(&self, ledger: &MigrationLedger) -> Result<usize, Error> {
+        self.check_table_name()?;
+
+        if self.dry_run {
+            return Ok(ledger.migrations.len());
+        }
+
+        self.setup_migration_table().await?;
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = run_import_ledger(self.db, &self.table, ledger).await;
+        lock.release().await?;
+
+        result.map(|_| ledger.migrations.len())
+    }
+
+    /// Removes the resources (tables, fields, indexes, events, functions, params and analyzers) defined by the
+    /// applied migration files, then the migrations table. The data unrelated to the migrations is kept.
+    pub async fn reset(&self) -> Result<(), Error> {
+        self.check_table_name()?;
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = run_reset(self.db, self.source.as_ref(), &self.table, &self.vars).await;
+        lock.release().await?;
+
+        result
+    }
+
+    /// Removes every table (with its records), function, param and analyzer of the database, except the migrations
+    /// table and the tables tracking it (lock, audit trail and progress of the unfinished migrations), and returns the
+    /// removed resources, e.g. to bring a local database back to empty without removing it. The applied migrations
+    /// stay recorded: use `reset` to apply them again.
+    pub async fn clean(&self) -> Result<Vec<SchemaDefinition>, Error> {
+        self.check_table_name()?;
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = run_clean(self.db, &self.migration_tables()).await;
+        lock.release().await?;
+
+        result
+    }
+
+    /// Applies the new or changed seed files of the seeds directory, then the ones of its `<env>` subdirectory
+    /// (e.g. `seeds/dev/users.surql`). Each file is tracked in the seeds table with its checksum, so it is only
+    /// applied again once changed: seed files should be idempotent (e.g. `UPSERT` rather than `CREATE`).
+    pub async fn seed(&self, env: Option<&str>) -> Result<SeedReport, Error> {
+        self.check_table_name()?;
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = run_seeds(self.db, &self.seeds_dir, env, &self.seeds_table, &self.vars, &self.pattern).await;
+        lock.release().await?;
+
+        result
+    }
+
+    /// Executes the assertion files of the assertions directory (e.g. `tests/001_assert.surql`), sorted by filename,
+    /// each passing when its last statement returns `true`, e.g. to check the data once the pending migrations are
+    /// applied to a clone of the production database.
+    pub async fn run_assertions(&self) -> Result<AssertionReport, Error> {
+        run_assertions(self.db, &self.assertions_dir, &self.vars, &self.pattern).await
+    }
+
+    /// Replaces the content of the database (including the migrations table) with an export of it, e.g. written by
+    /// `Surreal::export` before rehearsing the migrations. The users and access methods defined since the export
+    /// are left as they are.
+    pub async fn restore(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        self.check_table_name()?;
+
+        let lock = MigrationLock::acquire(self.db, &self.table, self.lock_timeout).await?;
+        let result = run_restore(self.db, &self.table, path.as_ref()).await;
+        lock.release().await?;
+
+        result
+    }
+
+    /// Removes the migrations lock whoever holds it (e.g. a lock left by a crashed migrator).
+    pub async fn force_unlock(&self) -> Result<(), Error> {
+        self.check_table_name()?;
+
+        MigrationLock::force_unlock(self.db, &self.table).await
+    }
+
+    /// Replays every migration file into an in-memory database and returns the resulting schema.
+    #[cfg(feature = "kv-mem")]
+    async fn expected_schema(&self) -> Result<Vec<SchemaDefinition>, Error> {
+        let shadow = Surreal::new::<Mem>(()).await?;
+        shadow.use_ns("ssm").use_db("shadow").await?;
+
+        let _ = Migrator::new(&shadow)
+            .source(self.source.as_ref())
+            .file_pattern(self.pattern.clone())
+            .table(self.table.as_str())
+            .allow_out_of_order(true)
+            .vars(self.vars.clone())
+            .run()
+            .await?;
+
+        read_schema(&shadow, &self.internal_tables()).await
+    }
+
+    /// The migrations table and the tables tracking its runs: the lock, the audit trail and the progress of the
+    /// unfinished migrations, resumed by the next run.
+    fn migration_tables(&self) -> Vec<String> {
+        vec![
+            self.table.clone(),
+            lock_table(&self.table),
+            audit_table(&self.table),
+            progress_table(&self.table),
+        ]
+    }
+
+    /// The tables managed by the migrator itself, which are not part of the migrated schema.
+    #[cfg(feature = "kv-mem")]
+    fn internal_tables(&self) -> Vec<String> {
+        let mut tables = self.migration_tables();
+        tables.push(self.seeds_table.clone());
+
+        tables
+    }
+
+    /// Ensures the table names can safely be used in the queries.
+    fn check_table_name(&self) -> Result<(), Error> {
+        let regex = Regex::new(r"^[a-zA-Z_][a-zA-Z_0-9]*$").expect("Failed to build the regexp");
+        for (kind, table) in [("migrations", &self.table), ("seeds", &self.seeds_table)] {
+            if !regex.is_match(table) {
+                return Err(
+                    Error::InvalidName {
+                        name: table.clone(),
+                        message: format!("The {} table name '{}' must only contain letters, numbers and underscores.", kind, table),
+                    }
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies every migration file to the shadow database, from zero, then switches back to the database.
+    /// The replay goes through the session of `self.db`, which every clone of the handle shares: they all point
+    /// at the shadow database until it is done, and back at the database on every path (failed replay, error or
+    /// cancelled run).
+    async fn replay_shadow(&self, shadow: &str) -> Result<(), Error> {
+        let session = define_shadow_database(self.db, shadow).await?;
+
+        let replayed = async {
+            self.db.use_db(shadow).await?;
+
+            let mut migrator = Migrator::new(self.db)
+                .source(self.source.as_ref())
+                .file_pattern(self.pattern.clone())
+                .table(self.table.as_str())
+                .vars(self.vars.clone())
+                .checksum_algorithm(self.checksum_algorithm)
+                .idempotent(self.idempotent)
+                .preflight(false);
+            if let Some(ref environment) = self.environment {
+                migrator = migrator.environment(environment.as_str());
+            }
+
+            // Boxed, as `run` replays the shadow database itself.
+            Box::pin(migrator.run()).await.map(|_| ())
+        }
+        .await;
+
+        remove_shadow_database(session, shadow, replayed).await
+    }
+
+    /// Defines the namespace and the database of the session, if any, when they do not exist.
+    async fn define_ns_db(&self) -> Result<(), Error> {
+        let (namespace, database): (Option<String>, Option<String>) = self.db
+            .query("RETURN session::ns(); RETURN session::db();")
+            .await?
+            .check()
+            .and_then(|mut response| Ok((response.take(0)?, response.take(1)?)))?;
+
+        let (Some(namespace), Some(database)) = (namespace, database) else {
+            return Ok(());
+        };
+
+        // The names are escaped, so any name without a backtick can be defined.
+        for name in [&namespace, &database] {
+            if name.contains('`') {
+                return Err(
+                    Error::InvalidName {
+                        name: name.clone(),
+                        message: format!("The namespace or database name '{}' must not contain a backtick.", name),
+                    }
+                );
+            }
+        }
+
+        let _ = self.db
+            .query(format!("DEFINE NAMESPACE IF NOT EXISTS `{}`;\nDEFINE DATABASE IF NOT EXISTS `{}`;", namespace, database))
+            .await?
+            .check()?;
+
+        debug!(%namespace, %database, "Namespace and database defined");
+
+        Ok(())
+    }
+
+    async fn setup_migration_table(&self) -> Result<(), surrealdb::Error> {
+        // The `created_at` field is computed when a record is created, unless given (e.g. by a ledger import), and
+        // kept when it is updated (e.g. by a repair): the applied migrations are ordered by it.
+        let sql = format!(
+            r#"
+                DEFINE TABLE IF NOT EXISTS {table} SCHEMAFULL;
+                DEFINE FIELD IF NOT EXISTS filename ON TABLE {table} TYPE string;
+                DEFINE FIELD IF NOT EXISTS checksum ON TABLE {table} TYPE option<string>;
+                DEFINE FIELD OVERWRITE created_at ON TABLE {table} TYPE datetime VALUE $before OR $value OR time::now();
+                DEFINE FIELD IF NOT EXISTS execution_time_ms ON TABLE {table} TYPE option<int>;
+                DEFINE FIELD IF NOT EXISTS applied_by ON TABLE {table} TYPE option<string>;
+                DEFINE FIELD IF NOT EXISTS tool_version ON TABLE {table} TYPE option<string>;
+                DEFINE FIELD IF NOT EXISTS repeatable ON TABLE {table} TYPE option<bool>;
+                DEFINE FIELD IF NOT EXISTS out_of_order ON TABLE {table} TYPE option<bool>;
+                DEFINE FIELD IF NOT EXISTS baseline ON TABLE {table} TYPE option<bool>;
+                DEFINE FIELD IF NOT EXISTS skipped ON TABLE {table} TYPE option<bool>;
+                DEFINE FIELD IF NOT EXISTS checksum_algorithm ON TABLE {table} TYPE option<string>;
+            "#,
+            table = self.table
+        );
+
+        let _ = self.db
+            .query(sql)
+            .await?
+            .check()?;
+
+        if self.audit {
+            let sql = format!(
+                r#"
+                    DEFINE TABLE IF NOT EXISTS {table} SCHEMAFULL;
+                    DEFINE FIELD IF NOT EXISTS filename ON TABLE {table} TYPE string;
+                    DEFINE FIELD IF NOT EXISTS checksum ON TABLE {table} TYPE string;
+                    DEFINE FIELD IF NOT EXISTS sql ON TABLE {table} TYPE string;
+                    DEFINE FIELD IF NOT EXISTS applied_by ON TABLE {table} TYPE option<string>;
+                    DEFINE FIELD IF NOT EXISTS created_at ON TABLE {table} TYPE datetime VALUE time::now();
+                "#,
+                table = audit_table(&self.table)
+            );
+
+            let _ = self.db
+                .query(sql)
+                .await?
+                .check()?;
+        }
+
+        Ok(())
+    }
+
+    async fn run_migration_files(&self) -> Result<MigrationReport, Error> {
+        let db = self.db;
+        let mut report = MigrationReport::default();
+
+        // Get the files already processed.
+        let migrations = self.retry.run(|_| get_migrations(db, &self.table)).await?;
+
+        debug!(migrations = ?migrations.iter().map(|migration| &migration.filename).collect::<Vec<_>>(), "Migrated files");
+
+        // Get the surql migration files to execute.
+        let entries = read_migration_files(self.source.as_ref(), &self.pattern).await?;
+
+        debug!(files = ?entries.iter().map(|entry| &entry.filename).collect::<Vec<_>>(), "Migration files");
+
+        // Check for forbidden updates and removals before migrating anything.
+        let pending = self.select_pending(check_migration_files(&migrations, &entries, &self.policy, &mut report.warnings)?);
+        check_trailing_semicolons(&pending, &mut report.warnings);
+        check_modification_times(self.source.as_ref(), &migrations, &entries, &mut report.warnings).await?;
+
+        // Migrations applied before checksums were recorded, or recorded with another checksum algorithm (verified
+        // above with theirs): store the current ones.
+        let algorithm = self.checksum_algorithm;
+        let outdated = migrations.iter().filter(|migration| {
+            migration.checksum.is_none() || migration.checksum_algorithm.as_deref().unwrap_or("sha256") != algorithm.name()
+        });
+        for migration in outdated {
+            // The changed files allowed by the policy keep their checksum, so they are reported on every run.
+            if let Some(entry) = entries.iter().find(|entry| migration == &entry.filename && entry.matches(migration)) {
+                self.retry
+                    .run(|_| async {
+                        let _ = db
+                            .query(format!(
+                                "UPDATE {} SET checksum=$checksum, checksum_algorithm=$checksum_algorithm WHERE filename=$filename;",
+                                self.table
+                            ))
+                            .bind(("filename", entry.filename.clone()))
+                            .bind(("checksum", entry.checksum_with(algorithm)))
+                            .bind(("checksum_algorithm", algorithm.name()))
+                            .await?
+                            .check()?;
+
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+
+        // The new or changed repeatable files run after the pending ones.
+        let (repeatable, unchanged) = self.retry.run(|_| self.resolve_repeatable_files()).await?;
+
+        let last_migration = last_applied_migration(&migrations).cloned();
+        report.skipped = migrations.into_iter().map(|migration| migration.filename).chain(unchanged).collect();
+
+        let filenames: Vec<String> = pending
+            .iter()
+            .chain(repeatable.iter())
+            .map(|entry| entry.filename.clone())
+            .collect();
+        self.emit_discovered(&filenames, &report.skipped);
+
+        self.check_plan(pending.iter().chain(repeatable.iter()).filter(|entry| self.applies(entry)))?;
+        check_server_version(db, &self.retry, pending.iter().chain(repeatable.iter()).filter(|entry| self.applies(entry))).await?;
+        if self.preflight && pending.iter().chain(repeatable.iter()).any(|entry| self.applies(entry)) {
+            self.retry.run(|_| check_permissions(db, &self.table)).await?;
+        }
+
+        run_all(&self.hooks.before_all, db, &filenames).await?;
+
+        // Migrate the pending files, then the repeatable files.
+        let entries = pending
+            .into_iter()
+            .map(|entry| {
+                let kind = if is_out_of_order(&entry, last_migration.as_ref()) {
+                    MigrationKind::OutOfOrder
+                } else {
+                    MigrationKind::Versioned
+                };
+                (entry, kind)
+            })
+            .chain(repeatable.into_iter().map(|entry| (entry, MigrationKind::Repeatable)));
+
+        if self.single_transaction {
+            if self.is_cancelled() {
+                warn!("Migration run cancelled");
+                report.cancelled = true;
+                return Ok(report);
+            }
+
+            let (entries, excluded): (Vec<_>, Vec<_>) = entries.partition(|(entry, _)| self.applies(entry));
+            for (entry, kind) in excluded {
+                report.excluded.push(self.record_excluded(entry, kind).await?);
+            }
+
+            report.applied = self.apply_in_single_transaction(entries).await?;
+            run_all(&self.hooks.after_all, db, &filenames).await?;
+
+            return Ok(report);
+        }
+
+        let mut entries = entries.peekable();
+        while let Some((entry, kind)) = entries.next() {
+            if self.is_cancelled() {
+                warn!(next = %entry.filename, "Migration run cancelled");
+                report.cancelled = true;
+                return Ok(report);
+            }
+
+            if !self.applies(&entry) {
+                report.excluded.push(self.record_excluded(entry, kind).await?);
+                continue;
+            }
+
+            // The next files are applied with this one in a single round trip, as long as they can be.
+            let mut batch = vec![(entry, kind)];
+            if self.is_batchable(&batch[0].0) {
+                while batch.len() < self.batch_size {
+                    match entries.next_if(|(entry, _)| self.applies(entry) && self.is_batchable(entry)) {
+                        Some(next) => batch.push(next),
+                        None => break,
+                    }
+                }
+            }
+
+            if batch.len() > 1 {
+                self.apply_batch(batch, &mut report).await?;
+            } else {
+                for (entry, kind) in batch {
+                    self.apply_file(entry, kind, &mut report).await?;
+                }
+            }
+        }
+
+        run_all(&self.hooks.after_all, db, &filenames).await?;
+
+        Ok(report)
+    }
+
+    /// Applies a migration file, reporting it as applied, or as failed in `continue_on_error` mode.
+    async fn apply_file(&self, entry: MigrationFile, kind: MigrationKind, report: &mut MigrationReport) -> Result<(), Error> {
+        if kind == MigrationKind::OutOfOrder && self.policy.out_of_order == PolicyAction::Warn {
+            warn!(filename = %entry.filename, "Applying the migration file out of order");
+        }
+
+        let filename = entry.filename.clone();
+        match self.apply_migration(entry, kind).await {
+            Ok(migration) => report.applied.push(migration),
+            Err(err) if self.continue_on_error => {
+                warn!(filename = %filename, "Continuing after the failure of the migration file");
+                report.failed.push(FailedMigration { filename, code: err.code(), error: err.to_string() });
+            },
+            Err(err) => return Err(err),
+        }
+
+        Ok(())
+    }
+
+    /// Fails unless the migration files to apply are the ones of the expected plan, if any.
+    fn check_plan<'f>(&self, entries: impl IntoIterator<Item = &'f MigrationFile>) -> Result<(), Error> {
+        let Some(expected) = &self.expected_plan else {
+            return Ok(());
+        };
+
+        let actual = MigrationPlan {
+            version: expected.version,
+            migrations: entries
+                .into_iter()
+                .map(|entry| PlannedMigration {
+                    filename: entry.filename.clone(),
+                    checksum: entry.checksum_with(self.checksum_algorithm),
+                })
+                .collect(),
+        };
+
+        let filenames = expected.differences(&actual);
+        if !filenames.is_empty() {
+            error!(filenames = ?filenames, "The pending migrations differ from the approved plan");
+            return Err(Error::PlanMismatch {
+                filenames,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether a migration file can be applied in a batch with others (see `batch_size`).
+    fn is_batchable(&self, entry: &MigrationFile) -> bool {
+        let directives = Directives::parse(&entry.content);
+
+        self.batch_size > 1
+            && self.hooks.before_each.is_empty()
+            && self.hooks.after_each.is_empty()
+            && self.migration_timeout.is_none()
+            && !entry.is_streamed()
+            && !(directives.no_transaction || directives.data)
+    }
+
+    /// Applies consecutive migration files in a single query and transaction, recording each of them. When it
+    /// fails, nothing is applied, so the files are applied again one at a time, up to the failing one.
+    async fn apply_batch(&self, batch: Vec<(MigrationFile, MigrationKind)>, report: &mut MigrationReport) -> Result<(), Error> {
+        for (entry, kind) in &batch {
+            if *kind == MigrationKind::OutOfOrder && self.policy.out_of_order == PolicyAction::Warn {
+                warn!(filename = %entry.filename, "Applying the migration file out of order");
+            }
+        }
+
+        // The checksum stays the one of the file, whatever the values of its variables.
+        let files: Result<Vec<(MigrationFile, MigrationKind)>, Error> = batch
+            .iter()
+            .map(|(entry, kind)| {
+                self.prepare(&entry.content).map(|content| (MigrationFile { content, ..entry.clone() }, *kind))
+            })
+            .collect();
+
+        if let Ok(ref files) = files {
+            for (entry, _) in files {
+                debug!(filename = %entry.filename, sql = %entry.content, "Executing the migration file");
+            }
+        }
+
+        let started_at = Instant::now();
+        let result = match files {
+            Ok(ref files) => self.retry
+                .run(|attempt| async move {
+                    // The connection may have dropped once the transaction was committed, recording every file.
+                    if attempt > 0 && self.is_recorded(&files[0].0).await? {
+                        return Ok(());
+                    }
+
+                    self.execute_migrations(files).await
+                })
+                .await,
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = result {
+            warn!(error = %err, files = batch.len(), "Failed to migrate the batch of files, applying them one at a time");
+            for (entry, kind) in batch {
+                self.apply_file(entry, kind, report).await?;
+            }
+            return Ok(());
+        }
+
+        // The duration is the one of the whole batch.
+        let duration = started_at.elapsed();
+        info!(?duration, files = batch.len(), "Files successfully migrated in a single query");
+
+        for (entry, _) in batch {
+            emit(&self.events, MigrationEvent::Started { filename: entry.filename.clone() });
+            emit(&self.events, MigrationEvent::Applied { filename: entry.filename.clone(), duration });
+            report.applied.push(ExecutedMigration { filename: entry.filename, duration, rows: None });
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether a migration file is applied in the environment of the migrator.
+    fn applies(&self, entry: &MigrationFile) -> bool {
+        Directives::parse(&entry.content).applies_to(self.environment.as_deref())
+    }
+
+    /// Returns whether a migration file has one of the `tags`, if any.
+    fn is_tagged(&self, entry: &MigrationFile) -> bool {
+        Directives::parse(&entry.content).has_any_tag(&self.tags)
+    }
+
+    /// Keeps the pending migration files having one of the `tags`, then the ones selected by the `target`.
+    fn select_pending(&self, pending: Vec<MigrationFile>) -> Vec<MigrationFile> {
+        self.target.select(pending.into_iter().filter(|entry| self.is_tagged(entry)).collect())
+    }
+
+    /// Records a migration file for other environments as skipped, without executing it.
+    async fn record_excluded(&self, entry: MigrationFile, kind: MigrationKind) -> Result<String, Error> {
+        self.retry
+            .run(|_| async {
+                let _ = self.db
+                    .query(self.record_statements(kind, "", true))
+                    .bind(("filename", entry.filename.clone()))
+                    .bind(("checksum", entry.checksum_with(self.checksum_algorithm)))
+                    .bind(("checksum_algorithm", self.checksum_algorithm.name()))
+                    .bind(("applied_by", self.applied_by.clone()))
+                    .bind(("tool_version", TOOL_VERSION))
+                    .await?
+                    .check()?;
+
+                Ok(())
+            })
+            .await?;
+
+        info!(filename = %entry.filename, environment = ?self.environment, "File for other environments recorded as skipped");
+        emit(&self.events, MigrationEvent::Skipped { filename: entry.filename.clone() });
+
+        Ok(entry.filename)
+    }
+
+    /// Returns the SQL of a migration file to execute: its variables substituted, and its definitions made
+    /// idempotent when asked.
+    fn prepare(&self, content: &str) -> Result<String, Error> {
+        let content = substitute(content, &self.vars)?;
+
+        Ok(if self.idempotent { make_idempotent(&content) } else { content })
+    }
+
+    /// Returns whether the cancellation token, if any, is cancelled.
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(|token| token.is_cancelled())
+    }
+
+    /// Returns the pending migrations selected by a target and the `tags`, split between the ones applied in the
+    /// environment of the migrator and the ones for other environments, as a run would.
+    async fn select_unexecuted(&self, target: &ApplyTarget) -> Result<(Vec<MigrationFile>, Vec<MigrationFile>), Error> {
+        let migrations = get_migrations(self.db, &self.table).await?;
+        let entries = read_migration_files(self.source.as_ref(), &self.pattern).await?;
+        let pending = check_migration_files(&migrations, &entries, &self.policy, &mut vec![])?;
+
+        Ok(target
+            .select(pending.into_iter().filter(|entry| self.is_tagged(entry)).collect())
+            .into_iter()
+            .partition(|entry| self.applies(entry)))
+    }
+
+    /// Records the pending migrations selected by a target as applied without executing them, marked as baseline,
+    /// and the ones for other environments as skipped. They are recorded in a single transaction, retried as a whole
+    /// on a transient failure, so none of them is recorded unless every one is.
+    async fn record_pending(&self, target: &ApplyTarget) -> Result<Vec<String>, Error> {
+        let (pending, excluded) = self.select_unexecuted(target).await?;
+        let files: Vec<(&MigrationFile, bool)> = pending
+            .iter()
+            .map(|entry| (entry, false))
+            .chain(excluded.iter().map(|entry| (entry, true)))
+            .collect();
+        if files.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut sql = "BEGIN TRANSACTION;\n".to_string();
+        for (index, (_, skipped)) in files.iter().enumerate() {
+            sql.push_str(&format!(
+                "CREATE {table} SET filename=$filename_{index}, checksum=$checksum_{index}, checksum_algorithm=$checksum_algorithm, \
+                 applied_by=$applied_by, tool_version=$tool_version, {flag}=true;\n",
+                table = self.table,
+                index = index,
+                flag = if *skipped { "skipped" } else { "baseline" }
+            ));
+        }
+        sql.push_str("COMMIT TRANSACTION;");
+
+        self.retry
+            .run(|_| async {
+                let mut query = self.db
+                    .query(sql.as_str())
+                    .bind(("checksum_algorithm", self.checksum_algorithm.name()))
+                    .bind(("applied_by", self.applied_by.clone()))
+                    .bind(("tool_version", TOOL_VERSION));
+                for (index, (entry, _)) in files.iter().enumerate() {
+                    query = query
+                        .bind((format!("filename_{}", index), entry.filename.clone()))
+                        .bind((format!("checksum_{}", index), entry.checksum_with(self.checksum_algorithm)));
+                }
+
+                let _ = query.await?.check()?;
+
+                Ok(())
+            })
+            .await?;
+
+        for entry in &excluded {
+            info!(filename = %entry.filename, environment = ?self.environment, "File for other environments recorded as skipped");
+            emit(&self.events, MigrationEvent::Skipped { filename: entry.filename.clone() });
+        }
+        for entry in &pending {
+            info!(filename = %entry.filename, "File recorded as applied");
+        }
+
+        Ok(pending.into_iter().map(|entry| entry.filename).collect())
+    }
+
+    /// Returns the repeatable migration files never applied or changed since their last application,
+    /// and the names of the unchanged ones.
+    async fn resolve_repeatable_files(&self) -> Result<(Vec<MigrationFile>, Vec<String>), Error> {
+        // The repeatable files run after every pending file, so not when only some of them are applied.
+        if self.target != ApplyTarget::All {
+            return Ok((vec![], vec![]));
+        }
+
+        let migrations = get_repeatable_migrations(self.db, &self.table).await?;
+        let entries = read_repeatable_files(self.source.as_ref(), &self.pattern).await?;
+
+        let (unchanged, pending): (Vec<MigrationFile>, Vec<MigrationFile>) = entries
+            .into_iter()
+            .partition(|entry| {
+                migrations
+                    .iter()
+                    .any(|migration| migration == &entry.filename && migration.checksum.as_ref() == Some(&entry.checksum_for(migration)))
+            });
+        let pending = pending.into_iter().filter(|entry| self.is_tagged(entry)).collect();
+
+        Ok((pending, unchanged.into_iter().map(|entry| entry.filename).collect()))
+    }
+
+    /// Reports the migration files to apply, then the ones already applied.
+    fn emit_discovered(&self, filenames: &[String], skipped: &[String]) {
+        emit(&self.events, MigrationEvent::Discovered { filenames: filenames.to_vec() });
+        for filename in skipped {
+            emit(&self.events, MigrationEvent::Skipped { filename: filename.clone() });
+        }
+    }
+
+    /// Applies a migration file, reporting when it starts and whether it is applied or failed.
+    async fn apply_migration(&self, entry: MigrationFile, kind: MigrationKind) -> Result<ExecutedMigration, Error> {
+        let filename = entry.filename.clone();
+        emit(&self.events, MigrationEvent::Started { filename: filename.clone() });
+
+        match self.migrate_file(entry, kind).await {
+            Ok(migration) => {
+                emit(&self.events, MigrationEvent::Applied { filename, duration: migration.duration });
+                Ok(migration)
+            },
+            Err(err) => {
+                emit(&self.events, MigrationEvent::Failed { filename, error: err.to_string() });
+                Err(err)
+            },
+        }
+    }
+
+    /// Applies a migration file and records it in the migrations table.
+    #[instrument(name = "migration", skip_all, fields(filename = %entry.filename))]
+    async fn migrate_file(&self, entry: MigrationFile, kind: MigrationKind) -> Result<ExecutedMigration, Error> {
+        let db = self.db;
+
+        if let Err(err) = run_each(&self.hooks.before_each, db, &entry.filename).await {
+            error!(error = %err, "The before_each hook failed");
+            return Err(err);
+        }
+
+        let started_at = Instant::now();
+
+        // The checksum stays the one of the file, whatever the values of its variables.
+        let entry = match self.prepare(&entry.content) {
+            Ok(content) => MigrationFile { content, ..entry },
+            Err(err) => {
+                error!(error = %err, "Failed to migrate the file");
+                return Err(err);
+            },
+        };
+
+        debug!(sql = %entry.content, "Executing the migration file");
+
+        let directives = Directives::parse(&entry.content);
+        let execution = async {
+            if directives.data && !entry.is_streamed() {
+                // Resumed from its last committed batch by the next run rather than retried.
+                let batch_size = directives.batch_size.unwrap_or(DEFAULT_DATA_BATCH_SIZE);
+                self.execute_data_migration(&entry, kind, batch_size).await.map(Some)
+            } else if directives.no_transaction || entry.is_streamed() {
+                // The statements applied before a failure would be applied again: never retry.
+                self.execute_migration(&entry, kind).await.map(|_| None)
+            } else {
+                let entry = &entry;
+                self.retry
+                    .run(|attempt| async move {
+                        // The connection may have dropped once the transaction was committed.
+                        if attempt > 0 && self.is_recorded(entry).await? {
+                            return Ok(());
+                        }
+
+                        self.execute_migration(entry, kind).await
+                    })
+                    .await
+                    .map(|_| None)
+            }
+        };
+
+        let result = match self.migration_timeout {
+            Some(duration) => timeout(duration, execution)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(Error::Timeout { filename: Some(entry.filename.clone()), duration })
+                }),
+            None => execution.await,
+        };
+
+        let rows = match result {
+            Ok(rows) => rows,
+            Err(err) => {
+                error!(error = %err, "Failed to migrate the file");
+                return Err(err);
+            },
+        };
+
+        let duration = started_at.elapsed();
+
+        // The duration is only known once the file is applied.
+        self.retry
+            .run(|_| async {
+                let _ = db
+                    .query(format!("UPDATE {} SET execution_time_ms=$execution_time_ms WHERE filename=$filename;", self.table))
+                    .bind(("filename", entry.filename.clone()))
+                    .bind(("execution_time_ms", duration.as_millis() as u64))
+                    .await?
+                    .check()?;
+
+                Ok(())
+            })
+            .await?;
+
+        info!(?duration, "File successfully migrated");
+
+        if let Err(err) = run_each(&self.hooks.after_each, db, &entry.filename).await {
+            error!(error = %err, "The after_each hook failed");
+            return Err(err);
+        }
+
+        Ok(ExecutedMigration {
+            filename: entry.filename,
+            duration,
+            rows,
+        })
+    }
+
+    /// Returns whether a migration file is recorded in the migrations table with its current checksum.
+    async fn is_recorded(&self, entry: &MigrationFile) -> Result<bool, Error> {
+        let migrations = self.db
+            .query(format!("SELECT * FROM {} WHERE filename=$filename AND checksum=$checksum;", self.table))
+            .bind(("filename", entry.filename.clone()))
+            .bind(("checksum", entry.checksum_with(self.checksum_algorithm)))
+            .await?
+            .check()?
+            .take::<Vec<Migration>>(0)?;
+
+        Ok(!migrations.is_empty())
+    }
+
+    /// Executes a migration file and records it in the migrations table, in a transaction unless the file
+    /// opts out with the `no-transaction` directive or is streamed.
+    async fn execute_migration(&self, entry: &MigrationFile, kind: MigrationKind) -> Result<(), Error> {
+        let db = self.db;
+        let directives = Directives::parse(&entry.content);
+        let record = self.record_statements(kind, "", false);
+
+        if entry.is_streamed() || directives.no_transaction {
+            define_progress_table(db, &self.table).await?;
+
+            let executed = if self.resume { self.resumed_statements(entry).await? } else { 0 };
+            let result = if entry.is_streamed() {
+                self.execute_streamed(entry, executed).await
+            } else {
+                self.execute_statements(entry, executed).await
+            };
+            if let Err(Error::StatementFailed { statement, .. }) = result {
+                let checksum = self.executed_checksum(entry, statement - 1).await?;
+                record_failed_statement(db, &self.table, &entry.filename, statement - 1, &checksum).await?;
+            }
+            result?;
+
+            let _ = db
+                .query(format!("{}\nDELETE type::thing($progress_table, $filename);", record))
+                .bind(("filename", entry.filename.clone()))
+                .bind(("checksum", entry.checksum_with(self.checksum_algorithm)))
+                .bind(("checksum_algorithm", self.checksum_algorithm.name()))
+                .bind(("sql", entry.content.clone()))
+                .bind(("applied_by", self.applied_by.clone()))
+                .bind(("tool_version", TOOL_VERSION))
+                .bind(("progress_table", progress_table(&self.table)))
+                .await?
+                .check()?;
+        } else {
+            // Apply the file and record it atomically: either both succeed or nothing is applied.
+            let sql = format!(
+                "BEGIN TRANSACTION;\n{}\n{}\nCOMMIT TRANSACTION;",
+                terminate_statements(&entry.content),
+                record
+            );
+
+            let response = db
+                .query(sql)
+                .bind(("filename", entry.filename.clone()))
+                .bind(("checksum", entry.checksum_with(self.checksum_algorithm)))
+                .bind(("checksum_algorithm", self.checksum_algorithm.name()))
+                .bind(("sql", entry.content.clone()))
+                .bind(("applied_by", self.applied_by.clone()))
+                .bind(("tool_version", TOOL_VERSION))
+                .await
+                .map_err(|err| query_error(entry, err, 1))?;
+            check_statements(entry, response)?;
+        }
+
+        Ok(())
+    }
+
+    /// Executes a data migration in batches, then records it and removes its progress in a transaction, returning
+    /// the records its batches went through.
+    async fn execute_data_migration(&self, entry: &MigrationFile, kind: MigrationKind, batch_size: usize) -> Result<u64, Error> {
+        let checksum = entry.checksum_with(self.checksum_algorithm);
+        let rows = run_data_migration(self.db, &self.table, entry, &checksum, batch_size, |batches, rows| {
+            emit(&self.events, MigrationEvent::Progress { filename: entry.filename.clone(), batches, rows });
+        })
+        .await?;
+
+        let sql = format!(
+            "BEGIN TRANSACTION;\n{}\nDELETE type::thing($progress_table, $filename);\nCOMMIT TRANSACTION;",
+            self.record_statements(kind, "", false)
+        );
+
+        let _ = self.db
+            .query(sql)
+            .bind(("filename", entry.filename.clone()))
+            .bind(("checksum", checksum))
+            .bind(("checksum_algorithm", self.checksum_algorithm.name()))
+            .bind(("sql", entry.content.clone()))
+            .bind(("applied_by", self.applied_by.clone()))
+            .bind(("tool_version", TOOL_VERSION))
+            .bind(("progress_table", progress_table(&self.table)))
+            .await?
+            .check()?;
+
+        Ok(rows)
+    }
+
+    /// Returns the statements of a migration file executed before it failed in a previous run, when they are still
+    /// its first statements.
+    async fn resumed_statements(&self, entry: &MigrationFile) -> Result<usize, Error> {
+        let Some((executed, checksum)) = read_failed_statement(self.db, &self.table, &entry.filename).await? else {
+            return Ok(0);
+        };
+
+        if self.executed_checksum(entry, executed).await? != checksum {
+            warn!(statements = executed, "The statements executed before the failure changed, the file is applied from the start");
+            return Ok(0);
+        }
+
+        info!(statements = executed, "Resuming the migration file from the failed statement");
+
+        Ok(executed)
+    }
+
+    /// Returns the checksum of the first `count` statements of a migration file, read again when streamed.
+    async fn executed_checksum(&self, entry: &MigrationFile, count: usize) -> Result<String, Error> {
+        if !entry.is_streamed() {
+            return Ok(statements_checksum(parse_statements(&entry.content).iter().take(count)));
+        }
+
+        let mut statements: Vec<Statement> = vec![];
+        if let Some(mut reader) = self.source.open(&entry.filename).await? {
+            let mut splitter = StatementSplitter::new();
+            let mut line = String::new();
+            while statements.len() < count && reader.read_line(&mut line).await? > 0 && !is_down_marker(&line) {
+                statements.extend(splitter.push(&line));
+                line.clear();
+            }
+            if statements.len() < count {
+                statements.extend(splitter.finish().0);
+            }
+        }
+
+        Ok(statements_checksum(statements.iter().take(count)))
+    }
+
+    /// Executes the statements of a migration file opting out of transactions after the first `executed` ones.
+    async fn execute_statements(&self, entry: &MigrationFile, executed: usize) -> Result<(), Error> {
+        let mut response = self.db
+            .query(skip_statements(&entry.content, executed))
+            .await
+            .map_err(|err| query_error(entry, err, 0))?;
+
+        match failed_statement(&mut response) {
+            Some((index, err)) => Err(statement_error(entry, executed + index, err)),
+            None => Ok(()),
+        }
+    }
+
+    /// Executes a migration file too large to be read at once, reading its up section a line at a time and sending
+    /// its statements after the first `executed` ones in batches of `STREAM_BATCH_SIZE`, so only a batch is held in
+    /// memory.
+    async fn execute_streamed(&self, entry: &MigrationFile, mut executed: usize) -> Result<(), Error> {
+        let Some(mut reader) = self.source.open(&entry.filename).await? else {
+            // Its checksum would not be the one of the file applied.
+            return Err(
+                Error::IO(
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("The migration file '{}' changed while being applied.", entry.filename)
+                    )
+                )
+            );
+        };
+
+        let mut splitter = StatementSplitter::new();
+        let mut batch: Vec<Statement> = vec![];
+        let mut skipped = 0;
+        let mut line = String::new();
+
+        // The statements executed by a previous run are read, but not executed again.
+        let to_skip = executed;
+        let mut keep = |statements: Vec<Statement>, batch: &mut Vec<Statement>| {
+            for statement in statements {
+                if skipped < to_skip {
+                    skipped += 1;
+                } else {
+                    batch.push(statement);
+                }
+            }
+        };
+
+        while reader.read_line(&mut line).await? > 0 {
+            if is_down_marker(&line) {
+                break;
+            }
+
+            keep(splitter.push(&line), &mut batch);
+            line.clear();
+
+            if batch.len() >= STREAM_BATCH_SIZE {
+                self.execute_batch(entry, &batch, executed).await?;
+                executed += batch.len();
+                batch.clear();
+            }
+        }
+
+        keep(splitter.finish().0.into_iter().collect(), &mut batch);
+        if !batch.is_empty() {
+            self.execute_batch(entry, &batch, executed).await?;
+        }
+
+        debug!(statements = executed + batch.len(), "Streamed the migration file");
+
+        Ok(())
+    }
+
+    /// Executes a batch of statements of a streamed migration file, given the number of statements executed
+    /// before it, reporting the failed statement with its position in the file.
+    async fn execute_batch(&self, entry: &MigrationFile, batch: &[Statement], executed: usize) -> Result<(), Error> {
+        let sql: String = batch.iter().map(|statement| format!("{};\n", statement.sql)).collect();
+
+        let mut response = match self.db.query(self.prepare(&sql)?).await {
+            Ok(response) => response,
+            Err(err) => {
+                let message = err.to_string();
+
+                // Locate the parse error from the line of the batch it points to.
+                return match error_line(&message) {
+                    Some(line) if message.contains("Parse error") => Err(
+                        Error::ParseError {
+                            filename: entry.filename.clone(),
+                            line: batch_line(batch, line),
+                            message,
+                        }
+                    ),
+                    _ => Err(err.into()),
+                };
+            },
+        };
+
+        match failed_statement(&mut response) {
+            Some((index, err)) => match batch.get(index) {
+                Some(statement) => Err(
+                    Error::StatementFailed {
+                        filename: entry.filename.clone(),
+                        statement: executed + index + 1,
+                        line: statement.line,
+                        sql: statement.sql.clone(),
+                        message: err.to_string(),
+                    }
+                ),
+                None => Err(err.into()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the statements recording a migration file in the migrations table (as skipped, for the files of
+    /// other environments) and, in audit mode, its executed `$sql` in the audit table, with its `$filename`,
+    /// `$checksum` and `$sql` parameters suffixed (e.g. `$filename_2`) to record several files in a single query.
+    /// Each statement is on its own line.
+    fn record_statements(&self, kind: MigrationKind, suffix: &str, skipped: bool) -> String {
+        let record = format!(
+            "CREATE {table} SET filename=$filename{suffix}, checksum=$checksum{suffix}, checksum_algorithm=$checksum_algorithm, applied_by=$applied_by, tool_version=$tool_version{kind}{skipped};",
+            table = self.table,
+            suffix = suffix,
+            kind = match kind {
+                MigrationKind::Versioned => "",
+                MigrationKind::OutOfOrder => ", out_of_order=true",
+                MigrationKind::Repeatable => ", repeatable=true",
+            },
+            skipped = if skipped { ", skipped=true" } else { "" }
+        );
+
+        // The files of other environments are not executed, so there is nothing to audit.
+        let record = if self.audit && !skipped {
+            format!(
+                "{record}\nCREATE {table} SET filename=$filename{suffix}, checksum=$checksum{suffix}, sql=$sql{suffix}, applied_by=$applied_by;",
+                record = record,
+                table = audit_table(&self.table),
+                suffix = suffix
+            )
+        } else {
+            record
+        };
+
+        if kind == MigrationKind::Repeatable {
+            // Replace the record of the previous application of the file.
+            format!("DELETE {} WHERE filename=$filename{};\n{}", self.table, suffix, record)
+        } else {
+            record
+        }
+    }
+
+    /// Applies the migration files and records them in a single transaction, reporting them as applied only once
+    /// every one of them is.
+    async fn apply_in_single_transaction(&self, entries: Vec<(MigrationFile, MigrationKind)>) -> Result<Vec<ExecutedMigration>, Error> {
+        let is_transactional = |entry: &MigrationFile| {
+            let directives = Directives::parse(&entry.content);
+            !directives.no_transaction && !directives.data && !entry.is_streamed()
+        };
+        if let Some((entry, _)) = entries.iter().find(|(entry, _)| !is_transactional(entry)) {
+            error!(filename = %entry.filename, "The migration file cannot be applied in a single transaction");
+            return Err(Error::NotTransactional { filename: entry.filename.clone() });
+        }
+
+        if entries.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut files: Vec<(MigrationFile, MigrationKind)> = vec![];
+        for (entry, kind) in entries {
+            run_each(&self.hooks.before_each, self.db, &entry.filename).await?;
+            emit(&self.events, MigrationEvent::Started { filename: entry.filename.clone() });
+
+            // The checksum stays the one of the file, whatever the values of its variables.
+            let content = self.prepare(&entry.content)?;
+            files.push((MigrationFile { content, ..entry }, kind));
+        }
+
+        let started_at = Instant::now();
+        let files = &files;
+        let result = self.retry
+            .run(|attempt| async move {
+                // The connection may have dropped once the transaction was committed, recording every file.
+                if attempt > 0 && self.is_recorded(&files[0].0).await? {
+                    return Ok(());
+                }
+
+                self.execute_migrations(files).await
+            })
+            .await;
+
+        if let Err(err) = result {
+            error!(error = %err, "Failed to migrate the files, none of them is applied");
+            for (entry, _) in files {
+                emit(&self.events, MigrationEvent::Failed { filename: entry.filename.clone(), error: err.to_string() });
+            }
+            return Err(err);
+        }
+
+        let duration = started_at.elapsed();
+        info!(?duration, files = files.len(), "Files successfully migrated in a single transaction");
+
+        let mut applied: Vec<ExecutedMigration> = vec![];
+        for (entry, _) in files {
+            emit(&self.events, MigrationEvent::Applied { filename: entry.filename.clone(), duration });
+            run_each(&self.hooks.after_each, self.db, &entry.filename).await?;
+
+            // The duration is the one of the whole transaction.
+            applied.push(ExecutedMigration { filename: entry.filename.clone(), duration, rows: None });
+        }
+
+        Ok(applied)
+    }
+
+    /// Executes the migration files and records them in the migrations table in a single transaction.
+    async fn execute_migrations(&self, files: &[(MigrationFile, MigrationKind)]) -> Result<(), Error> {
+        // Each file with the line of the query before its content and the index of its first statement.
+        let mut located: Vec<(&MigrationFile, usize, usize)> = vec![];
+        let mut sql = "BEGIN TRANSACTION;\n".to_string();
+        let (mut line, mut statement) = (1, 0);
+
+        for (index, (entry, kind)) in files.iter().enumerate() {
+            let content = terminate_statements(&entry.content);
+            let record = self.record_statements(*kind, &format!("_{}", index), false);
+            located.push((entry, line, statement));
+
+            sql.push_str(&format!("{}\n{}\n", content, record));
+            line += content.lines().count() + record.lines().count();
+            statement += parse_statements(&entry.content).len() + record.lines().count();
+        }
+        sql.push_str("COMMIT TRANSACTION;");
+
+        let mut query = self.db
+            .query(sql)
+            .bind(("checksum_algorithm", self.checksum_algorithm.name()))
+            .bind(("applied_by", self.applied_by.clone()))
+            .bind(("tool_version", TOOL_VERSION));
+        for (index, (entry, _)) in files.iter().enumerate() {
+            query = query
+                .bind((format!("filename_{}", index), entry.filename.clone()))
+                .bind((format!("checksum_{}", index), entry.checksum_with(self.checksum_algorithm)))
+                .bind((format!("sql_{}", index), entry.content.clone()));
+        }
+
+        let mut response = match query.await {
+            Ok(response) => response,
+            Err(err) => {
+                // Locate the parse errors in the file containing their line.
+                let error_line = error_line(&err.to_string()).unwrap_or(0);
+                return match located.iter().rev().find(|(_, line, _)| *line < error_line) {
+                    Some((entry, line, _)) => Err(query_error(entry, err, *line)),
+                    None => Err(err.into()),
+                };
+            },
+        };
+
+        match failed_statement(&mut response) {
+            Some((index, err)) => match located.iter().rev().find(|(_, _, statement)| *statement <= index) {
+                Some((entry, _, statement)) => Err(statement_error(entry, index - statement, err)),
+                None => Err(err.into()),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+/// Checks the response of the execution of a migration file, reporting the failed statement of the file
+/// with its position.
+pub(crate) fn check_statements(entry: &MigrationFile, response: Response) -> Result<(), Error> {
+    checked_statements(entry, response).map(|_| ())
+}
+
+/// Fails like `check_statements`, or returns the response to read the results of the statements.
+pub(crate) fn checked_statements(entry: &MigrationFile, mut response: Response) -> Result<Response, Error> {
+    match failed_statement(&mut response) {
+        Some((index, err)) => Err(statement_error(entry, index, err)),
+        None => Ok(response),
+    }
+}
+
+/// Returns the index and the error of the statement causing the failure of a query, if any.
+fn failed_statement(response: &mut Response) -> Option<(usize, surrealdb::Error)> {
+    let mut errors: Vec<(usize, surrealdb::Error)> = response.take_errors().into_iter().collect();
+    if errors.is_empty() {
+        return None;
+    }
+
+    errors.sort_by_key(|(index, _)| *index);
+
+    // In a failed transaction every statement fails: report the one causing the failure.
+    let position = errors
+        .iter()
+        .position(|(_, err)| !err.to_string().contains("failed transaction"))
+        .unwrap_or(0);
+
+    Some(errors.swap_remove(position))
+}
+
+/// Converts the failure of a statement of a migration file, given its index in the file.
+fn statement_error(entry: &MigrationFile, index: usize, err: surrealdb::Error) -> Error {
+    match parse_statements(&entry.content).get(index) {
+        Some(statement) => Error::StatementFailed {
+            filename: entry.filename.clone(),
+            statement: index + 1,
+            line: statement.line,
+            sql: statement.sql.clone(),
+            message: err.to_string(),
+        },
+        // Not a statement of the file (e.g. the record of the migration).
+        None => err.into(),
+    }
+}
+
+/// Converts the failure of the query of a migration file, locating its parse errors in the file. The `offset` is
+/// the number of lines sent before the content of the file (e.g. `BEGIN TRANSACTION;`).
+pub(crate) fn query_error(entry: &MigrationFile, err: surrealdb::Error, offset: usize) -> Error {
+    let message = err.to_string();
+
+    match error_line(&message) {
+        Some(line) if message.contains("Parse error") => Error::ParseError {
+            filename: entry.filename.clone(),
+            line: line.saturating_sub(offset),
+            message,
+        },
+        _ => err.into(),
+    }
+}
+
+/// Returns the line of the query a parse error points to (e.g. `--> [3:10]`), if any.
+fn error_line(message: &str) -> Option<usize> {
+    let regex = Regex::new(r"-->\s*\[(\d+):\d+\]").expect("Failed to build the regexp");
+
+    regex
+        .captures(message)
+        .and_then(|captures| captures[1].parse::<usize>().ok())
+}
+
+/// Returns the line of a streamed migration file matching a line of a batch of its statements, each statement
+/// being sent on its own lines.
+fn batch_line(batch: &[Statement], line: usize) -> usize {
+    let mut start = 1;
+
+    for statement in batch {
+        let lines = statement.sql.lines().count().max(1);
+        if line < start + lines {
+            return statement.line + (line - start);
+        }
+        start += lines;
+    }
+
+    line
+}
+
+/// Returns the name of the table storing the executed SQL of the migrations, in audit mode.
+fn audit_table(table: &str) -> String {
+    format!("{}_audit", table)
+}
+
+/// Returns `<user>@<hostname>` from the environment, or `unknown` for the missing parts.
+fn default_applied_by() -> String {
+    let user = env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+
+    let hostname = env::var("HOSTNAME")
+        .or_else(|_| env::var("COMPUTERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+
+    format!("{}@{}", user, hostname)
+}
+
+/// Ensures the last statement of a migration file is terminated, so other statements can be appended to it.
+pub(crate) fn terminate_statements(content: &str) -> String {
+    let content = content.trim_end();
+
+    if content.is_empty() || content.ends_with(';') {
+        content.to_string()
+    } else {
+        // On its own line, in case the file ends with a comment.
+        format!("{}\n;", content)
+    }
+}
+
+/// Checks the migration files against the migrations already applied, as strictly as the policy requires, and
+/// returns the pending ones. The deviations the policy makes warnings are added to `warnings`.
+fn check_migration_files(
+    migrations: &[Migration],
+    entries: &[MigrationFile],
+    policy: &Policy,
+    warnings: &mut Vec<MigrationWarning>,
+) -> Result<Vec<MigrationFile>, Error> {
+    let mut remaining_migrations: Vec<Migration> = migrations.to_vec();
+    let mut pending: Vec<MigrationFile> = vec![];
+
+    let last_migration = last_applied_migration(migrations);
+
+    for entry in entries {
+        // Check if the file has already been migrated.
+        let migration = migrations
+            .iter()
+            .find(|migration: &&Migration| *migration == &entry.filename);
+
+        if let Some(migration) = migration {
+            // Ensure the file has not been updated after its migration, with the checksum algorithm of its record.
+            if !entry.matches(migration) {
+                match policy.modified {
+                    PolicyAction::Error => {
+                        error!(filename = %entry.filename, "Forbidden: The migration file has been updated after its migration");
+                        return Err(
+                            Error::ChecksumMismatch {
+                                filename: entry.filename.clone(),
+                                expected: migration.checksum.clone().unwrap_or_default(),
+                                actual: entry.checksum_for(migration),
+                            }
+                        );
+                    },
+                    PolicyAction::Warn => warnings.push(MigrationWarning::new(
+                        &entry.filename,
+                        WarningKind::Modified,
+                        "The migration file has been updated after its migration.",
+                    )),
+                    PolicyAction::Ignore => (),
+                }
+            }
+
+            debug!(filename = %entry.filename, "File already migrated");
+        } else {
+            // When a migration with a higher version has already been applied, it should fail.
+            if policy.out_of_order == PolicyAction::Error && is_out_of_order(entry, last_migration) {
+                error!(filename = %entry.filename, last_migration = %last_migration.unwrap().filename, "The migration file appears before the last migration file");
+
+                return Err(
+                    Error::OutOfOrder {
+                        filename: entry.filename.clone(),
+                        last_applied: last_migration.unwrap().filename.clone(),
+                    }
+                );
+            }
+            if policy.out_of_order == PolicyAction::Warn && is_out_of_order(entry, last_migration) {
+                warnings.push(MigrationWarning::new(
+                    &entry.filename,
+                    WarningKind::OutOfOrder,
+                    format!("The migration file appears before the last migration file '{}'.", last_migration.unwrap().filename),
+                ));
+            }
+
+            pending.push(entry.clone());
+        }
+
+        // Update the migrations list.
+        let position = remaining_migrations.iter().position(|migration| { *migration.filename == entry.filename });
+        if let Some(pos) = position {
+            remaining_migrations.remove(pos);
+        }
+    }
+
+    if remaining_migrations.len() > 0 {
+        let missing: Vec<String> = remaining_migrations.into_iter().map(|migration| migration.filename).collect();
+
+        match policy.missing {
+            PolicyAction::Error => {
+                error!(missing = ?missing, "Some migration files are missing - migrations failed");
+                return Err(Error::MissingMigration { filenames: missing });
+            },
+            PolicyAction::Warn => {
+                for filename in missing {
+                    warnings.push(MigrationWarning::new(filename, WarningKind::Missing, "The applied migration file is missing."));
+                }
+            },
+            PolicyAction::Ignore => (),
+        }
+    }
+
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Error, FsSource, MigrationFile, MigrationState, Migrator, Policy, PolicyAction, VecSource};
+
+    use super::ApplyTarget;
+
+    fn entry(filename: &str) -> MigrationFile {
+        MigrationFile {
+            filename: filename.to_string(),
+            content: String::new(),
+            checksum: String::new(),
+            raw_content: String::new(),
+            streamed_checksums: None,
+        }
+    }
+
+    fn select(target: ApplyTarget) -> Vec<String> {
+        let pending = vec![entry("003_a.surql"), entry("004.surql"), entry("010_b.surql")];
+
+        target
+            .select(pending)
+            .into_iter()
+            .map(|entry| entry.filename)
+            .collect()
+    }
+
+    #[test]
+    fn it_selects_the_pending_migrations_of_a_target() {
+        assert_eq!(select(ApplyTarget::All), ["003_a.surql", "004.surql", "010_b.surql"]);
+        assert_eq!(select(ApplyTarget::To("4".to_string())), ["003_a.surql", "004.surql"]);
+        assert_eq!(select(ApplyTarget::To("002".to_string())), Vec::<String>::new());
+        assert_eq!(select(ApplyTarget::Count(1)), ["003_a.surql"]);
+        assert_eq!(select(ApplyTarget::Count(5)), ["003_a.surql", "004.surql", "010_b.surql"]);
+    }
+
+    #[test]
+    fn it_selects_the_files_required_by_the_target() {
+        let requiring = |filename: &str, requires: &str| MigrationFile {
+            content: format!("-- ssm:requires {}\nDEFINE TABLE t;", requires),
+            ..entry(filename)
+        };
+        // In the order of the dependencies: the billing file requires the accounts one, requiring the users one.
+        let pending = vec![
+            entry("20240201_audit.surql"),
+            entry("20240250_users.surql"),
+            requiring("20240301_accounts.surql", "20240250_users"),
+            requiring("20240101_billing.surql", "20240301_accounts.surql"),
+            entry("20240401_tags.surql"),
+        ];
+
+        let selected: Vec<String> = ApplyTarget::To("20240101".to_string())
+            .select(pending)
+            .into_iter()
+            .map(|entry| entry.filename)
+            .collect();
+        assert_eq!(selected, ["20240250_users.surql", "20240301_accounts.surql", "20240101_billing.surql"]);
+    }
+
+    #[tokio::test]
+    async fn it_applies_every_file_or_none_in_a_single_transaction() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let db = &db;
+        let run = |files: Vec<(&'static str, &'static str)>| async move {
+            Migrator::new(db)
+                .source(VecSource::new(files))
+                .single_transaction(true)
+                .run()
+                .await
+        };
+
+        let result = run(vec![
+            ("001_users.surql", "DEFINE TABLE users;"),
+            ("002_posts.surql", "DEFINE TABLE posts;\nTHROW 'broken';"),
+        ])
+        .await;
+        assert!(matches!(result, Err(Error::StatementFailed { ref filename, statement: 2, line: 2, .. }) if filename == "002_posts.surql"));
+        let statuses = Migrator::new(db)
+            .source(VecSource::new([("001_users.surql", "DEFINE TABLE users;")]))
+            .status()
+            .await
+            .unwrap();
+        assert_eq!(statuses[0].state, MigrationState::Pending);
+
+        let report = run(vec![("001_users.surql", "DEFINE TABLE users;"), ("002_posts.surql", "DEFINE TABLE posts;")])
+            .await
+            .unwrap();
+        assert_eq!(report.applied.len(), 2);
+
+        let result = run(vec![("003_index.surql", "-- ssm:no-transaction\nDEFINE INDEX name ON users FIELDS name;")]).await;
+        assert!(matches!(result, Err(Error::NotTransactional { .. })));
+    }
+
+    #[tokio::test]
+    async fn it_only_fails_on_the_deviations_the_policy_makes_errors() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        Migrator::new(&db)
+            .source(VecSource::new([("001_users.surql", "DEFINE TABLE users;"), ("003_posts.surql", "DEFINE TABLE posts;")]))
+            .run()
+            .await
+            .unwrap();
+
+        let source = VecSource::new([
+            ("001_users.surql", "DEFINE TABLE users SCHEMAFULL;"),
+            ("002_tags.surql", "DEFINE TABLE tags;"),
+        ]);
+        let result = Migrator::new(&db).source(&source).run().await;
+        assert!(matches!(result, Err(Error::ChecksumMismatch { .. })));
+
+        let policy = Policy { modified: PolicyAction::Warn, missing: PolicyAction::Ignore, ..Default::default() };
+        let result = Migrator::new(&db).source(&source).policy(policy).run().await;
+        assert!(matches!(result, Err(Error::OutOfOrder { .. })));
+
+        let policy = Policy { out_of_order: PolicyAction::Ignore, ..policy };
+        let report = Migrator::new(&db).source(&source).policy(policy).run().await.unwrap();
+        assert_eq!(report.applied.iter().map(|migration| migration.filename.as_str()).collect::<Vec<_>>(), ["002_tags.surql"]);
+    }
+
+    #[tokio::test]
+    async fn it_applies_the_files_in_batches() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let report = Migrator::new(&db)
+            .source(VecSource::new([
+                ("001_users.surql", "DEFINE TABLE users;"),
+                ("002_posts.surql", "DEFINE TABLE posts"),
+                ("003_index.surql", "-- ssm:no-transaction\nDEFINE INDEX name ON users FIELDS name;"),
+                ("004_tags.surql", "DEFINE TABLE tags;"),
+            ]))
+            .batch_size(10)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(report.applied.len(), 4);
+
+        // The failing batch is applied again one file at a time, up to the failing one.
+        let result = Migrator::new(&db)
+            .source(VecSource::new([
+                ("001_users.surql", "DEFINE TABLE users;"),
+                ("002_posts.surql", "DEFINE TABLE posts"),
+                ("003_index.surql", "-- ssm:no-transaction\nDEFINE INDEX name ON users FIELDS name;"),
+                ("004_tags.surql", "DEFINE TABLE tags;"),
+                ("005_a.surql", "CREATE a:1;"),
+                ("006_b.surql", "THROW 'boom';"),
+                ("007_c.surql", "CREATE c:1;"),
+            ]))
+            .batch_size(10)
+            .run()
+            .await;
+        assert!(matches!(result, Err(Error::StatementFailed { ref filename, .. }) if filename == "006_b.surql"));
+
+        let applied: Vec<String> = db.query("SELECT VALUE filename FROM migrations ORDER BY filename;").await.unwrap().take(0).unwrap();
+        assert_eq!(applied, ["001_users.surql", "002_posts.surql", "003_index.surql", "004_tags.surql", "005_a.surql"]);
+    }
+
+    #[cfg(feature = "blake3")]
+    #[tokio::test]
+    async fn it_upgrades_the_checksums_of_another_algorithm() {
+        use crate::{ChecksumAlgorithm, Migration};
+
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let files = || VecSource::new([("001_users.surql", "DEFINE TABLE users;")]);
+        Migrator::new(&db).source(files()).run().await.unwrap();
+
+        // The SHA-256 record is verified with SHA-256, then rewritten with BLAKE3.
+        let report = Migrator::new(&db)
+            .source(files())
+            .checksum_algorithm(ChecksumAlgorithm::Blake3)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(report.skipped, vec!["001_users.surql".to_string()]);
+
+        let migrations: Vec<Migration> = db.query("SELECT * FROM migrations;").await.unwrap().take(0).unwrap();
+        assert_eq!(migrations[0].checksum_algorithm.as_deref(), Some("blake3"));
+        assert_eq!(migrations[0].checksum, Some(ChecksumAlgorithm::Blake3.digest("DEFINE TABLE users;")));
+
+        let statuses = Migrator::new(&db).source(files()).status().await.unwrap();
+        assert_eq!(statuses[0].state, MigrationState::Applied);
+    }
+
+    #[tokio::test]
+    async fn it_records_the_executed_sql_in_the_audit_table() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        Migrator::new(&db)
+            .source(VecSource::new([
+                ("001_users.surql", "DEFINE TABLE ${TABLE};"),
+                ("002_index.surql", "-- ssm:no-transaction\nDEFINE INDEX name ON users FIELDS name;"),
+            ]))
+            .var("TABLE", "users")
+            .audit(true)
+            .run()
+            .await
+            .unwrap();
+
+        let sql: Vec<String> = db
+            .query("SELECT VALUE sql FROM migrations_audit ORDER BY filename;")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(sql, ["DEFINE TABLE users;", "-- ssm:no-transaction\nDEFINE INDEX name ON users FIELDS name;"]);
+    }
+
+    #[tokio::test]
+    async fn it_streams_the_statements_of_the_large_files() {
+        let dir = std::env::temp_dir().join("ssm_streamed_migration_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let statements: String = (0..1200).map(|i| format!("CREATE ${{TABLE}}:{} SET n = {};\n", i, i)).collect();
+        let content = format!("-- Backfill.\n{}-- +down\nREMOVE TABLE users;\n", statements);
+        std::fs::write(dir.join("001_backfill.surql"), &content).unwrap();
+
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        let migrator = || Migrator::new(&db).source(FsSource::new(&dir).stream_threshold(0)).var("TABLE", "users");
+
+        migrator().run().await.unwrap();
+
+        let count: Option<usize> = db.query("RETURN count(SELECT * FROM users);").await.unwrap().take(0).unwrap();
+        assert_eq!(count, Some(1200));
+        let checksums: Vec<String> = db.query("SELECT VALUE checksum FROM migrations;").await.unwrap().take(0).unwrap();
+        assert_eq!(checksums, [crate::checksum(&content)]);
+
+        std::fs::write(dir.join("002_fail.surql"), "CREATE users:a;\n\nTHROW 'boom';\n").unwrap();
+        let result = migrator().run().await;
+        assert!(matches!(result, Err(Error::StatementFailed { statement: 2, line: 3, .. })));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_defines_the_namespace_and_the_database() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("fresh-ns").use_db("fresh-db").await.unwrap();
+
+        Migrator::new(&db)
+            .source(VecSource::new([("001_users.surql", "DEFINE TABLE users;")]))
+            .create_ns_db(true)
+            .run()
+            .await
+            .unwrap();
+
+        db.query("INFO FOR NS;").await.unwrap().check().unwrap();
+        db.query("INFO FOR DB;").await.unwrap().check().unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_lists_the_applied_and_pending_migrations() {
+        let dir = std::env::temp_dir().join("ssm_it_lists_the_applied_and_pending_migrations");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("001_users.surql"), "DEFINE TABLE users;").unwrap();
+        std::fs::write(dir.join("R__views.surql"), "DEFINE TABLE views;").unwrap();
+
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        Migrator::new(&db).dir(&dir).run().await.unwrap();
+
+        std::fs::write(dir.join("002_posts.surql"), "DEFINE TABLE ${TABLE};").unwrap();
+        let migrator = Migrator::new(&db).dir(&dir).var("TABLE", "posts");
+
+        let applied = migrator.applied().await.unwrap();
+        assert_eq!(
+            applied.iter().map(|migration| (migration.filename.as_str(), migration.repeatable)).collect::<Vec<_>>(),
+            [("001_users.surql", false), ("R__views.surql", true)]
+        );
+        assert_eq!(applied[0].path, Some(dir.join("001_users.surql")));
+
+        let pending = migrator.pending().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].filename, "002_posts.surql");
+        assert_eq!(pending[0].content, "DEFINE TABLE posts;");
+        assert_eq!(pending[0].path, Some(dir.join("002_posts.surql")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_records_the_faked_migrations_without_executing_them() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let source = VecSource::new([
+            ("001_users.surql", "DEFINE TABLE users;"),
+            ("002_posts.surql", "DEFINE TABLE posts;"),
+            ("003_tags.surql", "DEFINE TABLE tags;"),
+        ]);
+
+        let faked = Migrator::new(&db)
+            .source(&source)
+            .target(ApplyTarget::To("002".to_string()))
+            .fake()
+            .await
+            .unwrap();
+        assert_eq!(faked, ["001_users.surql", "002_posts.surql"]);
+
+        let tables: Option<serde_json::Value> = db.query("INFO FOR DB;").await.unwrap().take((0, "tables")).unwrap();
+        assert!(tables.unwrap().get("users").is_none());
+
+        let report = Migrator::new(&db).source(&source).run().await.unwrap();
+        assert_eq!(report.applied.iter().map(|migration| migration.filename.as_str()).collect::<Vec<_>>(), ["003_tags.surql"]);
+    }
+
+    #[tokio::test]
+    async fn it_only_fakes_the_migrations_of_the_environment_and_the_tags() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let source = VecSource::new([
+            ("001_users.surql", "-- ssm:tags auth\nDEFINE TABLE users;"),
+            ("002_fixtures.surql", "-- ssm:env dev\n-- ssm:tags auth\nCREATE users:test;"),
+            ("003_posts.surql", "DEFINE TABLE posts;"),
+        ]);
+
+        let faked = Migrator::new(&db).source(&source).environment("prod").tags(["auth"]).fake().await.unwrap();
+        assert_eq!(faked, ["001_users.surql"]);
+
+        // The file for other environments is recorded as skipped, as by a run, and the untagged one is still pending.
+        let records: Vec<(String, Option<bool>)> = db
+            .query("SELECT VALUE [filename, skipped] FROM migrations ORDER BY filename;")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(records, [("001_users.surql".to_string(), None), ("002_fixtures.surql".to_string(), Some(true))]);
+    }
+
+    #[tokio::test]
+    async fn it_keeps_the_existing_definitions_when_idempotent() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        db.query("DEFINE TABLE users;").await.unwrap().check().unwrap();
+
+        let source = VecSource::new([("001_users.surql", "DEFINE TABLE users;\nDEFINE FIELD email ON users TYPE string;")]);
+        assert!(Migrator::new(&db).source(&source).run().await.is_err());
+
+        let report = Migrator::new(&db).source(&source).idempotent(true).run().await.unwrap();
+        assert_eq!(report.applied.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_only_applies_the_tagged_migrations() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let source = VecSource::new([
+            ("001_users.surql", "-- ssm:tags auth\nDEFINE TABLE users;"),
+            ("002_posts.surql", "-- ssm:tags blog\nDEFINE TABLE posts;"),
+            ("003_sessions.surql", "-- ssm:tags auth, critical\nDEFINE TABLE sessions;"),
+        ]);
+
+        let report = Migrator::new(&db).source(&source).tags(["auth"]).run().await.unwrap();
+        assert_eq!(
+            report.applied.iter().map(|migration| migration.filename.as_str()).collect::<Vec<_>>(),
+            ["001_users.surql", "003_sessions.surql"]
+        );
+
+        // The file left pending now has a lower version than an applied one.
+        let statuses = Migrator::new(&db).source(&source).tags(["blog"]).status().await.unwrap();
+        assert_eq!(
+            statuses.iter().map(|status| (status.filename.as_str(), status.state)).collect::<Vec<_>>(),
+            [("002_posts.surql", MigrationState::OutOfOrder)]
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use std::{env, path::{Path, PathBuf}};
+
+use chrono::prelude::*;
+use regex::Regex;
+use tokio::{fs::{create_dir_all, read_to_string, OpenOptions}, io::AsyncWriteExt};
+
+use crate::{down_filename, version, Error, FilePattern, FsSource, MigrationSource};
+
+/// The zero padding used for the first migration file of a directory.
+const DEFAULT_VERSION_WIDTH: usize = 3;
+
+/// The directory of the templates of the new migration files, read by `MigrationTemplate::load` in the CLI.
+pub const DEFAULT_TEMPLATES_DIR: &str = "./templates";
+
+/// The template of the new migration files, in the templates directory.
+pub const TEMPLATE_FILE: &str = "migration.surql.tmpl";
+
+/// The template of the new `.down` files, in the templates directory.
+pub const DOWN_TEMPLATE_FILE: &str = "migration.down.surql.tmpl";
+
+const DEFAULT_TEMPLATE: &str = "-- Migration: {{filename}}\n-- Created at: {{date}}\n\n{{body}}";
+
+const DEFAULT_DOWN_TEMPLATE: &str = "-- Rollback of: {{filename}}\n-- Created at: {{date}}\n\n{{body}}";
+
+/// The format of the number prefix of a new migration file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NamingScheme {
+    /// The number following the highest prefix of the migration files (e.g. `004_create_users_table.surql`).
+    #[default]
+    Sequential,
+    /// The current UTC date and time (e.g. `20240521143000_create_users_table.surql`), avoiding conflicts on the
+    /// next number between branches.
+    Timestamp,
+}
+
+/// The files created by `new_migration`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewMigration {
+    pub path: PathBuf,
+    pub down_path: Option<PathBuf>,
+}
+
+/// The content of the new migration files, e.g. to enforce a header comment or wrap the statements in a
+/// `BEGIN TRANSACTION`. The templates can use the `{{name}}`, `{{filename}}`, `{{version}}`, `{{date}}` (RFC 3339,
+/// UTC), `{{author}}` and `{{body}}` placeholders, the body being appended to the templates without it.
+///
+/// ```ignore
+/// let template = MigrationTemplate::new()
+///     .up("-- {{name}} by {{author}} on {{date}}\nBEGIN TRANSACTION;\n\n{{body}}\nCOMMIT TRANSACTION;\n");
+///
+/// new_migration_with_template(Path::new("./migrations"), &source, "add_users", NamingScheme::Sequential, &template, &pattern).await?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationTemplate {
+    up: String,
+    down: String,
+    author: String,
+    body: String,
+    down_body: Option<String>,
+}
+
+impl Default for MigrationTemplate {
+    fn default() -> Self {
+        MigrationTemplate {
+            up: DEFAULT_TEMPLATE.to_string(),
+            down: DEFAULT_DOWN_TEMPLATE.to_string(),
+            author: env::var("USER")
+                .or_else(|_| env::var("USERNAME"))
+                .unwrap_or_else(|_| "unknown".to_string()),
+            body: String::new(),
+            down_body: None,
+        }
+    }
+}
+
+impl MigrationTemplate {
+    /// The default templates: a header with the filename and the creation date.
+    pub fn new() -> Self {
+        MigrationTemplate::default()
+    }
+
+    /// Reads the templates of a directory (`migration.surql.tmpl` and `migration.down.surql.tmpl`), keeping the
+    /// default ones for the missing files.
+    pub async fn load(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut template = MigrationTemplate::default();
+
+        if let Some(up) = read_template(&dir.as_ref().join(TEMPLATE_FILE)).await? {
+            template.up = up;
+        }
+        if let Some(down) = read_template(&dir.as_ref().join(DOWN_TEMPLATE_FILE)).await? {
+            template.down = down;
+        }
+
+        Ok(template)
+    }
+
+    /// The template of the migration file.
+    pub fn up(mut self, template: impl Into<String>) -> Self {
+        self.up = template.into();
+        self
+    }
+
+    /// The template of the `.down` file.
+    pub fn down(mut self, template: impl Into<String>) -> Self {
+        self.down = template.into();
+        self
+    }
+
+    /// The value of `{{author}}`. (default: the `USER` or `USERNAME` environment variable)
+    pub fn author(mut self, author: impl Into<String>) -> Self {
+        self.author = author.into();
+        self
+    }
+
+    /// The statements of the migration file, in place of `{{body}}`. (default: none)
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Also create the `.down` file, with these statements in place of `{{body}}`. (default: no `.down` file)
+    pub fn down_body(mut self, down_body: impl Into<String>) -> Self {
+        self.down_body = Some(down_body.into());
+        self
+    }
+
+    /// Fills the placeholders of a template for a new file.
+    fn render(&self, template: &str, body: &str, filename: &str, name: &str, version: &str, date: &str) -> String {
+        let content = template
+            .replace("{{name}}", name)
+            .replace("{{filename}}", filename)
+            .replace("{{version}}", version)
+            .replace("{{date}}", date)
+            .replace("{{author}}", &self.author);
+
+        if content.contains("{{body}}") {
+            content.replace("{{body}}", body)
+        } else {
+            format!("{}{}", content, body)
+        }
+    }
+}
+
+async fn read_template(path: &Path) -> Result<Option<String>, Error> {
+    match read_to_string(path).await {
+        Ok(template) => Ok(Some(template)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Creates the next numbered migration file (e.g. `004_create_users_table.surql`) in the migration
+/// directory, and optionally its `.down.surql` companion.
+pub async fn new_migration(migration_dir_path: &str, name: &str, with_down: bool, scheme: NamingScheme) -> Result<NewMigration, Error> {
+    let source = FsSource::new(migration_dir_path);
+
+    new_migration_with_body(Path::new(migration_dir_path), &source, name, scheme, "", with_down.then_some(""), &FilePattern::default())
+        .await
+}
+
+/// Creates the next numbered migration file like `new_migration`, with the given statements after its header,
+/// and its `.down` companion with the given down statements, if any. The file is created in the directory with
+/// the first extension of the pattern, and numbered after the files of the source matching the pattern (e.g. the
+/// directory and its subdirectories, or all the merged migration directories).
+pub async fn new_migration_with_body(
+    migration_dir_path: &Path,
+    source: &dyn MigrationSource,
+    name: &str,
+    scheme: NamingScheme,
+    body: &str,
+    down_body: Option<&str>,
+    pattern: &FilePattern,
+) -> Result<NewMigration, Error> {
+    let mut template = MigrationTemplate::default().body(body);
+    if let Some(down_body) = down_body {
+        template = template.down_body(down_body);
+    }
+
+    new_migration_with_template(migration_dir_path, source, name, scheme, &template, pattern).await
+}
+
+/// Creates the next numbered migration file like `new_migration_with_body`, its content (and the one of its
+/// `.down` companion, when the template has a down body) rendered from a template.
+pub async fn new_migration_with_template(
+    migration_dir_path: &Path,
+    source: &dyn MigrationSource,
+    name: &str,
+    scheme: NamingScheme,
+    template: &MigrationTemplate,
+    pattern: &FilePattern,
+) -> Result<NewMigration, Error> {
+    let regex = Regex::new(r"^[a-zA-Z_0-9]+$").expect("Failed to build the regexp");
+    if !regex.is_match(name) {
+        return Err(
+            Error::InvalidName {
+                name: name.to_string(),
+                message: format!("The migration name '{}' must only contain letters, numbers and underscores.", name),
+            }
+        );
+    }
+
+    create_dir_all(migration_dir_path).await?;
+
+    let now = Utc::now();
+    let version = match scheme {
+        NamingScheme::Sequential => {
+            let filenames: Vec<String> = source
+                .list()
+                .await?
+                .into_iter()
+                .filter(|filename| pattern.is_migration(filename))
+                .collect();
+
+            next_version(&filenames)
+        },
+        NamingScheme::Timestamp => now.format("%Y%m%d%H%M%S").to_string(),
+    };
+
+    let filename = format!("{}_{}.{}", version, name, pattern.extension());
+    let created_at = now.to_rfc3339();
+
+    let path = migration_dir_path.join(&filename);
+    create_file(&path, &template.render(&template.up, &template.body, &filename, name, &version, &created_at)).await?;
+
+    let down_path = match template.down_body {
+        Some(ref down_body) => {
+            let down_path = migration_dir_path.join(down_filename(&filename));
+            create_file(&down_path, &template.render(&template.down, down_body, &filename, name, &version, &created_at)).await?;
+
+            Some(down_path)
+        },
+        None => None,
+    };
+
+    Ok(NewMigration { path, down_path })
+}
+
+/// Returns the number following the highest prefix of the migration files, zero padded like them.
+fn next_version(filenames: &[String]) -> String {
+    let width = filenames
+        .iter()
+        .map(|filename| version(filename).len())
+        .max()
+        .unwrap_or(DEFAULT_VERSION_WIDTH);
+
+    let last = filenames
+        .iter()
+        .filter_map(|filename| version(filename).parse::<u64>().ok())
+        .max()
+        .unwrap_or(0);
+
+    format!("{:0width$}", last + 1, width = width)
+}
+
+/// Creates a file with the given content, failing if it already exists.
+async fn create_file(path: &Path, content: &str) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .await?;
+
+    file.write_all(content.as_bytes()).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_version, MigrationTemplate};
+
+    #[test]
+    fn it_computes_the_next_version() {
+        assert_eq!(next_version(&[]), "001");
+        assert_eq!(
+            next_version(&["001_create_users_table.surql".to_string(), "002.surql".to_string()]),
+            "003"
+        );
+        assert_eq!(next_version(&["0009_i18n.surql".to_string()]), "0010");
+        assert_eq!(next_version(&["99.surql".to_string()]), "100");
+    }
+
+    #[test]
+    fn it_renders_the_templates() {
+        let template = MigrationTemplate::new()
+            .up("-- {{name}} ({{version}}) by {{author}}\nBEGIN TRANSACTION;\n{{body}}\nCOMMIT TRANSACTION;\n")
+            .author("jane")
+            .body("DEFINE TABLE users;");
+
+        assert_eq!(
+            template.render(&template.up, &template.body, "004_add_users.surql", "add_users", "004", "2024-05-21T14:30:00+00:00"),
+            "-- add_users (004) by jane\nBEGIN TRANSACTION;\nDEFINE TABLE users;\nCOMMIT TRANSACTION;\n"
+        );
+        assert_eq!(
+            template.render("-- {{filename}}\n", "DEFINE TABLE users;", "004_add_users.surql", "add_users", "004", ""),
+            "-- 004_add_users.surql\nDEFINE TABLE users;"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use regex::Regex;
+
+/// The extension of the migration files when none is configured.
+pub const DEFAULT_EXTENSION: &str = "surql";
+
+/// Which files of a source are migration files: `<file_number>(_<filename>).<extension>` and
+/// `R__<filename>.<extension>` for the repeatable ones, with one of the accepted extensions, unless ignored.
+///
+/// ```ignore
+/// let pattern = FilePattern::new()
+///     .extensions(["surql", "sql"])
+///     .migration_regex(Regex::new(r"^[0-9]+__[a-z_]+\.sql$")?)
+///     .ignore(["*_wip.surql"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FilePattern {
+    extensions: Vec<String>,
+    migration_regex: Option<Regex>,
+    ignored: Vec<IgnoreRule>,
+    /// The regexes of the numbered and repeatable migration files, built from the extensions.
+    numbered_regex: Regex,
+    repeatable_regex: Regex,
+}
+
+impl Default for FilePattern {
+    fn default() -> Self {
+        let extensions = vec![DEFAULT_EXTENSION.to_string()];
+
+        FilePattern {
+            numbered_regex: extension_regex(NUMBERED_PREFIX, &extensions),
+            repeatable_regex: extension_regex(REPEATABLE_PREFIX, &extensions),
+            extensions,
+            migration_regex: None,
+            ignored: vec![],
+        }
+    }
+}
+
+impl FilePattern {
+    pub fn new() -> Self {
+        FilePattern::default()
+    }
+
+    /// The accepted extensions of the migration files, without the dot, the first one being used for the new
+    /// files. (default: ["surql"])
+    pub fn extensions<E: Into<String>>(mut self, extensions: impl IntoIterator<Item = E>) -> Self {
+        self.extensions = extensions.into_iter().map(Into::into).collect();
+        self.numbered_regex = extension_regex(NUMBERED_PREFIX, &self.extensions);
+        self.repeatable_regex = extension_regex(REPEATABLE_PREFIX, &self.extensions);
+        self
+    }
+
+    /// The regex the names of the numbered migration files must match, instead of the one built from the
+    /// extensions. The names must still start with their version number, which orders them.
+    pub fn migration_regex(mut self, regex: Regex) -> Self {
+        self.migration_regex = Some(regex);
+        self
+    }
+
+    /// Gitignore-style patterns of the files to leave out of the migrations (e.g. `*_wip.surql`, `drafts/`), a
+    /// pattern starting with `!` including the files matched by a previous one again. Blank lines and lines
+    /// starting with `#` are skipped, so the lines of a `.ssmignore` file can be given as is.
+    pub fn ignore<P: AsRef<str>>(mut self, patterns: impl IntoIterator<Item = P>) -> Self {
+        self.ignored.extend(patterns.into_iter().filter_map(|pattern| IgnoreRule::parse(pattern.as_ref())));
+        self
+    }
+
+    /// Returns whether a file is ignored, given its path relative to the migration directory with `/`
+    /// separators (e.g. `drafts/005_users.surql`), or its filename.
+    pub fn is_ignored(&self, path: &str) -> bool {
+        self.ignored
+            .iter()
+            .fold(false, |ignored, rule| if rule.matches(path) { !rule.negated } else { ignored })
+    }
+
+    /// Returns the extension of the new migration files.
+    pub fn extension(&self) -> &str {
+        self.extensions
+            .first()
+            .map_or(DEFAULT_EXTENSION, String::as_str)
+    }
+
+    /// Returns whether a filename is a numbered migration file.
+    pub fn is_migration(&self, filename: &str) -> bool {
+        if self.is_ignored(filename) {
+            return false;
+        }
+
+        match self.migration_regex {
+            Some(ref regex) => regex.is_match(filename) && !is_down_filename(filename),
+            None => self.numbered_regex.is_match(filename),
+        }
+    }
+
+    /// Returns whether a filename is a repeatable migration file.
+    pub fn is_repeatable(&self, filename: &str) -> bool {
+        self.repeatable_regex.is_match(filename) && !self.is_ignored(filename)
+    }
+
+    /// Returns whether the numbered migration files are matched by a custom regex.
+    pub(crate) fn has_migration_regex(&self) -> bool {
+        self.migration_regex.is_some()
+    }
+
+    /// Returns whether a filename has one of the accepted extensions.
+    pub fn has_extension(&self, filename: &str) -> bool {
+        filename
+            .rsplit_once('.')
+            .is_some_and(|(_, extension)| self.extensions.iter().any(|accepted| accepted == extension))
+    }
+}
+
+/// The prefix of the names of the numbered migration files.
+const NUMBERED_PREFIX: &str = r"^[0-9]+[a-zA-Z_0-9]{0,}";
+
+/// The prefix of the names of the repeatable migration files.
+const REPEATABLE_PREFIX: &str = r"^R__[a-zA-Z_0-9]+";
+
+/// Builds the regex of a filename prefix followed by one of the extensions.
+fn extension_regex(prefix: &str, extensions: &[String]) -> Regex {
+    let extensions: Vec<String> = extensions
+        .iter()
+        .map(|extension| regex::escape(extension))
+        .collect();
+
+    Regex::new(&format!(r"{}\.({})$", prefix, extensions.join("|"))).expect("Failed to build the regexp")
+}
+
+/// A gitignore-style pattern of `FilePattern::ignore`.
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    regex: Regex,
+    /// Whether the pattern includes the matched files again (`!pattern`).
+    negated: bool,
+    /// Whether the pattern matches directories (`pattern/`), ignoring the files they contain.
+    dir_only: bool,
+    /// Whether the pattern contains a `/`, matching the path from the migration directory rather than any name.
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+
+        let mut regex = String::from("^");
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    regex.push_str(".*");
+                },
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+
+        Some(IgnoreRule {
+            regex: Regex::new(&regex).expect("Failed to build the regexp"),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let (dirs, filename) = path.rsplit_once('/').unwrap_or(("", path));
+
+        if self.dir_only {
+            // The directories containing the file (e.g. `a` and `a/b` for `a/b/file.surql`), or their names.
+            let mut prefix = String::new();
+            dirs.split('/').filter(|dir| !dir.is_empty()).any(|dir| {
+                if !prefix.is_empty() {
+                    prefix.push('/');
+                }
+                prefix.push_str(dir);
+
+                self.regex.is_match(if self.anchored { &prefix } else { dir })
+            })
+        } else if self.anchored {
+            self.regex.is_match(path)
+        } else {
+            self.regex.is_match(filename)
+        }
+    }
+}
+
+/// Returns whether a filename is a down migration file (e.g. `002_create_post_table.down.surql`).
+fn is_down_filename(filename: &str) -> bool {
+    filename
+        .rsplit_once('.')
+        .is_some_and(|(name, _)| name.ends_with(".down"))
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::FilePattern;
+
+    #[test]
+    fn it_matches_the_configured_extensions_and_regex() {
+        let pattern = FilePattern::new();
+        assert!(pattern.is_migration("001_users.surql"));
+        assert!(!pattern.is_migration("001_users.sql"));
+        assert!(!pattern.is_migration("001_users.down.surql"));
+        assert!(pattern.is_repeatable("R__functions.surql"));
+
+        let pattern = FilePattern::new().extensions(["sql", "surql"]);
+        assert!(pattern.is_migration("001_users.sql"));
+        assert!(pattern.is_migration("002_posts.surql"));
+        assert!(pattern.is_repeatable("R__functions.sql"));
+        assert!(!pattern.is_migration("001_users.sqlite"));
+        assert_eq!(pattern.extension(), "sql");
+
+        let pattern = FilePattern::new().migration_regex(Regex::new(r"^[0-9]+__[a-z_]+\.sql$").unwrap());
+        assert!(pattern.is_migration("001__users.sql"));
+        assert!(!pattern.is_migration("001_users.sql"));
+    }
+
+    #[test]
+    fn it_ignores_the_files_matching_gitignore_style_patterns() {
+        let pattern = FilePattern::new().ignore(["# Work in progress.", "", "*_wip.surql", "!003_keep_wip.surql", "drafts/", "/legacy/*.surql"]);
+
+        assert!(!pattern.is_migration("002_users_wip.surql"));
+        assert!(pattern.is_migration("003_keep_wip.surql"));
+        assert!(pattern.is_migration("004_posts.surql"));
+        assert!(pattern.is_ignored("drafts/005_comments.surql"));
+        assert!(pattern.is_ignored("2024/drafts/006_tags.surql"));
+        assert!(pattern.is_ignored("legacy/001_init.surql"));
+        assert!(!pattern.is_ignored("2024/legacy/001_init.surql"));
+    }
+}
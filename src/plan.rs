@@ -0,0 +1,133 @@
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, PendingMigration};
+
+/// The version of the format of the plans written by `MigrationPlan::to_json`.
+pub const PLAN_FORMAT_VERSION: u32 = 1;
+
+/// A pending migration file of a `MigrationPlan`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PlannedMigration {
+    pub filename: String,
+    pub checksum: String,
+}
+
+/// The pending migration files of a database and their checksums, in the order they would be applied. Written
+/// to a file to be reviewed, then passed to `Migrator::expected_plan` so the run fails if what would be applied
+/// changed in the meantime (a new file, an edited one, or one applied by someone else).
+///
+/// ```ignore
+/// let plan = MigrationPlan::new(&Migrator::new(&db).plan().await?);
+/// fs::write("plan.json", plan.to_json())?;
+///
+/// // Once approved.
+/// let plan = MigrationPlan::from_json(&fs::read_to_string("plan.json")?)?;
+/// Migrator::new(&db).expected_plan(plan).run().await?;
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct MigrationPlan {
+    pub version: u32,
+    pub migrations: Vec<PlannedMigration>,
+}
+
+impl MigrationPlan {
+    /// The plan of the pending migrations returned by `Migrator::plan`.
+    pub fn new(pending: &[PendingMigration]) -> Self {
+        MigrationPlan {
+            version: PLAN_FORMAT_VERSION,
+            migrations: pending
+                .iter()
+                .map(|migration| PlannedMigration {
+                    filename: migration.filename.clone(),
+                    checksum: migration.checksum.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the plan as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize the plan")
+    }
+
+    /// Reads a plan written by `to_json`, failing with an `Error::IO` of kind `InvalidData` when it is not one.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let plan: MigrationPlan = serde_json::from_str(json).map_err(io::Error::from)?;
+        if plan.version != PLAN_FORMAT_VERSION {
+            return Err(Error::IO(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported plan version {} (expected {}).", plan.version, PLAN_FORMAT_VERSION),
+            )));
+        }
+
+        Ok(plan)
+    }
+
+    /// Returns the filenames of the migrations planned by only one of the plans, or with another checksum, or
+    /// every filename of `other` when only their order differs. Empty when the plans are the same.
+    pub(crate) fn differences(&self, other: &MigrationPlan) -> Vec<String> {
+        if self.migrations == other.migrations {
+            return vec![];
+        }
+
+        let mut filenames: Vec<String> = vec![];
+        let different = self
+            .migrations
+            .iter()
+            .filter(|migration| !other.migrations.contains(migration))
+            .chain(other.migrations.iter().filter(|migration| !self.migrations.contains(migration)));
+        for migration in different {
+            if !filenames.contains(&migration.filename) {
+                filenames.push(migration.filename.clone());
+            }
+        }
+
+        if filenames.is_empty() {
+            filenames = other.migrations.iter().map(|migration| migration.filename.clone()).collect();
+        }
+
+        filenames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Error, Migrator, VecSource};
+
+    use super::MigrationPlan;
+
+    #[tokio::test]
+    async fn it_refuses_to_apply_a_plan_that_changed() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let source = VecSource::new([("001_users.surql", "DEFINE TABLE users;")]);
+        let plan = MigrationPlan::new(&Migrator::new(&db).source(&source).plan().await.unwrap());
+        let plan = MigrationPlan::from_json(&plan.to_json()).unwrap();
+        assert_eq!(plan.migrations.len(), 1);
+
+        let changed = VecSource::new([
+            ("001_users.surql", "DEFINE TABLE users;"),
+            ("002_posts.surql", "DEFINE TABLE posts;"),
+        ]);
+        match Migrator::new(&db).source(&changed).expected_plan(plan.clone()).run().await {
+            Err(Error::PlanMismatch { filenames }) => assert_eq!(filenames, ["002_posts.surql"]),
+            result => panic!("Unexpected result: {:?}", result.map(|report| report.applied.len())),
+        }
+
+        let report = Migrator::new(&db).source(&source).expected_plan(plan.clone()).run().await.unwrap();
+        assert_eq!(report.applied.len(), 1);
+
+        // Applied since.
+        assert!(matches!(
+            Migrator::new(&db).source(&source).expected_plan(plan).run().await,
+            Err(Error::PlanMismatch { .. })
+        ));
+    }
+}
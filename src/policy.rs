@@ -0,0 +1,48 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+/// How a deviation of the migration files from the migrations table is handled.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyAction {
+    /// The run fails before applying anything.
+    #[default]
+    Error,
+    /// The deviation is logged as a warning, and the run goes on.
+    Warn,
+    /// The run goes on silently.
+    Ignore,
+}
+
+impl fmt::Display for PolicyAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PolicyAction::Error => write!(f, "error"),
+            PolicyAction::Warn => write!(f, "warn"),
+            PolicyAction::Ignore => write!(f, "ignore"),
+        }
+    }
+}
+
+/// How strict the migration runs are with the migration files deviating from the migrations table, e.g. to only
+/// warn about the files edited after their migration on a development database. Every deviation is an error by
+/// default.
+///
+/// ```ignore
+/// let policy = Policy { modified: PolicyAction::Warn, ..Default::default() };
+///
+/// Migrator::new(&db).policy(policy).run().await?;
+/// ```
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// The applied files changed since their migration (`Error::ChecksumMismatch`). They are not applied again
+    /// either way, and keep their recorded checksum. (default: error)
+    pub modified: PolicyAction,
+    /// The applied files no longer in the migration directory (`Error::MissingMigration`). (default: error)
+    pub missing: PolicyAction,
+    /// The pending files with a lower version than the last applied one (`Error::OutOfOrder`), otherwise applied
+    /// and recorded as out of order. (default: error)
+    pub out_of_order: PolicyAction,
+}
@@ -0,0 +1,81 @@
+use surrealdb::{Connection, Surreal};
+use tracing::{debug, error};
+
+use crate::Error;
+
+/// Fails with `Error::InsufficientPermissions` unless the user signed in can define a table, and create and delete
+/// its records, by probing the scratch `<table>_preflight` table (removed afterwards), so the missing permissions
+/// are found before the first pending migration is applied rather than halfway through them.
+pub(crate) async fn check_permissions<C: Connection>(db: &Surreal<C>, table: &str) -> Result<(), Error> {
+    let scratch = preflight_table(table);
+
+    let result = probe(db, &scratch).await;
+    // Nothing is left behind, even when a probe failed.
+    let removed = run(db, "REMOVE TABLE", format!("REMOVE TABLE IF EXISTS {};", scratch)).await;
+
+    result?;
+    removed?;
+    debug!("The user can define tables, and create and delete records");
+
+    Ok(())
+}
+
+async fn probe<C: Connection>(db: &Surreal<C>, scratch: &str) -> Result<(), Error> {
+    run(db, "DEFINE TABLE", format!("DEFINE TABLE IF NOT EXISTS {} SCHEMALESS;", scratch)).await?;
+
+    // The record users are not refused the records they are not allowed to write, they are left out.
+    let mut response = run(db, "CREATE", format!("CREATE {}:check SET checked = true RETURN checked;", scratch)).await?;
+    if response.take::<Option<bool>>((0, "checked"))? != Some(true) {
+        return Err(refused("CREATE", "no record was created"));
+    }
+
+    let mut response = run(db, "DELETE", format!("DELETE {}:check RETURN BEFORE;", scratch)).await?;
+    if response.take::<Option<bool>>((0, "checked"))? != Some(true) {
+        return Err(refused("DELETE", "no record was deleted"));
+    }
+
+    Ok(())
+}
+
+/// Runs a probe, its failures caused by the permissions of the user being `Error::InsufficientPermissions`.
+async fn run<C: Connection>(db: &Surreal<C>, operation: &str, sql: String) -> Result<surrealdb::Response, Error> {
+    match db.query(sql).await.and_then(|response| response.check()) {
+        Ok(response) => Ok(response),
+        Err(e) if is_permission_error(&e) => Err(refused(operation, &e.to_string())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn refused(operation: &str, message: &str) -> Error {
+    error!(%operation, %message, "The user is not allowed to apply the migrations");
+
+    Error::InsufficientPermissions { operation: operation.to_string(), message: message.to_string() }
+}
+
+fn is_permission_error(err: &surrealdb::Error) -> bool {
+    let message = err.to_string();
+    message.contains("Not enough permissions") || message.contains("not allowed")
+}
+
+/// Returns the name of the scratch table probed by the permission check.
+fn preflight_table(table: &str) -> String {
+    format!("{}_preflight", table)
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use super::check_permissions;
+
+    #[tokio::test]
+    async fn it_leaves_nothing_behind_once_the_permissions_are_checked() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        check_permissions(&db, "migrations").await.unwrap();
+
+        let tables: Option<serde_json::Value> = db.query("INFO FOR DB;").await.unwrap().take((0, "tables")).unwrap();
+        assert!(tables.unwrap().get("migrations_preflight").is_none());
+    }
+}
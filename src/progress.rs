@@ -0,0 +1,135 @@
+use surrealdb::{Connection, Surreal};
+use tracing::info;
+
+use crate::{parse_statements, ChecksumAlgorithm, Error, Statement};
+
+/// Returns the name of the table tracking the progress of the data migrations and of the failed migration files
+/// opting out of transactions.
+pub(crate) fn progress_table(table: &str) -> String {
+    format!("{}_progress", table)
+}
+
+/// Defines the progress table when it does not exist, e.g. on a database in strict mode.
+pub(crate) async fn define_progress_table<C: Connection>(db: &Surreal<C>, table: &str) -> Result<(), Error> {
+    let _ = db
+        .query(format!("DEFINE TABLE IF NOT EXISTS {} SCHEMALESS;", progress_table(table)))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// Records the statements of a migration file executed before the one that failed, and their checksum (see
+/// `statements_checksum`), for a run with `Migrator::resume` to continue from the failed statement once fixed.
+pub(crate) async fn record_failed_statement<C: Connection>(
+    db: &Surreal<C>,
+    table: &str,
+    filename: &str,
+    executed: usize,
+    checksum: &str,
+) -> Result<(), Error> {
+    let _ = db
+        .query("UPSERT type::thing($progress_table, $filename) SET statement=$statement, executed_checksum=$checksum;")
+        .bind(("progress_table", progress_table(table)))
+        .bind(("filename", filename.to_string()))
+        .bind(("checksum", checksum.to_string()))
+        .bind(("statement", executed))
+        .await?
+        .check()?;
+
+    info!(statements = executed, "Progress recorded, the file can be resumed from the failed statement");
+
+    Ok(())
+}
+
+/// Returns the statements of a migration file executed before a failure, and their checksum, if recorded.
+pub(crate) async fn read_failed_statement<C: Connection>(
+    db: &Surreal<C>,
+    table: &str,
+    filename: &str,
+) -> Result<Option<(usize, String)>, Error> {
+    let executed = db
+        .query("SELECT VALUE [statement, executed_checksum] FROM ONLY type::thing($progress_table, $filename) WHERE statement > 0;")
+        .bind(("progress_table", progress_table(table)))
+        .bind(("filename", filename.to_string()))
+        .await?
+        .check()?
+        .take::<Option<(usize, String)>>(0)?;
+
+    Ok(executed)
+}
+
+/// Returns the checksum of the SQL of statements, telling whether the ones executed before a failure are still the
+/// first ones of the file, whatever its other statements (e.g. the failed one, fixed).
+pub(crate) fn statements_checksum<'s>(statements: impl IntoIterator<Item = &'s Statement>) -> String {
+    let sql: String = statements.into_iter().map(|statement| format!("{};\n", statement.sql)).collect();
+
+    ChecksumAlgorithm::Sha256.digest(&sql)
+}
+
+/// Returns the statements of a migration file after the first `count` ones, each one on the line it is on in the
+/// file, so the errors still point at the file.
+pub(crate) fn skip_statements(content: &str, count: usize) -> String {
+    if count == 0 {
+        return content.to_string();
+    }
+
+    let mut sql = String::new();
+    let mut line = 1;
+    for statement in parse_statements(content).into_iter().skip(count) {
+        while line < statement.line {
+            sql.push('\n');
+            line += 1;
+        }
+
+        sql.push_str(&statement.sql);
+        sql.push(';');
+        line += statement.sql.matches('\n').count();
+    }
+
+    sql
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Error, Migrator, VecSource};
+
+    use super::skip_statements;
+
+    #[test]
+    fn it_skips_the_executed_statements() {
+        let content = "-- ssm:no-transaction\nDEFINE TABLE a;\nDEFINE TABLE b; DEFINE TABLE c;\n\nDEFINE FIELD d ON c\n    TYPE string;\n";
+
+        assert_eq!(skip_statements(content, 0), content);
+        assert_eq!(skip_statements(content, 2), "\n\nDEFINE TABLE c;\n\nDEFINE FIELD d ON c\n    TYPE string;");
+        assert_eq!(skip_statements(content, 4), "");
+    }
+
+    #[tokio::test]
+    async fn it_resumes_a_file_from_the_failed_statement() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let source = VecSource::new([(
+            "001_counters.surql",
+            "-- ssm:no-transaction\nCREATE counter:users SET value = 1;\nUPDATE counter:users SET value += 1;\n${LAST};\n",
+        )]);
+
+        let result = Migrator::new(&db).source(&source).var("LAST", "THROW 'broken'").run().await;
+        assert!(matches!(result, Err(Error::StatementFailed { statement: 3, .. })));
+
+        // Replaying the whole file would fail on the CREATE of the existing record.
+        Migrator::new(&db)
+            .source(&source)
+            .var("LAST", "UPDATE counter:users SET value += 10")
+            .resume(true)
+            .run()
+            .await
+            .unwrap();
+
+        let value: Option<u32> = db.query("SELECT VALUE value FROM ONLY counter:users;").await.unwrap().take(0).unwrap();
+        assert_eq!(value, Some(12));
+    }
+}
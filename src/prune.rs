@@ -0,0 +1,90 @@
+use surrealdb::{Connection, Surreal};
+use tracing::info;
+
+use crate::{
+    get_migrations, get_repeatable_migrations, source::{read_migration_files, read_repeatable_files}, Error, FilePattern,
+    MigrationSource,
+};
+
+/// Removes the records of the applied migrations whose file no longer exists (e.g. deleted on purpose) and
+/// returns their filenames, or only returns them in dry run mode.
+pub(crate) async fn run_prune<C: Connection>(
+    db: &Surreal<C>,
+    source: &dyn MigrationSource,
+    table: &str,
+    pattern: &FilePattern,
+    dry_run: bool,
+) -> Result<Vec<String>, Error> {
+    let entries = read_migration_files(source, pattern).await?;
+    let repeatable_entries = read_repeatable_files(source, pattern).await?;
+
+    let migrations = get_migrations(db, table).await?;
+    let repeatable_migrations = get_repeatable_migrations(db, table).await?;
+
+    let mut orphaned: Vec<String> = vec![];
+    let records = migrations
+        .iter()
+        .filter(|migration| !entries.iter().any(|entry| *migration == &entry.filename))
+        .chain(
+            repeatable_migrations
+                .iter()
+                .filter(|migration| !repeatable_entries.iter().any(|entry| *migration == &entry.filename))
+        );
+    for migration in records {
+        if !orphaned.contains(&migration.filename) {
+            orphaned.push(migration.filename.clone());
+        }
+    }
+
+    if dry_run {
+        return Ok(orphaned);
+    }
+
+    for filename in &orphaned {
+        let _ = db
+            .query(format!("DELETE {} WHERE filename=$filename;", table))
+            .bind(("filename", filename.clone()))
+            .await?
+            .check()?;
+
+        info!(filename = %filename, "Record of the deleted file pruned");
+    }
+
+    Ok(orphaned)
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Error, Migrator, VecSource};
+
+    #[tokio::test]
+    async fn it_prunes_the_records_of_the_deleted_files() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        Migrator::new(&db)
+            .source(VecSource::new([
+                ("001_users.surql", "DEFINE TABLE users;"),
+                ("002_tmp.surql", "DEFINE TABLE tmp;"),
+                ("R__views.surql", "DEFINE TABLE views;"),
+            ]))
+            .run()
+            .await
+            .unwrap();
+
+        let source = VecSource::new([("001_users.surql", "DEFINE TABLE users;")]);
+        assert!(matches!(
+            Migrator::new(&db).source(&source).run().await,
+            Err(Error::MissingMigration { .. })
+        ));
+
+        let orphaned = Migrator::new(&db).source(&source).dry_run(true).prune().await.unwrap();
+        assert_eq!(orphaned, ["002_tmp.surql", "R__views.surql"]);
+
+        let pruned = Migrator::new(&db).source(&source).prune().await.unwrap();
+        assert_eq!(pruned, orphaned);
+        assert!(Migrator::new(&db).source(&source).run().await.is_ok());
+    }
+}
@@ -0,0 +1,152 @@
+use std::{collections::HashMap, path::Path};
+
+use surrealdb::{Connection, Surreal};
+use tracing::{debug, error, info};
+
+use crate::{
+    clean::run_clean, lock::lock_table, migrator::{checked_statements, terminate_statements}, parse_statements,
+    seed::read_dir_files, vars::substitute, Error, FilePattern, FsSource, MigrationFile,
+};
+
+/// The default directory of the assertion files.
+pub const DEFAULT_ASSERTIONS_DIR: &str = "./tests";
+
+/// What has been found by the assertion files of a rehearsal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AssertionReport {
+    /// The assertion files whose last statement returned `true`.
+    pub passed: Vec<String>,
+    /// The assertion files whose last statement did not return `true`, or that failed to execute.
+    pub failed: Vec<FailedAssertion>,
+}
+
+impl AssertionReport {
+    /// Returns whether every assertion file passed.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// An assertion file that did not pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedAssertion {
+    pub filename: String,
+    pub message: String,
+}
+
+/// Executes the assertion files of a directory (e.g. `tests/001_assert.surql`), sorted by filename, and checks that
+/// the last statement of each one returns `true`. A failed assertion does not stop the next ones, and a missing
+/// directory has no assertion.
+pub(crate) async fn run_assertions<C: Connection>(
+    db: &Surreal<C>,
+    dir: &Path,
+    vars: &HashMap<String, String>,
+    pattern: &FilePattern,
+) -> Result<AssertionReport, Error> {
+    let mut report = AssertionReport::default();
+    if !tokio::fs::try_exists(dir).await? {
+        debug!(dir = %dir.display(), "No assertion files");
+        return Ok(report);
+    }
+
+    for entry in read_dir_files(&FsSource::new(dir), None, pattern).await? {
+        let content = substitute(&entry.content, vars)?;
+        let entry = MigrationFile { content, ..entry };
+
+        match check_assertion(db, &entry).await {
+            Ok(()) => {
+                info!(filename = %entry.filename, "Assertion passed");
+                report.passed.push(entry.filename);
+            },
+            Err(message) => {
+                error!(filename = %entry.filename, %message, "Assertion failed");
+                report.failed.push(FailedAssertion { filename: entry.filename, message });
+            },
+        }
+    }
+
+    Ok(report)
+}
+
+/// Executes an assertion file, returning why it did not pass.
+async fn check_assertion<C: Connection>(db: &Surreal<C>, entry: &MigrationFile) -> Result<(), String> {
+    let statements = parse_statements(&entry.content).len();
+    if statements == 0 {
+        return Err("The file has no statement.".to_string());
+    }
+
+    let response = db
+        .query(terminate_statements(&entry.content))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let returned = checked_statements(entry, response)
+        .map_err(|e| e.to_string())?
+        .take::<Option<serde_json::Value>>(statements - 1)
+        .map_err(|e| e.to_string())?;
+
+    match returned {
+        Some(serde_json::Value::Bool(true)) => Ok(()),
+        Some(value) => Err(format!("The last statement returned {} instead of true.", value)),
+        None => Err("The last statement returned nothing instead of true.".to_string()),
+    }
+}
+
+/// Removes every table, function, param and analyzer of the database, including the migrations table (but not its
+/// lock, held meanwhile), then imports an export of the database (e.g. written by `surreal export` before a
+/// rehearsal). The users and access methods defined since the export are not removed.
+pub(crate) async fn run_restore<C: Connection>(db: &Surreal<C>, table: &str, path: &Path) -> Result<(), Error> {
+    let removed = run_clean(db, &[lock_table(table)]).await?;
+    debug!(resources = removed.len(), "Database emptied before the restore");
+
+    db.import(path).await?;
+    info!(path = %path.display(), "Database restored");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Migrator, VecSource};
+
+    #[tokio::test]
+    async fn it_rehearses_the_migrations_then_restores_the_database() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let dir = env::temp_dir().join("ssm_rehearsal_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tests")).unwrap();
+        fs::write(dir.join("tests").join("001_assert.surql"), "LET $count = count(SELECT * FROM user);\nRETURN $count = ${USERS};").unwrap();
+        fs::write(dir.join("tests").join("002_assert.surql"), "RETURN (SELECT VALUE active FROM ONLY user:1);").unwrap();
+
+        Migrator::new(&db).source(VecSource::new([("001_users.surql", "DEFINE TABLE user;\nCREATE user:1;")])).run().await.unwrap();
+        let export = dir.join("export.surql");
+        db.export(&export).await.unwrap();
+
+        let source = VecSource::new([
+            ("001_users.surql", "DEFINE TABLE user;\nCREATE user:1;"),
+            ("002_activate.surql", "DEFINE FIELD active ON user TYPE option<bool>;\nCREATE user:2 SET active = true;"),
+        ]);
+        let migrator = Migrator::new(&db).source(&source).assertions_dir(dir.join("tests")).var("USERS", "2");
+        migrator.run().await.unwrap();
+
+        // The first user was not activated by the migration.
+        let report = migrator.run_assertions().await.unwrap();
+        assert_eq!(report.passed, ["001_assert.surql"]);
+        assert_eq!(report.failed[0].filename, "002_assert.surql");
+
+        migrator.restore(&export).await.unwrap();
+
+        let applied: Vec<String> = db.query("SELECT VALUE filename FROM migrations;").await.unwrap().take(0).unwrap();
+        assert_eq!(applied, ["001_users.surql"]);
+        let users: Vec<serde_json::Value> = db.query("SELECT * FROM user;").await.unwrap().take(0).unwrap();
+        assert_eq!(users.len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+use surrealdb::{Connection, Surreal};
+use tracing::info;
+
+use crate::{get_migrations, source::read_migration_files, ChecksumAlgorithm, Error, FilePattern, MigrationFile, MigrationSource};
+
+/// What has been fixed (or would be fixed, in dry run mode) in the migrations table by a repair.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// The migrations whose stored checksum has been updated to match their current file.
+    pub updated_checksums: Vec<String>,
+    /// The migrations whose file no longer exists (e.g. rolled back manually), removed from the table.
+    pub removed: Vec<String>,
+    /// The migrations recorded more than once, whose extra records have been removed.
+    pub deduplicated: Vec<String>,
+    /// Whether the repair was a dry run, in which case nothing has been changed.
+    pub dry_run: bool,
+}
+
+impl RepairReport {
+    /// Returns whether the migrations table was already consistent with the migration files.
+    pub fn is_empty(&self) -> bool {
+        self.updated_checksums.is_empty() && self.removed.is_empty() && self.deduplicated.is_empty()
+    }
+}
+
+/// Reconciles the migrations table with the migration files.
+pub(crate) async fn run_repair<C: Connection>(
+    db: &Surreal<C>,
+    source: &dyn MigrationSource,
+    table: &str,
+    pattern: &FilePattern,
+    algorithm: ChecksumAlgorithm,
+    dry_run: bool,
+) -> Result<RepairReport, Error> {
+    let migrations = get_migrations(db, table).await?;
+    let entries = read_migration_files(source, pattern).await?;
+
+    let mut report = RepairReport { dry_run, ..Default::default() };
+    let mut updated_entries: Vec<&MigrationFile> = vec![];
+
+    for (index, migration) in migrations.iter().enumerate() {
+        // Only consider the first record of a migration recorded several times.
+        if migrations[..index].iter().any(|previous| previous.filename == migration.filename) {
+            if !report.deduplicated.contains(&migration.filename) {
+                report.deduplicated.push(migration.filename.clone());
+            }
+            continue;
+        }
+
+        match entries.iter().find(|entry| migration == &entry.filename) {
+            Some(entry) if migration.checksum.as_ref() != Some(&entry.checksum_for(migration)) => {
+                report.updated_checksums.push(entry.filename.clone());
+                updated_entries.push(entry);
+            },
+            Some(_) => (),
+            None => report.removed.push(migration.filename.clone()),
+        }
+    }
+
+    if dry_run {
+        return Ok(report);
+    }
+
+    for entry in updated_entries {
+        let _ = db
+            .query(format!("UPDATE {} SET checksum=$checksum, checksum_algorithm=$checksum_algorithm WHERE filename=$filename;", table))
+            .bind(("filename", entry.filename.clone()))
+            .bind(("checksum", entry.checksum_with(algorithm)))
+            .bind(("checksum_algorithm", algorithm.name()))
+            .await?
+            .check()?;
+
+        info!(filename = %entry.filename, "Checksum updated");
+    }
+
+    for filename in &report.removed {
+        let _ = db
+            .query(format!("DELETE {} WHERE filename=$filename;", table))
+            .bind(("filename", filename.clone()))
+            .await?
+            .check()?;
+
+        info!(filename = %filename, "Record of the missing file removed");
+    }
+
+    for filename in &report.deduplicated {
+        // Keep the first record only.
+        let sql = format!(
+            r#"
+                LET $records = SELECT VALUE id FROM {table} WHERE filename=$filename ORDER BY created_at ASC;
+                DELETE array::slice($records, 1);
+            "#,
+            table = table
+        );
+
+        let _ = db
+            .query(sql)
+            .bind(("filename", filename.clone()))
+            .await?
+            .check()?;
+
+        info!(filename = %filename, "Duplicated records removed");
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{get_migrations, Migrator, VecSource};
+
+    #[tokio::test]
+    async fn it_keeps_the_application_dates_of_the_repaired_migrations() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        Migrator::new(&db).source(VecSource::new([("001_users.surql", "DEFINE TABLE users;")])).run().await.unwrap();
+        let source = VecSource::new([("001_users.surql", "DEFINE TABLE users;"), ("002_posts.surql", "DEFINE TABLE posts;")]);
+        Migrator::new(&db).source(&source).run().await.unwrap();
+        let before = get_migrations(&db, "migrations").await.unwrap();
+
+        let source = VecSource::new([("001_users.surql", "DEFINE TABLE users SCHEMAFULL;"), ("002_posts.surql", "DEFINE TABLE posts;")]);
+        let report = Migrator::new(&db).source(&source).repair().await.unwrap();
+        assert_eq!(report.updated_checksums, ["001_users.surql"]);
+
+        let after = get_migrations(&db, "migrations").await.unwrap();
+        assert_eq!(after.iter().map(|migration| migration.filename()).collect::<Vec<_>>(), ["001_users.surql", "002_posts.surql"]);
+        assert_eq!(after[0].applied_at(), before[0].applied_at());
+        assert_ne!(after[0].checksum(), before[0].checksum());
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use surrealdb::{Connection, Surreal};
+use tracing::{info, warn};
+
+use crate::{directives::split_sections, get_migrations, get_repeatable_migrations, vars::substitute, Error, MigrationSource};
+
+/// A resource defined by a migration file with a `DEFINE` statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Resource {
+    Table(String),
+    Field { name: String, table: String },
+    Index { name: String, table: String },
+    Event { name: String, table: String },
+    Function(String),
+    Param(String),
+    Analyzer(String),
+}
+
+impl Resource {
+    /// The table the resource is defined on, if any.
+    fn table(&self) -> Option<&str> {
+        match *self {
+            Resource::Field { ref table, .. } | Resource::Index { ref table, .. } | Resource::Event { ref table, .. } => Some(table),
+            _ => None,
+        }
+    }
+
+    /// The statement removing the resource.
+    pub(crate) fn remove_statement(&self) -> String {
+        match *self {
+            Resource::Table(ref name) => format!("REMOVE TABLE IF EXISTS {};", name),
+            Resource::Field { ref name, ref table } => format!("REMOVE FIELD IF EXISTS {} ON TABLE {};", name, table),
+            Resource::Index { ref name, ref table } => format!("REMOVE INDEX IF EXISTS {} ON TABLE {};", name, table),
+            Resource::Event { ref name, ref table } => format!("REMOVE EVENT IF EXISTS {} ON TABLE {};", name, table),
+            Resource::Function(ref name) => format!("REMOVE FUNCTION IF EXISTS {};", name),
+            Resource::Param(ref name) => format!("REMOVE PARAM IF EXISTS {};", name),
+            Resource::Analyzer(ref name) => format!("REMOVE ANALYZER IF EXISTS {};", name),
+        }
+    }
+}
+
+/// Returns the resources defined by a migration file, in order of definition.
+fn defined_resources(content: &str) -> Vec<Resource> {
+    let regex = Regex::new(
+        r"(?i)\bDEFINE\s+(TABLE|FIELD|INDEX|EVENT|FUNCTION|PARAM|ANALYZER)\s+(?:IF\s+NOT\s+EXISTS\s+|OVERWRITE\s+)?([^\s(;]+)(?:\s+ON\s+(?:TABLE\s+)?([a-zA-Z_][a-zA-Z_0-9]*))?"
+    ).expect("Failed to build the regexp");
+
+    // Ignore the commented out statements.
+    let content: String = content
+        .lines()
+        .filter(|line| {
+            let line = line.trim_start();
+            !(line.starts_with("--") || line.starts_with("//") || line.starts_with('#'))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    regex
+        .captures_iter(&content)
+        .filter_map(|captures| {
+            let name = captures[2].to_string();
+            let table = captures.get(3).map(|table| table.as_str().to_string());
+
+            match (captures[1].to_uppercase().as_str(), table) {
+                ("TABLE", _) => Some(Resource::Table(name)),
+                ("FIELD", Some(table)) => Some(Resource::Field { name, table }),
+                ("INDEX", Some(table)) => Some(Resource::Index { name, table }),
+                ("EVENT", Some(table)) => Some(Resource::Event { name, table }),
+                ("FUNCTION", _) => Some(Resource::Function(name)),
+                ("PARAM", _) => Some(Resource::Param(name)),
+                ("ANALYZER", _) => Some(Resource::Analyzer(name)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Removes the resources defined by the applied migration files, then the migrations table.
+pub(crate) async fn run_reset<C: Connection>(
+    db: &Surreal<C>,
+    source: &dyn MigrationSource,
+    table: &str,
+    vars: &HashMap<String, String>,
+) -> Result<(), Error> {
+    let migrations = get_migrations(db, table).await?;
+    let repeatable_migrations = get_repeatable_migrations(db, table).await?;
+
+    // Remove the resources in the reverse order of their definition (the repeatable files run last).
+    let mut resources: Vec<Resource> = vec![];
+    for migration in repeatable_migrations.iter().rev().chain(migrations.iter().rev()) {
+        // The files for other environments have not been executed.
+        if migration.skipped == Some(true) {
+            continue;
+        }
+
+        let Some(content) = source.read(&migration.filename).await? else {
+            warn!(filename = %migration.filename, "Missing migration file, its resources are not removed");
+            continue;
+        };
+
+        let (up, _) = split_sections(&content);
+        for resource in defined_resources(&substitute(up, vars)?).into_iter().rev() {
+            if !resources.contains(&resource) {
+                resources.push(resource);
+            }
+        }
+    }
+
+    // The fields, indexes and events of a removed table are removed with it.
+    let tables: Vec<Resource> = resources
+        .iter()
+        .filter(|resource| matches!(resource, Resource::Table(_)))
+        .cloned()
+        .collect();
+    resources.retain(|resource| match resource.table() {
+        Some(name) => !tables.contains(&Resource::Table(name.to_string())),
+        None => true,
+    });
+
+    let statements: Vec<String> = resources
+        .iter()
+        .map(Resource::remove_statement)
+        .collect();
+
+    let sql = format!(
+        "BEGIN TRANSACTION;\n{}\nREMOVE TABLE IF EXISTS {};\nCOMMIT TRANSACTION;",
+        statements.join("\n"),
+        table
+    );
+
+    let _ = db.query(sql).await?.check()?;
+
+    for resource in &resources {
+        info!(resource = ?resource, "Resource removed");
+    }
+    info!(table = %table, "Migrations table removed");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{defined_resources, Resource};
+
+    #[test]
+    fn it_parses_the_defined_resources() {
+        let content = "
+            -- DEFINE TABLE commented;
+            DEFINE TABLE users SCHEMAFULL;
+            DEFINE FIELD IF NOT EXISTS email ON TABLE users TYPE string;
+            define index email_idx on users fields email unique;
+            DEFINE EVENT OVERWRITE email_changed ON users WHEN $before.email != $after.email THEN {};
+            DEFINE FUNCTION fn::greet($name: string) { RETURN 'Hello ' + $name; };
+            DEFINE PARAM $endpoint VALUE 'https://example.com';
+            DEFINE ANALYZER simple TOKENIZERS blank;
+            DEFINE TABLE posts;
+        ";
+
+        assert_eq!(
+            defined_resources(content),
+            vec![
+                Resource::Table("users".to_string()),
+                Resource::Field { name: "email".to_string(), table: "users".to_string() },
+                Resource::Index { name: "email_idx".to_string(), table: "users".to_string() },
+                Resource::Event { name: "email_changed".to_string(), table: "users".to_string() },
+                Resource::Function("fn::greet".to_string()),
+                Resource::Param("$endpoint".to_string()),
+                Resource::Analyzer("simple".to_string()),
+                Resource::Table("posts".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use std::{future::Future, time::Duration};
+
+use tokio::time::sleep;
+use tracing::warn;
+
+use crate::Error;
+
+/// The default delay before the first retry of a failed operation.
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// How the operations failing with a transient error (e.g. a WebSocket connection dropped while the
+/// SurrealDB pods roll out) are retried, with an exponential backoff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times a failed operation is retried. (default: 0)
+    pub retries: u32,
+    /// The delay before the first retry, doubled for each of the next ones. (default: 500ms)
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            delay: DEFAULT_RETRY_DELAY,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(retries: u32, delay: Duration) -> Self {
+        RetryPolicy { retries, delay }
+    }
+
+    /// Runs an operation, given the number of the attempt (starting from 0), until it succeeds, fails with
+    /// an error that is not transient, or the retries are exhausted.
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T, Error>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            match operation(attempt).await {
+                Err(err) if attempt < self.retries && err.is_transient() => {
+                    let delay = self.delay.saturating_mul(2u32.saturating_pow(attempt));
+                    attempt += 1;
+
+                    warn!(error = %err, attempt, retries = self.retries, ?delay, "Transient error, retrying");
+                    sleep(delay).await;
+                },
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use crate::Error;
+
+    use super::RetryPolicy;
+
+    fn connection_reset() -> Error {
+        Error::IO(io::Error::new(io::ErrorKind::ConnectionReset, "reset"))
+    }
+
+    #[tokio::test]
+    async fn it_retries_the_transient_errors() {
+        let policy = RetryPolicy::new(2, Duration::from_millis(1));
+
+        let mut attempts = 0;
+        let result = policy
+            .run(|attempt| {
+                attempts += 1;
+                async move { if attempt < 2 { Err(connection_reset()) } else { Ok(attempt) } }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let result = policy
+            .run(|_| {
+                attempts += 1;
+                async { Err::<(), _>(connection_reset()) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let result = policy
+            .run(|_| {
+                attempts += 1;
+                async { Err::<(), _>(Error::UndefinedVariable { name: "NAME".to_string() }) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
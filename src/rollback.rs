@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use surrealdb::{Connection, Surreal};
+use tracing::{error, info};
+
+use crate::{
+    compare_versions, directives::split_sections, get_migrations, vars::substitute, version, Error, Migration, MigrationSource,
+};
+
+/// How many of the applied migrations should be rolled back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RollbackTarget {
+    /// Roll back the last `n` applied migrations.
+    Steps(usize),
+    /// Roll back every migration whose number prefix is greater than the given version (e.g. `"004"`).
+    To(String),
+}
+
+/// Returns the name of the down migration file paired with a migration file, with the same extension.
+/// (e.g. `002_create_post_table.surql` -> `002_create_post_table.down.surql`)
+pub fn down_filename(filename: &str) -> String {
+    match filename.rsplit_once('.') {
+        Some((name, extension)) => format!("{}.down.{}", name, extension),
+        None => format!("{}.down.surql", filename),
+    }
+}
+
+pub(crate) async fn run_rollback<C: Connection>(
+    db: &Surreal<C>,
+    source: &dyn MigrationSource,
+    table: &str,
+    target: RollbackTarget,
+    vars: &HashMap<String, String>,
+) -> Result<(), Error> {
+    let migrations = get_migrations(db, table).await?;
+
+    // Select the migrations to roll back, the most recent first.
+    let targets: Vec<&Migration> = match target {
+        RollbackTarget::Steps(steps) => migrations
+            .iter()
+            .rev()
+            .take(steps)
+            .collect(),
+        RollbackTarget::To(ref to) => migrations
+            .iter()
+            .rev()
+            .filter(|migration| compare_versions(version(&migration.filename), version(to)).is_gt())
+            .collect(),
+    };
+
+    // Read every down section or file before executing anything, so a missing one does not leave a partial rollback.
+    let mut down_migrations: Vec<(&Migration, Option<String>)> = vec![];
+    for migration in targets {
+        // A file for other environments has not been executed: only its record is removed.
+        if migration.skipped == Some(true) {
+            down_migrations.push((migration, None));
+            continue;
+        }
+
+        let down_filename = down_filename(&migration.filename);
+
+        let down_section = source
+            .read(&migration.filename)
+            .await?
+            .and_then(|content| split_sections(&content).1.map(str::to_string));
+        let content = match down_section {
+            Some(content) => Some(content),
+            None => source.read(&down_filename).await?,
+        };
+
+        match content {
+            Some(content) => down_migrations.push((migration, Some(substitute(&content, vars)?))),
+            None => {
+                error!(filename = %migration.filename, "Missing down migration file");
+                return Err(
+                    Error::MissingDownMigration {
+                        filename: migration.filename.clone(),
+                        down_filename,
+                    }
+                );
+            },
+        }
+    }
+
+    for (migration, content) in down_migrations {
+        if let Some(content) = content {
+            let _ = db.query(content).await?.check()?;
+        }
+        let _ = db
+            .query(format!("DELETE {} WHERE filename=$filename;", table))
+            .bind(("filename", migration.filename.clone()))
+            .await?
+            .check()?;
+
+        info!(filename = %migration.filename, "File successfully rolled back");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use std::{collections::HashMap, path::Path};
+
+use regex::Regex;
+use serde::Deserialize;
+use surrealdb::{Connection, Surreal};
+use tracing::{debug, error, info, instrument};
+
+use crate::{
+    checksum, migrator::{check_statements, terminate_statements}, vars::substitute, Error, FilePattern, FsSource,
+    MigrationFile, MigrationSource,
+};
+
+/// The default name of the table tracking the applied seed files.
+pub const DEFAULT_SEEDS_TABLE: &str = "seeds";
+
+/// What has been done by a seed run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    /// The seed files applied during the run, new or changed since their last application.
+    pub applied: Vec<String>,
+    /// The seed files unchanged since their last application.
+    pub skipped: Vec<String>,
+}
+
+/// A seed file recorded in the seeds table.
+#[derive(Deserialize, Debug)]
+struct Seed {
+    filename: String,
+    checksum: String,
+}
+
+/// Applies the new or changed seed files of a directory: the `.surql` files (or the ones with an extension of the
+/// pattern) of the directory, then the ones of its `<env>` subdirectory (recorded as `<env>/<filename>`).
+pub(crate) async fn run_seeds<C: Connection>(
+    db: &Surreal<C>,
+    dir: &Path,
+    env: Option<&str>,
+    table: &str,
+    vars: &HashMap<String, String>,
+    pattern: &FilePattern,
+) -> Result<SeedReport, Error> {
+    setup_seeds_table(db, table).await?;
+
+    let seeds = db
+        .query(format!("SELECT filename, checksum FROM {};", table))
+        .await?
+        .check()?
+        .take::<Vec<Seed>>(0)?;
+
+    let mut report = SeedReport::default();
+
+    for entry in read_seed_files(dir, env, pattern).await? {
+        let unchanged = seeds
+            .iter()
+            .any(|seed| seed.filename == entry.filename && seed.checksum == entry.checksum);
+
+        if unchanged {
+            debug!(filename = %entry.filename, "Seed file already applied");
+            report.skipped.push(entry.filename);
+            continue;
+        }
+
+        let filename = entry.filename.clone();
+        apply_seed(db, entry, env, table, vars).await?;
+        report.applied.push(filename);
+    }
+
+    Ok(report)
+}
+
+/// Returns the seed files shared by every environment, then the ones of the environment, each sorted by filename.
+async fn read_seed_files(dir: &Path, env: Option<&str>, pattern: &FilePattern) -> Result<Vec<MigrationFile>, Error> {
+    let mut entries = read_dir_files(&FsSource::new(dir), None, pattern).await?;
+
+    if let Some(env) = env {
+        let regex = Regex::new(r"^[a-zA-Z0-9_-]+$").expect("Failed to build the regexp");
+        if !regex.is_match(env) {
+            return Err(
+                Error::InvalidName {
+                    name: env.to_string(),
+                    message: format!("The seed environment '{}' must only contain letters, numbers, dashes and underscores.", env),
+                }
+            );
+        }
+
+        if dir.join(env).is_dir() {
+            entries.extend(read_dir_files(&FsSource::new(dir.join(env)), Some(env), pattern).await?);
+        } else {
+            debug!(env = %env, "No seed files for the environment");
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Reads the `.surql` files of a directory, named after the environment they belong to, if any.
+pub(crate) async fn read_dir_files(source: &FsSource, env: Option<&str>, pattern: &FilePattern) -> Result<Vec<MigrationFile>, Error> {
+    let mut filenames: Vec<String> = source
+        .list()
+        .await?
+        .into_iter()
+        .filter(|filename| pattern.has_extension(filename))
+        .collect();
+    filenames.sort();
+
+    let mut entries: Vec<MigrationFile> = vec![];
+    for filename in filenames {
+        let Some(content) = source.read(&filename).await? else {
+            continue;
+        };
+
+        entries.push(MigrationFile {
+            filename: match env {
+                Some(env) => format!("{}/{}", env, filename),
+                None => filename,
+            },
+            checksum: checksum(&content),
+            raw_content: content.clone(),
+            content,
+            streamed_checksums: None,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Applies a seed file and records it in the seeds table, in a transaction.
+#[instrument(name = "seed", skip_all, fields(filename = %entry.filename))]
+async fn apply_seed<C: Connection>(
+    db: &Surreal<C>,
+    entry: MigrationFile,
+    env: Option<&str>,
+    table: &str,
+    vars: &HashMap<String, String>,
+) -> Result<(), Error> {
+    // The checksum stays the one of the file, whatever the values of its variables.
+    let entry = match substitute(&entry.content, vars) {
+        Ok(content) => MigrationFile { content, ..entry },
+        Err(err) => {
+            error!(error = %err, "Failed to apply the seed file");
+            return Err(err);
+        },
+    };
+
+    // Replace the record of the previous application of the file.
+    let sql = format!(
+        "BEGIN TRANSACTION;\n{}\nDELETE {table} WHERE filename=$filename;\nCREATE {table} SET filename=$filename, checksum=$checksum, env=$env;\nCOMMIT TRANSACTION;",
+        terminate_statements(&entry.content),
+        table = table
+    );
+
+    let response = db
+        .query(sql)
+        .bind(("filename", entry.filename.clone()))
+        .bind(("checksum", entry.checksum.clone()))
+        .bind(("env", env.map(str::to_string)))
+        .await?;
+
+    if let Err(err) = check_statements(&entry, response) {
+        error!(error = %err, "Failed to apply the seed file");
+        return Err(err);
+    }
+
+    info!("Seed file successfully applied");
+
+    Ok(())
+}
+
+async fn setup_seeds_table<C: Connection>(db: &Surreal<C>, table: &str) -> Result<(), surrealdb::Error> {
+    let sql = format!(
+        r#"
+            DEFINE TABLE IF NOT EXISTS {table} SCHEMAFULL;
+            DEFINE FIELD IF NOT EXISTS filename ON TABLE {table} TYPE string;
+            DEFINE FIELD IF NOT EXISTS checksum ON TABLE {table} TYPE string;
+            DEFINE FIELD IF NOT EXISTS env ON TABLE {table} TYPE option<string>;
+            DEFINE FIELD IF NOT EXISTS created_at ON TABLE {table} TYPE datetime VALUE time::now();
+        "#,
+        table = table
+    );
+
+    let _ = db
+        .query(sql)
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::FilePattern;
+
+    use super::read_seed_files;
+
+    #[tokio::test]
+    async fn it_reads_the_seed_files_of_an_environment() {
+        let dir = env::temp_dir().join("ssm_seed_files_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("dev")).unwrap();
+        fs::create_dir_all(dir.join("prod")).unwrap();
+
+        fs::write(dir.join("roles.surql"), "UPSERT role:admin;").unwrap();
+        fs::write(dir.join("countries.surql"), "UPSERT country:fr;").unwrap();
+        fs::write(dir.join("README.md"), "Seeds").unwrap();
+        fs::write(dir.join("dev").join("users.surql"), "UPSERT user:test;").unwrap();
+        fs::write(dir.join("prod").join("users.surql"), "UPSERT user:admin;").unwrap();
+
+        let filenames = |entries: Vec<crate::MigrationFile>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.filename).collect()
+        };
+
+        assert_eq!(
+            filenames(read_seed_files(&dir, Some("dev"), &FilePattern::default()).await.unwrap()),
+            ["countries.surql", "roles.surql", "dev/users.surql"]
+        );
+        assert_eq!(
+            filenames(read_seed_files(&dir, None, &FilePattern::default()).await.unwrap()),
+            ["countries.surql", "roles.surql"]
+        );
+        assert!(read_seed_files(&dir, Some("../prod"), &FilePattern::default()).await.is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
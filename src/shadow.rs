@@ -0,0 +1,149 @@
+use surrealdb::{Connection, Surreal};
+use tokio::runtime::Handle;
+use tracing::{debug, error, info, warn};
+
+use crate::Error;
+
+/// The migrated database of the session, while the handle is switched to the shadow database. Every clone of
+/// the handle shares the session, so it is switched back by `remove_shadow_database`, or when dropped before
+/// (e.g. the run was cancelled during the replay).
+pub(crate) struct ShadowSession<C: Connection> {
+    db: Surreal<C>,
+    database: String,
+    restored: bool,
+}
+
+impl<C: Connection> Drop for ShadowSession<C> {
+    fn drop(&mut self) {
+        if self.restored {
+            return;
+        }
+
+        // Drop cannot wait, so the switch back is spawned on the runtime of the migrator.
+        let Ok(runtime) = Handle::try_current() else {
+            return;
+        };
+        let db = self.db.clone();
+        let database = std::mem::take(&mut self.database);
+        runtime.spawn(async move {
+            if let Err(e) = db.use_db(database.as_str()).await {
+                error!(%database, error = %e, "The session could not be switched back to the database");
+            }
+        });
+    }
+}
+
+/// Defines the scratch database the migration history is replayed into, in the namespace of the session, and
+/// returns the database of the session to switch back to. An existing database is refused, so the shadow
+/// database removed afterwards is always one the migrator created.
+pub(crate) async fn define_shadow_database<C: Connection>(db: &Surreal<C>, shadow: &str) -> Result<ShadowSession<C>, Error> {
+    if shadow.contains('`') {
+        return Err(
+            Error::InvalidName {
+                name: shadow.to_string(),
+                message: format!("The shadow database name '{}' must not contain a backtick.", shadow),
+            }
+        );
+    }
+
+    let database: Option<String> = db
+        .query("RETURN session::db();")
+        .await?
+        .check()?
+        .take(0)?;
+
+    let Some(database) = database else {
+        return Err(
+            Error::InvalidName {
+                name: shadow.to_string(),
+                message: "The shadow database needs the database of the connection to be selected.".to_string(),
+            }
+        );
+    };
+
+    if database == shadow {
+        return Err(
+            Error::InvalidName {
+                name: shadow.to_string(),
+                message: format!("The shadow database must not be the migrated database '{}'.", database),
+            }
+        );
+    }
+
+    let _ = db
+        .query(format!("DEFINE DATABASE `{}`;", shadow))
+        .await?
+        .check()?;
+
+    debug!(%shadow, "Shadow database defined");
+
+    Ok(ShadowSession { db: db.clone(), database, restored: false })
+}
+
+/// Switches the session back to the migrated database and removes the shadow database, even when the replay
+/// failed, then returns the result of the replay.
+pub(crate) async fn remove_shadow_database<C: Connection>(
+    mut session: ShadowSession<C>,
+    shadow: &str,
+    replayed: Result<(), Error>,
+) -> Result<(), Error> {
+    let db = session.db.clone();
+    let restored = db.use_db(session.database.as_str()).await;
+    session.restored = restored.is_ok();
+    let removed = match restored {
+        Ok(()) => db.query(format!("REMOVE DATABASE IF EXISTS `{}`;", shadow)).await.and_then(|response| response.check()).map(|_| ()),
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = replayed {
+        if let Err(ref removal) = removed {
+            warn!(%shadow, error = %removal, "The shadow database could not be removed");
+        }
+        error!(%shadow, error = %e, "The migrations failed on the shadow database");
+        return Err(Error::ShadowFailed { database: shadow.to_string(), source: Box::new(e) });
+    }
+    removed?;
+
+    info!(%shadow, "The migration history applies to an empty database");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Error, Migrator, VecSource};
+
+    #[tokio::test]
+    async fn it_replays_the_history_on_a_shadow_database_first() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        // The record was created by hand on the database, so the second file only applies to it.
+        db.query("CREATE legacy:admin;").await.unwrap().check().unwrap();
+        let source = VecSource::new([
+            ("001_users.surql", "DEFINE TABLE users;"),
+            (
+                "002_admin.surql",
+                "LET $admin = SELECT * FROM legacy:admin;\nIF array::len($admin) = 0 { THROW 'legacy:admin is missing' };",
+            ),
+        ]);
+
+        let result = Migrator::new(&db).source(&source).shadow_database("shadow").run().await;
+        assert!(matches!(result, Err(Error::ShadowFailed { .. })), "{:?}", result);
+
+        // Nothing was applied to the database, and the shadow database is removed.
+        let applied: Vec<String> = db.query("SELECT VALUE filename FROM migrations;").await.unwrap().take(0).unwrap();
+        assert!(applied.is_empty());
+        let databases: Option<serde_json::Value> = db.query("INFO FOR NS;").await.unwrap().take((0, "databases")).unwrap();
+        assert!(databases.unwrap().get("shadow").is_none());
+
+        // The handle is switched back to the database, for the migrator and its clones alike.
+        let database: Option<String> = db.clone().query("RETURN session::db();").await.unwrap().take(0).unwrap();
+        assert_eq!(database.as_deref(), Some("test"));
+
+        let report = Migrator::new(&db).source(&source).run().await.unwrap();
+        assert_eq!(report.applied.len(), 2);
+    }
+}
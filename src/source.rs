@@ -0,0 +1,725 @@
+use std::{cmp::Reverse, collections::{BinaryHeap, HashMap}, path::{Path, PathBuf}, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use futures::{stream, StreamExt, TryStreamExt};
+use include_dir::Dir;
+use tokio::{
+    fs::{read_dir, read_to_string, File},
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader},
+};
+use tracing::{debug, error};
+
+use crate::{
+    checksum,
+    checksum::{ChecksumAlgorithm, StreamingDigest},
+    compare_versions,
+    directives::{is_down_marker, split_sections},
+    version, Directives, Error, FilePattern, MigrationFile,
+};
+
+/// How many migration files are read and hashed at the same time.
+const READ_CONCURRENCY: usize = 16;
+
+/// The size (in bytes) above which `FsSource` streams the migration files instead of reading them at once: 64 MiB.
+pub const DEFAULT_STREAM_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// A reader of a migration file too large to be read at once.
+pub type MigrationReader = Box<dyn AsyncBufRead + Send + Unpin>;
+
+/// Where the migration files are read from.
+///
+/// A source only lists and reads files: the filtering on the migration pattern
+/// (`<file_number>(_<filename>).surql`, see `FilePattern`), the ordering and the checksums are handled by the
+/// migrator.
+#[async_trait]
+pub trait MigrationSource: Send + Sync {
+    /// Returns the names of the files of the source.
+    async fn list(&self) -> Result<Vec<String>, Error>;
+
+    /// Returns the content of a file of the source, if it exists.
+    async fn read(&self, filename: &str) -> Result<Option<String>, Error>;
+
+    /// Returns a reader of a file of the source when it is too large to be read at once (e.g. a data backfill),
+    /// so its statements are streamed to the database in batches. None (the default) when the file is read with
+    /// `read`.
+    async fn open(&self, _filename: &str) -> Result<Option<MigrationReader>, Error> {
+        Ok(None)
+    }
+
+    /// Returns the path of a file of the source on the filesystem, if it is read from there and exists. None
+    /// (the default) for the sources held in memory or embedded in the binary.
+    async fn path(&self, _filename: &str) -> Result<Option<PathBuf>, Error> {
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl<S: MigrationSource + ?Sized> MigrationSource for &S {
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        (**self).list().await
+    }
+
+    async fn read(&self, filename: &str) -> Result<Option<String>, Error> {
+        (**self).read(filename).await
+    }
+
+    async fn open(&self, filename: &str) -> Result<Option<MigrationReader>, Error> {
+        (**self).open(filename).await
+    }
+
+    async fn path(&self, filename: &str) -> Result<Option<PathBuf>, Error> {
+        (**self).path(filename).await
+    }
+}
+
+/// The file of a migration directory listing the gitignore-style patterns of the files `FsSource` leaves out
+/// (e.g. work in progress, or files for another environment).
+pub const IGNORE_FILE: &str = ".ssmignore";
+
+/// The subdirectory of a migration directory holding the namespace migrations (e.g. `DEFINE DATABASE`,
+/// `DEFINE USER ... ON NAMESPACE`), applied with a namespace user and tracked apart from the migrations of the
+/// databases. A recursive `FsSource` leaves it out.
+pub const NAMESPACE_DIR: &str = "namespace";
+
+/// Migration files read from a directory of the filesystem, except the ones ignored by its `.ssmignore` file.
+#[derive(Debug, Clone)]
+pub struct FsSource {
+    dir: PathBuf,
+    recursive: bool,
+    stream_threshold: u64,
+    /// The paths of the listed files by filename, in recursive mode: built by the last `list`, so the files are
+    /// read without walking the directory again.
+    paths: Arc<Mutex<Option<HashMap<String, Vec<PathBuf>>>>>,
+}
+
+impl PartialEq for FsSource {
+    fn eq(&self, other: &Self) -> bool {
+        self.dir == other.dir && self.recursive == other.recursive && self.stream_threshold == other.stream_threshold
+    }
+}
+
+impl Eq for FsSource {}
+
+impl FsSource {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FsSource {
+            dir: dir.into(),
+            recursive: false,
+            stream_threshold: DEFAULT_STREAM_THRESHOLD,
+            paths: Arc::default(),
+        }
+    }
+
+    /// Also read the files of the subdirectories (e.g. `2024/`, `modules/auth/`), except the hidden ones and the
+    /// `namespace/` directory of the namespace migrations (see `NAMESPACE_DIR`). The files are listed by name
+    /// whatever their directory, so they are ordered by their number prefix and can be moved between directories.
+    /// (default: false)
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// The size (in bytes) above which the migration files are streamed instead of read at once, keeping the
+    /// memory bounded: their statements are sent to the database in batches, outside of a transaction.
+    /// (default: `DEFAULT_STREAM_THRESHOLD`, 64 MiB)
+    pub fn stream_threshold(mut self, stream_threshold: u64) -> Self {
+        self.stream_threshold = stream_threshold;
+        self
+    }
+
+    /// Returns the path of a file of the directory or, when recursive, of a listed file of its subdirectories.
+    async fn file_path(&self, filename: &str) -> Result<Option<PathBuf>, Error> {
+        if !self.recursive {
+            return Ok(Some(self.dir.join(filename)));
+        }
+
+        // A file read before any listing.
+        if self.paths.lock().expect("Failed to lock the paths").is_none() {
+            self.listed_paths().await?;
+        }
+
+        let paths = self.paths.lock().expect("Failed to lock the paths");
+        match paths.as_ref().and_then(|paths| paths.get(filename)).map(Vec::as_slice) {
+            Some([path]) => Ok(Some(path.clone())),
+            Some([first, second, ..]) => {
+                let (first, second) = (self.relative_path(first), self.relative_path(second));
+                error!(%first, %second, "Migration files with the same name");
+
+                Err(Error::DuplicateVersion { first, second })
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the path of a file relative to the directory, with `/` separators whatever the platform.
+    fn relative_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.dir)
+            .unwrap_or(path)
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Returns the paths of the files of the directory (and of its subdirectories when recursive) not ignored by its
+    /// `.ssmignore` file, and records them by filename when recursive.
+    async fn listed_paths(&self) -> Result<Vec<PathBuf>, Error> {
+        let ignore = match read_to_string(self.dir.join(IGNORE_FILE)).await {
+            Ok(content) => FilePattern::new().ignore(content.lines()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => FilePattern::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let paths: Vec<PathBuf> = self
+            .paths()
+            .await?
+            .into_iter()
+            .filter(|path| !ignore.is_ignored(&self.relative_path(path)))
+            .collect();
+
+        if self.recursive {
+            let mut filenames: HashMap<String, Vec<PathBuf>> = HashMap::new();
+            for path in &paths {
+                if let Some(filename) = path.file_name().and_then(|name| name.to_str()) {
+                    filenames.entry(filename.to_string()).or_default().push(path.clone());
+                }
+            }
+            for paths in filenames.values_mut() {
+                paths.sort();
+            }
+            *self.paths.lock().expect("Failed to lock the paths") = Some(filenames);
+        }
+
+        Ok(paths)
+    }
+
+    /// Returns the paths of the files of the directory, and of its subdirectories when recursive.
+    async fn paths(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut dirs: Vec<PathBuf> = vec![self.dir.clone()];
+        let mut paths: Vec<PathBuf> = vec![];
+
+        while let Some(dir) = dirs.pop() {
+            let mut entries = read_dir(&dir).await?;
+
+            while let Some(dir_entry) = entries.next_entry().await? {
+                let is_hidden = dir_entry.file_name().to_string_lossy().starts_with('.');
+
+                if self.recursive && !is_hidden && dir_entry.file_type().await?.is_dir() {
+                    if dir != self.dir || dir_entry.file_name() != NAMESPACE_DIR {
+                        dirs.push(dir_entry.path());
+                    }
+                } else {
+                    paths.push(dir_entry.path());
+                }
+            }
+        }
+
+        Ok(paths)
+    }
+}
+
+#[async_trait]
+impl MigrationSource for FsSource {
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        let filenames = self
+            .listed_paths()
+            .await?
+            .iter()
+            .filter_map(|path| path.file_name()?.to_str())
+            .map(str::to_string)
+            .collect();
+
+        Ok(filenames)
+    }
+
+    async fn read(&self, filename: &str) -> Result<Option<String>, Error> {
+        let Some(path) = self.file_path(filename).await? else {
+            return Ok(None);
+        };
+
+        match read_to_string(path).await {
+            Ok(content) => Ok(Some(content)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn open(&self, filename: &str) -> Result<Option<MigrationReader>, Error> {
+        let Some(path) = self.file_path(filename).await? else {
+            return Ok(None);
+        };
+
+        let file = match File::open(path).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        if file.metadata().await?.len() <= self.stream_threshold {
+            return Ok(None);
+        }
+
+        Ok(Some(Box::new(BufReader::new(file))))
+    }
+
+    async fn path(&self, filename: &str) -> Result<Option<PathBuf>, Error> {
+        Ok(self.file_path(filename).await?.filter(|path| path.is_file()))
+    }
+}
+
+/// Migration files embedded in the binary at compile time, built with the `embed_migrations!` macro.
+pub struct EmbeddedSource {
+    dir: Dir<'static>,
+}
+
+impl EmbeddedSource {
+    pub const fn new(dir: Dir<'static>) -> Self {
+        EmbeddedSource { dir }
+    }
+}
+
+#[async_trait]
+impl MigrationSource for EmbeddedSource {
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        let filenames = self.dir
+            .files()
+            .filter_map(|file| file.path().file_name()?.to_str())
+            .map(str::to_string)
+            .collect();
+
+        Ok(filenames)
+    }
+
+    async fn read(&self, filename: &str) -> Result<Option<String>, Error> {
+        let Some(file) = self.dir.get_file(filename) else {
+            return Ok(None);
+        };
+
+        match file.contents_utf8() {
+            Some(content) => Ok(Some(content.to_string())),
+            None => Err(
+                Error::IO(
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("The embedded migration file '{}' is not valid UTF-8.", filename)
+                    )
+                )
+            ),
+        }
+    }
+}
+
+/// Embeds the migration files of a directory in the binary at compile time.
+///
+/// The path is resolved from the directory the compiler runs in, so prefer using
+/// `$CARGO_MANIFEST_DIR`:
+///
+/// ```ignore
+/// static MIGRATIONS: EmbeddedSource = embed_migrations!("$CARGO_MANIFEST_DIR/migrations");
+///
+/// Migrator::new(&db).source(&MIGRATIONS).run().await?;
+/// ```
+#[macro_export]
+macro_rules! embed_migrations {
+    ($path:literal) => {{
+        // The expansion of `include_dir!` refers to the `include_dir` crate by name.
+        use $crate::include_dir;
+
+        $crate::EmbeddedSource::new(include_dir::include_dir!($path))
+    }};
+}
+
+/// Migration files held in memory as (filename, content) pairs, e.g. for tests or migrations
+/// fetched from an artifact store.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VecSource {
+    files: Vec<(String, String)>,
+}
+
+impl VecSource {
+    pub fn new<N: Into<String>, S: Into<String>>(files: impl IntoIterator<Item = (N, S)>) -> Self {
+        VecSource {
+            files: files
+                .into_iter()
+                .map(|(filename, content)| (filename.into(), content.into()))
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl MigrationSource for VecSource {
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        Ok(self.files.iter().map(|(filename, _)| filename.clone()).collect())
+    }
+
+    async fn read(&self, filename: &str) -> Result<Option<String>, Error> {
+        let content = self.files
+            .iter()
+            .find(|(name, _)| name == filename)
+            .map(|(_, content)| content.clone());
+
+        Ok(content)
+    }
+}
+
+/// Migration files merged from several sources (e.g. the migration directories of several crates of a
+/// workspace), applied as a single plan ordered by their number prefix. Two files sharing a version in
+/// different sources make the migration fail with a `DuplicateVersion` error.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MultiSource<S> {
+    sources: Vec<S>,
+}
+
+impl<S: MigrationSource> MultiSource<S> {
+    pub fn new(sources: impl IntoIterator<Item = S>) -> Self {
+        MultiSource { sources: sources.into_iter().collect() }
+    }
+}
+
+#[async_trait]
+impl<S: MigrationSource> MigrationSource for MultiSource<S> {
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        let mut filenames: Vec<String> = vec![];
+        for source in &self.sources {
+            filenames.extend(source.list().await?);
+        }
+
+        Ok(filenames)
+    }
+
+    async fn read(&self, filename: &str) -> Result<Option<String>, Error> {
+        for source in &self.sources {
+            if let Some(content) = source.read(filename).await? {
+                return Ok(Some(content));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn open(&self, filename: &str) -> Result<Option<MigrationReader>, Error> {
+        for source in &self.sources {
+            if let Some(reader) = source.open(filename).await? {
+                return Ok(Some(reader));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn path(&self, filename: &str) -> Result<Option<PathBuf>, Error> {
+        for source in &self.sources {
+            if let Some(path) = source.path(filename).await? {
+                return Ok(Some(path));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Reads the files of a source that fit the migration pattern, sorted by filename.
+pub(crate) async fn read_migration_files(source: &dyn MigrationSource, pattern: &FilePattern) -> Result<Vec<MigrationFile>, Error> {
+    let entries = read_files(source, |filename| pattern.is_migration(filename)).await?;
+
+    // The files are sorted by version, so files sharing a version are next to each other.
+    for pair in entries.windows(2) {
+        if compare_versions(version(&pair[0].filename), version(&pair[1].filename)).is_eq() {
+            error!(first = %pair[0].filename, second = %pair[1].filename, "Two migration files share the same version");
+            return Err(
+                Error::DuplicateVersion {
+                    first: pair[0].filename.clone(),
+                    second: pair[1].filename.clone(),
+                }
+            );
+        }
+    }
+
+    order_dependencies(entries)
+}
+
+/// Orders the migration files (sorted by version) so each one comes after the files it requires with a
+/// `-- ssm:requires` directive, keeping the version order otherwise.
+fn order_dependencies(entries: Vec<MigrationFile>) -> Result<Vec<MigrationFile>, Error> {
+    // The files by name, with and without their extension.
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        positions.entry(entry.filename.as_str()).or_insert(index);
+        if let Some((stem, _)) = entry.filename.rsplit_once('.') {
+            positions.entry(stem).or_insert(index);
+        }
+    }
+
+    let mut dependents: Vec<Vec<usize>> = vec![vec![]; entries.len()];
+    let mut in_degrees: Vec<usize> = vec![0; entries.len()];
+    for (index, entry) in entries.iter().enumerate() {
+        for name in Directives::parse(&entry.content).requires {
+            let Some(&required) = positions.get(name.as_str()) else {
+                error!(filename = %entry.filename, requires = %name, "Missing required migration file");
+                return Err(Error::MissingDependency { filename: entry.filename.clone(), requires: name });
+            };
+
+            dependents[required].push(index);
+            in_degrees[index] += 1;
+        }
+    }
+
+    if in_degrees.iter().all(|in_degree| *in_degree == 0) {
+        return Ok(entries);
+    }
+
+    // Kahn's algorithm, taking the file with the lowest version among the ones whose requirements are all ordered.
+    let mut ready: BinaryHeap<Reverse<usize>> = (0..entries.len())
+        .filter(|index| in_degrees[*index] == 0)
+        .map(Reverse)
+        .collect();
+    let mut ordered: Vec<usize> = Vec::with_capacity(entries.len());
+    while let Some(Reverse(index)) = ready.pop() {
+        ordered.push(index);
+        for &dependent in &dependents[index] {
+            in_degrees[dependent] -= 1;
+            if in_degrees[dependent] == 0 {
+                ready.push(Reverse(dependent));
+            }
+        }
+    }
+
+    if ordered.len() < entries.len() {
+        let filenames: Vec<String> = (0..entries.len())
+            .filter(|index| in_degrees[*index] > 0)
+            .map(|index| entries[index].filename.clone())
+            .collect();
+
+        error!(filenames = ?filenames, "The migration files require each other");
+        return Err(Error::DependencyCycle { filenames });
+    }
+
+    let mut entries: Vec<Option<MigrationFile>> = entries.into_iter().map(Some).collect();
+
+    Ok(ordered.into_iter().filter_map(|index| entries[index].take()).collect())
+}
+
+/// Reads the files of a source that fit the repeatable migration pattern, sorted by filename.
+pub(crate) async fn read_repeatable_files(source: &dyn MigrationSource, pattern: &FilePattern) -> Result<Vec<MigrationFile>, Error> {
+    read_files(source, |filename| pattern.is_repeatable(filename)).await
+}
+
+async fn read_files(source: &dyn MigrationSource, is_match: impl Fn(&str) -> bool) -> Result<Vec<MigrationFile>, Error> {
+    let mut filenames: Vec<String> = source
+        .list()
+        .await?
+        .into_iter()
+        .filter(|filename| is_match(filename))
+        .collect();
+
+    // Sort the entries by their number prefix, compared numerically (e.g. timestamps after sequential numbers).
+    filenames.sort_by(|a, b| compare_versions(version(a), version(b)).then_with(|| a.cmp(b)));
+
+    // The files are read and hashed concurrently, but kept in order.
+    stream::iter(filenames)
+        .map(|filename| async move {
+            if let Some(reader) = source.open(&filename).await? {
+                return read_streamed(filename, reader).await;
+            }
+
+            let content = source.read(&filename).await?.unwrap_or_default();
+
+            // Only the up section is applied, but editing the down section still changes the checksum.
+            Ok::<MigrationFile, Error>(MigrationFile {
+                checksum: checksum(&content),
+                filename,
+                content: split_sections(&content).0.to_string(),
+                raw_content: content,
+                streamed_checksums: None,
+            })
+        })
+        .buffered(READ_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+/// Reads a migration file too large to be held in memory, computing its checksums and keeping only its header
+/// (the comments and blank lines before its first statement, e.g. its directives).
+async fn read_streamed(filename: String, mut reader: MigrationReader) -> Result<MigrationFile, Error> {
+    debug!(filename = %filename, "Streaming the migration file");
+
+    let mut digest = StreamingDigest::new();
+    let mut header = String::new();
+    let mut in_header = true;
+    let mut line = String::new();
+
+    while reader.read_line(&mut line).await? > 0 {
+        digest.update(line.as_bytes());
+
+        let trimmed = line.trim();
+        if in_header && (!(trimmed.is_empty() || trimmed.starts_with("--")) || is_down_marker(&line)) {
+            in_header = false;
+        }
+        if in_header {
+            header.push_str(&line);
+        }
+
+        line.clear();
+    }
+
+    let checksums = digest.finish();
+
+    Ok(MigrationFile {
+        filename,
+        checksum: checksums
+            .iter()
+            .find(|(algorithm, _)| *algorithm == ChecksumAlgorithm::Sha256)
+            .map(|(_, checksum)| checksum.clone())
+            .unwrap_or_default(),
+        content: header.clone(),
+        raw_content: header,
+        streamed_checksums: Some(checksums),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::{checksum, Error, FilePattern, MigrationSource};
+
+    use super::{read_migration_files, FsSource, MultiSource, VecSource, IGNORE_FILE, NAMESPACE_DIR};
+
+    #[tokio::test]
+    async fn it_sorts_migration_files_numerically() {
+        let source = VecSource::new([
+            ("10_x.surql", ""),
+            ("2_y.surql", ""),
+            ("20240521143000_z.surql", ""),
+            ("README.md", ""),
+        ]);
+
+        let filenames: Vec<String> = read_migration_files(&source, &FilePattern::default())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.filename)
+            .collect();
+
+        assert_eq!(filenames, ["2_y.surql", "10_x.surql", "20240521143000_z.surql"]);
+    }
+
+    #[tokio::test]
+    async fn it_reads_the_migration_files_of_the_subdirectories() {
+        let dir = env::temp_dir().join("ssm_recursive_source_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("2024")).unwrap();
+        fs::create_dir_all(dir.join("modules").join("auth")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(dir.join(NAMESPACE_DIR)).unwrap();
+        fs::create_dir_all(dir.join("drafts")).unwrap();
+
+        fs::write(dir.join("003_posts.surql"), "DEFINE TABLE posts;").unwrap();
+        fs::write(dir.join("2024").join("001_users.surql"), "DEFINE TABLE users;").unwrap();
+        fs::write(dir.join("modules").join("auth").join("002_sessions.surql"), "DEFINE TABLE sessions;").unwrap();
+        fs::write(dir.join(".git").join("004_hidden.surql"), "").unwrap();
+        fs::write(dir.join(NAMESPACE_DIR).join("005_tenant.surql"), "DEFINE DATABASE tenant;").unwrap();
+        // An ignored draft with the name of a migration file is never read in its place.
+        fs::write(dir.join("drafts").join("002_sessions.surql"), "DEFINE TABLE drafts;").unwrap();
+        fs::write(dir.join(IGNORE_FILE), "drafts/").unwrap();
+
+        let source = FsSource::new(&dir).recursive(true);
+        let entries = read_migration_files(&source, &FilePattern::default()).await.unwrap();
+
+        assert_eq!(
+            entries.iter().map(|entry| entry.filename.as_str()).collect::<Vec<_>>(),
+            ["001_users.surql", "002_sessions.surql", "003_posts.surql"]
+        );
+        assert_eq!(entries[1].content, "DEFINE TABLE sessions;");
+        assert_eq!(FsSource::new(&dir).recursive(true).read("002_sessions.surql").await.unwrap().as_deref(), Some("DEFINE TABLE sessions;"));
+        assert_eq!(FsSource::new(&dir).read("002_sessions.surql").await.unwrap(), None);
+
+        // The files with the same name are reported with their directory.
+        fs::write(dir.join("2024").join("003_posts.surql"), "DEFINE TABLE posts;").unwrap();
+        let result = read_migration_files(&FsSource::new(&dir).recursive(true), &FilePattern::default()).await;
+        assert!(
+            matches!(result, Err(Error::DuplicateVersion { ref first, ref second }) if first == "003_posts.surql" && second == "2024/003_posts.surql"),
+            "{:?}",
+            result
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_merges_the_migration_files_of_several_sources() {
+        let source = MultiSource::new([
+            VecSource::new([("001_users.surql", "DEFINE TABLE users;"), ("003_roles.surql", "DEFINE TABLE roles;")]),
+            VecSource::new([("002_invoices.surql", "DEFINE TABLE invoices;")]),
+        ]);
+
+        let entries = read_migration_files(&source, &FilePattern::default()).await.unwrap();
+
+        let filenames: Vec<&str> = entries.iter().map(|entry| entry.filename.as_str()).collect();
+        assert_eq!(filenames, ["001_users.surql", "002_invoices.surql", "003_roles.surql"]);
+        assert_eq!(entries[1].content, "DEFINE TABLE invoices;");
+
+        let source = MultiSource::new([
+            VecSource::new([("001_users.surql", "")]),
+            VecSource::new([("001_invoices.surql", "")]),
+        ]);
+        let result = read_migration_files(&source, &FilePattern::default()).await;
+        assert!(matches!(result, Err(Error::DuplicateVersion { .. })));
+    }
+
+    #[tokio::test]
+    async fn it_orders_the_migration_files_after_their_requirements() {
+        let filenames = |files: Vec<(&'static str, &'static str)>| async move {
+            read_migration_files(&VecSource::new(files), &FilePattern::default())
+                .await
+                .map(|entries| entries.into_iter().map(|entry| entry.filename).collect::<Vec<_>>())
+        };
+
+        let ordered = filenames(vec![
+            ("20240101000000_billing.surql", "-- ssm:requires 20240301000000_accounts\nDEFINE TABLE invoices;"),
+            ("20240201000000_users.surql", "DEFINE TABLE users;"),
+            ("20240301000000_accounts.surql", "-- ssm:requires 20240201000000_users.surql\nDEFINE TABLE accounts;"),
+        ])
+        .await
+        .unwrap();
+        assert_eq!(ordered, ["20240201000000_users.surql", "20240301000000_accounts.surql", "20240101000000_billing.surql"]);
+
+        let missing = filenames(vec![("001_billing.surql", "-- ssm:requires 000_accounts\n")]).await;
+        assert!(matches!(missing, Err(Error::MissingDependency { ref requires, .. }) if requires == "000_accounts"));
+
+        let cycle = filenames(vec![("001_a.surql", "-- ssm:requires 002_b\n"), ("002_b.surql", "-- ssm:requires 001_a\n")]).await;
+        assert!(matches!(cycle, Err(Error::DependencyCycle { ref filenames }) if filenames.len() == 2));
+    }
+
+    #[tokio::test]
+    async fn it_streams_the_migration_files_above_the_threshold() {
+        let dir = env::temp_dir().join("ssm_streamed_source_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let content = "-- ssm:env prod\n\nCREATE user:1;\nCREATE user:2;\n";
+        fs::write(dir.join("001_backfill.surql"), content).unwrap();
+
+        let entries = read_migration_files(&FsSource::new(&dir).stream_threshold(0), &FilePattern::default()).await.unwrap();
+
+        assert!(entries[0].is_streamed());
+        assert_eq!(entries[0].checksum, checksum(content));
+        assert_eq!(entries[0].content, "-- ssm:env prod\n\n");
+
+        let entries = read_migration_files(&FsSource::new(&dir), &FilePattern::default()).await.unwrap();
+        assert!(!entries[0].is_streamed());
+        assert_eq!(entries[0].content, content);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_fails_when_migration_files_share_a_version() {
+        let source = VecSource::new([("001_x.surql", ""), ("1_y.surql", ""), ("002.surql", "")]);
+
+        let result = read_migration_files(&source, &FilePattern::default()).await;
+
+        assert!(matches!(result, Err(Error::DuplicateVersion { .. })));
+    }
+}
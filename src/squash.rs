@@ -0,0 +1,247 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use surrealdb::{Connection, Surreal};
+use tokio::fs::{create_dir_all, remove_file, rename, write};
+use tracing::info;
+
+use crate::{
+    compare_versions, down_filename, get_migrations, source::read_migration_files, version, ChecksumAlgorithm, Directives, Error,
+    FilePattern, FsSource, MigrationFile,
+};
+
+/// The directory, relative to the migration directory, the squashed migration files are moved to. Being
+/// hidden, it is skipped when reading the migration files recursively.
+pub const DEFAULT_ARCHIVE_DIR: &str = ".archive";
+
+/// The migration files collapsed (or that would be collapsed, in dry run mode) into a baseline file by a squash.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SquashReport {
+    /// The file replacing the squashed migration files, if any.
+    pub baseline: Option<String>,
+    /// The squashed migration files, in order, moved to the archive directory with their `.down` files.
+    pub squashed: Vec<String>,
+    /// The directory the squashed files are moved to.
+    pub archive_dir: PathBuf,
+    /// The file the squash stopped before, as its directives (e.g. `-- ssm:env`, `-- ssm:kind data`) would not apply
+    /// to it once squashed.
+    pub stopped_at: Option<String>,
+    /// Whether the squash was a dry run, in which case nothing has been changed.
+    pub dry_run: bool,
+}
+
+impl SquashReport {
+    /// Returns whether there was nothing to squash.
+    pub fn is_empty(&self) -> bool {
+        self.squashed.is_empty()
+    }
+}
+
+/// What a squash collapses, and how the baseline file is recorded.
+pub(crate) struct SquashOptions<'a> {
+    /// The migration directory, whose files are rewritten.
+    pub dir: &'a Path,
+    pub table: &'a str,
+    pub pattern: &'a FilePattern,
+    /// The version of the last file to squash, included.
+    pub to: &'a str,
+    pub applied_by: &'a str,
+    pub algorithm: ChecksumAlgorithm,
+    pub dry_run: bool,
+}
+
+/// Collapses the applied migration files of a directory up to a version included into a single baseline file,
+/// named after the last squashed file (e.g. `004_squashed.surql`), recorded as applied in their place. The squash
+/// stops before the first file with directives of its own, other than `-- ssm:no-transaction`.
+pub(crate) async fn run_squash<C: Connection>(db: &Surreal<C>, options: &SquashOptions<'_>) -> Result<SquashReport, Error> {
+    let SquashOptions { dir, table, pattern, to, applied_by, algorithm, dry_run } = *options;
+
+    let migrations = get_migrations(db, table).await?;
+    let mut entries: Vec<MigrationFile> = read_migration_files(&FsSource::new(dir).stream_threshold(u64::MAX), pattern)
+        .await?
+        .into_iter()
+        .filter(|entry| compare_versions(version(&entry.filename), to).is_le())
+        .collect();
+
+    let archive_dir = dir.join(DEFAULT_ARCHIVE_DIR);
+
+    // Only the header of the baseline file is read for directives, so the ones of a file are kept with it.
+    let stopped_at = entries
+        .iter()
+        .position(has_own_directives)
+        .map(|position| entries.split_off(position)[0].filename.clone());
+    if let Some(ref filename) = stopped_at {
+        info!(%filename, "The squash stops before a migration file with directives");
+    }
+
+    // A single file is already a baseline.
+    let Some(last) = entries.last().filter(|_| entries.len() > 1) else {
+        return Ok(SquashReport { archive_dir, stopped_at, dry_run, ..Default::default() });
+    };
+
+    let not_applied: Vec<String> = entries
+        .iter()
+        .filter(|entry| !migrations.iter().any(|migration| migration == &entry.filename))
+        .map(|entry| entry.filename.clone())
+        .collect();
+    if !not_applied.is_empty() {
+        return Err(Error::NotApplied { filenames: not_applied });
+    }
+
+    for entry in &entries {
+        let migration = migrations.iter().find(|migration| *migration == &entry.filename);
+        if let Some(migration) = migration.filter(|migration| !entry.matches(migration)) {
+            return Err(
+                Error::ChecksumMismatch {
+                    filename: entry.filename.clone(),
+                    expected: migration.checksum.clone().unwrap_or_default(),
+                    actual: entry.checksum_for(migration),
+                }
+            );
+        }
+    }
+
+    let filename = format!("{}_squashed.{}", version(&last.filename), pattern.extension());
+    let squashed: Vec<String> = entries.iter().map(|entry| entry.filename.clone()).collect();
+    let report = SquashReport { baseline: Some(filename.clone()), squashed, archive_dir, stopped_at, dry_run };
+
+    if dry_run {
+        return Ok(report);
+    }
+
+    let content = squashed_content(&entries);
+    let path = dir.join(&filename);
+
+    // The originals are archived first, in case the baseline file takes the name of one of them.
+    create_dir_all(&report.archive_dir).await?;
+    for entry in &entries {
+        for name in [entry.filename.clone(), down_filename(&entry.filename)] {
+            if dir.join(&name).is_file() {
+                rename(dir.join(&name), report.archive_dir.join(&name)).await?;
+            }
+        }
+    }
+    write(&path, &content).await?;
+
+    let sql = format!(
+        r#"
+            BEGIN TRANSACTION;
+            DELETE {table} WHERE filename IN $filenames;
+            CREATE {table} SET filename=$filename, checksum=$checksum, checksum_algorithm=$checksum_algorithm, applied_by=$applied_by, tool_version=$tool_version, baseline=true;
+            COMMIT TRANSACTION;
+        "#,
+        table = table
+    );
+
+    let result = db
+        .query(sql)
+        .bind(("filenames", report.squashed.clone()))
+        .bind(("filename", filename.clone()))
+        .bind(("checksum", algorithm.digest(&content)))
+        .bind(("checksum_algorithm", algorithm.name()))
+        .bind(("applied_by", applied_by.to_string()))
+        .bind(("tool_version", env!("CARGO_PKG_VERSION")))
+        .await
+        .and_then(|response| response.check());
+
+    if let Err(err) = result {
+        // Put the originals back, as they are still the ones recorded.
+        remove_file(&path).await?;
+        for entry in &entries {
+            for name in [entry.filename.clone(), down_filename(&entry.filename)] {
+                if report.archive_dir.join(&name).is_file() {
+                    rename(report.archive_dir.join(&name), dir.join(&name)).await?;
+                }
+            }
+        }
+
+        return Err(err.into());
+    }
+
+    info!(filename = %filename, squashed = report.squashed.len(), "Migration files squashed");
+
+    Ok(report)
+}
+
+/// Returns whether a migration file has directives that would not apply to it once squashed with others. A file
+/// opting out of transactions makes the whole baseline file do so instead.
+fn has_own_directives(entry: &MigrationFile) -> bool {
+    let directives = Directives::parse(&entry.content);
+
+    directives != Directives { no_transaction: directives.no_transaction, ..Default::default() }
+}
+
+/// Concatenates the migration files after a header listing them. The baseline file is not wrapped in a
+/// transaction when one of them is not.
+fn squashed_content(entries: &[MigrationFile]) -> String {
+    let filenames: Vec<&str> = entries.iter().map(|entry| entry.filename.as_str()).collect();
+    let mut content = format!("-- Squashed migrations: {}\n-- Created at: {}\n", filenames.join(", "), Utc::now().to_rfc3339());
+
+    if entries.iter().any(|entry| Directives::parse(&entry.content).no_transaction) {
+        content.push_str("-- ssm:no-transaction\n");
+    }
+
+    for entry in entries {
+        content.push_str(&format!("\n-- From: {}\n{}\n", entry.filename, entry.content.trim_end()));
+    }
+
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Directives, MigrationFile, Migrator};
+
+    use super::squashed_content;
+
+    #[test]
+    fn it_concatenates_the_squashed_files() {
+        let file = |filename: &str, content: &str| MigrationFile {
+            filename: filename.to_string(),
+            checksum: String::new(),
+            content: content.to_string(),
+            raw_content: content.to_string(),
+            streamed_checksums: None,
+        };
+
+        let content = squashed_content(&[
+            file("001_users.surql", "DEFINE TABLE users;\n"),
+            file("002_index.surql", "-- ssm:no-transaction\nDEFINE INDEX email ON users FIELDS email CONCURRENTLY;"),
+        ]);
+
+        assert!(content.starts_with("-- Squashed migrations: 001_users.surql, 002_index.surql\n"));
+        assert!(Directives::parse(&content).no_transaction);
+        assert!(content.ends_with(
+            "\n-- From: 001_users.surql\nDEFINE TABLE users;\n\
+             \n-- From: 002_index.surql\n-- ssm:no-transaction\nDEFINE INDEX email ON users FIELDS email CONCURRENTLY;\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_stops_before_the_files_with_directives() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        let dir = env::temp_dir().join("ssm_squash_directives_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("001_users.surql"), "DEFINE TABLE users;").unwrap();
+        fs::write(dir.join("002_posts.surql"), "DEFINE TABLE posts;").unwrap();
+        fs::write(dir.join("003_fixtures.surql"), "-- ssm:env dev\nCREATE users:test;").unwrap();
+        fs::write(dir.join("004_tags.surql"), "DEFINE TABLE tags;").unwrap();
+
+        Migrator::new(&db).dir(&dir).environment("dev").run().await.unwrap();
+        let report = Migrator::new(&db).squash(&dir, "004").await.unwrap();
+
+        assert_eq!(report.squashed, ["001_users.surql", "002_posts.surql"]);
+        assert_eq!(report.baseline.as_deref(), Some("002_squashed.surql"));
+        assert_eq!(report.stopped_at.as_deref(), Some("003_fixtures.surql"));
+        assert!(dir.join("003_fixtures.surql").is_file() && dir.join("004_tags.surql").is_file());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+/// A statement of a migration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    /// The SQL of the statement, without its terminating `;`.
+    pub sql: String,
+    /// The line the statement starts on, starting from 1.
+    pub line: usize,
+}
+
+/// Splits the content of a migration file into its statements, ignoring the `;` inside strings, escaped
+/// identifiers and record IDs (e.g. `` `a;b` `` or `user:⟨a;b⟩`), comments and blocks (e.g. the body of a
+/// `DEFINE FUNCTION`).
+///
+/// ```
+/// let statements = surrealdb_simple_migration::parse_statements("DEFINE TABLE users;\nCREATE user:⟨a;b⟩;");
+///
+/// assert_eq!(statements[1].sql, "CREATE user:⟨a;b⟩");
+/// assert_eq!(statements[1].line, 2);
+/// ```
+pub fn parse_statements(content: &str) -> Vec<Statement> {
+    scan(content).0
+}
+
+/// Returns whether the last statement of a migration file is terminated by a `;`.
+pub(crate) fn is_terminated(content: &str) -> bool {
+    scan(content).1
+}
+
+/// Splits the content of a migration file into its statements, and tells whether the last one is terminated.
+fn scan(content: &str) -> (Vec<Statement>, bool) {
+    let mut splitter = StatementSplitter::new();
+    let mut statements: Vec<Statement> = vec![];
+
+    for line in content.split_inclusive('\n') {
+        statements.extend(splitter.push(line));
+    }
+
+    let (last, terminated) = splitter.finish();
+    statements.extend(last);
+
+    (statements, terminated)
+}
+
+/// Splits SQL into statements incrementally, a line at a time, so a file too large to be read at once can be
+/// parsed as it is read.
+#[derive(Debug)]
+pub(crate) struct StatementSplitter {
+    sql: String,
+    start_line: Option<usize>,
+    line: usize,
+    depth: i32,
+    /// The closing quote of the string (or escaped identifier) being read, if any.
+    closing: Option<char>,
+    in_block_comment: bool,
+}
+
+impl StatementSplitter {
+    pub(crate) fn new() -> Self {
+        StatementSplitter {
+            sql: String::new(),
+            start_line: None,
+            line: 1,
+            depth: 0,
+            closing: None,
+            in_block_comment: false,
+        }
+    }
+
+    /// Reads the next line (with its line break), returning the statements it terminates.
+    pub(crate) fn push(&mut self, text: &str) -> Vec<Statement> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut statements: Vec<Statement> = vec![];
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).copied();
+
+            if self.in_block_comment {
+                if c == '*' && next == Some('/') {
+                    self.in_block_comment = false;
+                    i += 2;
+                    continue;
+                }
+                if c == '\n' {
+                    self.line += 1;
+                }
+                i += 1;
+                continue;
+            }
+
+            // Copy the string (or escaped identifier) up to its closing quote, skipping the escaped characters.
+            if let Some(closing) = self.closing {
+                if c == '\\' && next.is_some() {
+                    self.sql.push(c);
+                    i += 1;
+                }
+                if chars[i] == '\n' {
+                    self.line += 1;
+                }
+                self.sql.push(chars[i]);
+                if chars[i] == closing && c != '\\' {
+                    self.closing = None;
+                }
+                i += 1;
+                continue;
+            }
+
+            // Comments are not part of the statements.
+            if c == '#' || (c == '-' && next == Some('-')) || (c == '/' && next == Some('/')) {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+
+            if c == '/' && next == Some('*') {
+                self.in_block_comment = true;
+                i += 2;
+                continue;
+            }
+
+            if c == '\n' {
+                self.line += 1;
+            }
+
+            if !c.is_whitespace() && self.start_line.is_none() {
+                self.start_line = Some(self.line);
+            }
+
+            match c {
+                '\'' | '"' | '`' | '⟨' => {
+                    self.closing = Some(if c == '⟨' { '⟩' } else { c });
+                    self.sql.push(c);
+                },
+                '{' | '(' | '[' => {
+                    self.depth += 1;
+                    self.sql.push(c);
+                },
+                '}' | ')' | ']' => {
+                    self.depth -= 1;
+                    self.sql.push(c);
+                },
+                ';' if self.depth <= 0 => {
+                    push_statement(&mut statements, &mut self.sql, self.start_line.take());
+                    self.depth = 0;
+                },
+                _ => self.sql.push(c),
+            }
+
+            i += 1;
+        }
+
+        statements
+    }
+
+    /// Returns the last statement, when it is not terminated by a `;`, and whether it is.
+    pub(crate) fn finish(mut self) -> (Option<Statement>, bool) {
+        let terminated = self.sql.trim().is_empty();
+
+        let mut statements: Vec<Statement> = vec![];
+        push_statement(&mut statements, &mut self.sql, self.start_line);
+
+        (statements.pop(), terminated)
+    }
+}
+
+/// Adds the pending SQL to the statements, unless it is empty.
+fn push_statement(statements: &mut Vec<Statement>, sql: &mut String, line: Option<usize>) {
+    let trimmed = sql.trim();
+
+    if let (false, Some(line)) = (trimmed.is_empty(), line) {
+        statements.push(Statement { sql: trimmed.to_string(), line });
+    }
+
+    sql.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_statements, Statement};
+
+    #[test]
+    fn it_splits_statements() {
+        let content = "-- Migration; with a comment\nDEFINE TABLE users;\n\nDEFINE FIELD name ON users TYPE string DEFAULT 'a;b';\n/* Block; comment */\nDEFINE FUNCTION fn::greet($name: string) {\n    RETURN 'Hello ' + $name;\n};\nCREATE users SET name = \"c\\\"d;\"";
+
+        assert_eq!(
+            parse_statements(content),
+            vec![
+                Statement { sql: "DEFINE TABLE users".to_string(), line: 2 },
+                Statement { sql: "DEFINE FIELD name ON users TYPE string DEFAULT 'a;b'".to_string(), line: 4 },
+                Statement { sql: "DEFINE FUNCTION fn::greet($name: string) {\n    RETURN 'Hello ' + $name;\n}".to_string(), line: 6 },
+                Statement { sql: "CREATE users SET name = \"c\\\"d;\"".to_string(), line: 9 },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_ignores_the_semicolons_of_record_ids_and_identifiers() {
+        let content = "CREATE user:⟨a;b⟩ SET `full;name` = 'c'; -- user:⟨d;e⟩\nDELETE user:`f;g`;";
+
+        assert_eq!(
+            parse_statements(content),
+            vec![
+                Statement { sql: "CREATE user:⟨a;b⟩ SET `full;name` = 'c'".to_string(), line: 1 },
+                Statement { sql: "DELETE user:`f;g`".to_string(), line: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_splits_statements_spanning_lines() {
+        let content = "/* a;\n b; */\nCREATE a SET b = 'x;\ny';\nCREATE c;";
+
+        assert_eq!(
+            parse_statements(content),
+            vec![
+                Statement { sql: "CREATE a SET b = 'x;\ny'".to_string(), line: 3 },
+                Statement { sql: "CREATE c".to_string(), line: 5 },
+            ]
+        );
+    }
+}
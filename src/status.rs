@@ -0,0 +1,211 @@
+use std::path::PathBuf;
+
+use chrono::prelude::*;
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    get_migrations, get_repeatable_migrations, is_out_of_order, last_applied_migration,
+    source::{read_migration_files, read_repeatable_files}, Directives, Error, FilePattern, Migration, MigrationSource,
+};
+
+/// The state of a migration, compared between the migration directory and the migrations table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    /// The file has been migrated and its content is unchanged.
+    Applied,
+    /// The file has not been migrated yet (or, for a repeatable file, changed since its last migration).
+    Pending,
+    /// The file has not been migrated yet, but appears before the last applied migration.
+    OutOfOrder,
+    /// The file has been migrated but its content changed since then.
+    Changed,
+    /// The file has been migrated but is no longer in the migration directory.
+    Missing,
+    /// The file is for other environments (`-- ssm:env`), recorded without being executed.
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationStatus {
+    pub filename: String,
+    pub state: MigrationState,
+    pub applied_at: Option<DateTime<Utc>>,
+    /// The checksum recorded when the file was applied, or else the checksum of the file.
+    pub checksum: Option<String>,
+    /// How long the execution of the file took, in milliseconds, when recorded.
+    pub execution_time_ms: Option<u64>,
+    /// Who applied the file, when recorded.
+    pub applied_by: Option<String>,
+    /// The version of `surrealdb-simple-migration` that applied the file, when recorded.
+    pub tool_version: Option<String>,
+    /// The tags of the file (`-- ssm:tags`), none when it is missing.
+    pub tags: Vec<String>,
+}
+
+/// A migration applied to the database, as recorded in the migrations table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedMigration {
+    pub filename: String,
+    /// The checksum recorded when the file was applied, none for the records predating the checksums.
+    pub checksum: Option<String>,
+    pub applied_at: DateTime<Utc>,
+    /// How long the execution of the file took, in milliseconds, when recorded.
+    pub execution_time_ms: Option<u64>,
+    /// Who applied the file, when recorded.
+    pub applied_by: Option<String>,
+    /// The version of `surrealdb-simple-migration` that applied the file, when recorded.
+    pub tool_version: Option<String>,
+    /// Whether the file is repeatable (`R__<name>.surql`), applied again whenever it changes.
+    pub repeatable: bool,
+    /// Whether the file is for other environments, recorded without being executed.
+    pub skipped: bool,
+    /// The path of the file, when it is still read from the filesystem.
+    pub path: Option<PathBuf>,
+}
+
+impl MigrationStatus {
+    fn applied(migration: &Migration, state: MigrationState, tags: Vec<String>) -> Self {
+        MigrationStatus {
+            filename: migration.filename.clone(),
+            state,
+            applied_at: Some(migration.created_at),
+            checksum: migration.checksum.clone(),
+            execution_time_ms: migration.execution_time_ms,
+            applied_by: migration.applied_by.clone(),
+            tool_version: migration.tool_version.clone(),
+            tags,
+        }
+    }
+}
+
+pub(crate) async fn read_status<C: Connection>(
+    db: &Surreal<C>,
+    source: &dyn MigrationSource,
+    table: &str,
+    pattern: &FilePattern,
+) -> Result<Vec<MigrationStatus>, Error> {
+    let migrations = get_migrations(db, table).await?;
+    let entries = read_migration_files(source, pattern).await?;
+
+    let last_migration = last_applied_migration(&migrations);
+
+    let mut statuses: Vec<MigrationStatus> = vec![];
+
+    for entry in &entries {
+        let migration = migrations
+            .iter()
+            .find(|migration| *migration == &entry.filename);
+
+        let status = match migration {
+            Some(migration) => MigrationStatus::applied(
+                migration,
+                match migration.skipped {
+                    _ if !entry.matches(migration) => MigrationState::Changed,
+                    Some(true) => MigrationState::Skipped,
+                    _ => MigrationState::Applied,
+                },
+                Directives::parse(&entry.content).tags,
+            ),
+            None => MigrationStatus {
+                filename: entry.filename.clone(),
+                state: if is_out_of_order(entry, last_migration) {
+                    MigrationState::OutOfOrder
+                } else {
+                    MigrationState::Pending
+                },
+                applied_at: None,
+                checksum: Some(entry.checksum.clone()),
+                execution_time_ms: None,
+                applied_by: None,
+                tool_version: None,
+                tags: Directives::parse(&entry.content).tags,
+            },
+        };
+
+        statuses.push(status);
+    }
+
+    // Migrated files that are no longer in the migration directory.
+    for migration in &migrations {
+        if !entries.iter().any(|entry| migration == &entry.filename) {
+            statuses.push(MigrationStatus::applied(migration, MigrationState::Missing, vec![]));
+        }
+    }
+
+    // Repeatable files are pending until migrated with their current content.
+    let repeatable_migrations = get_repeatable_migrations(db, table).await?;
+    for entry in read_repeatable_files(source, pattern).await? {
+        let migration = repeatable_migrations
+            .iter()
+            .find(|migration| *migration == &entry.filename);
+
+        let status = match migration {
+            Some(migration) if migration.checksum.as_ref() == Some(&entry.checksum_for(migration)) => {
+                let state = if migration.skipped == Some(true) { MigrationState::Skipped } else { MigrationState::Applied };
+                MigrationStatus::applied(migration, state, Directives::parse(&entry.content).tags)
+            },
+            _ => MigrationStatus {
+                filename: entry.filename.clone(),
+                state: MigrationState::Pending,
+                applied_at: None,
+                checksum: Some(entry.checksum.clone()),
+                execution_time_ms: None,
+                applied_by: None,
+                tool_version: None,
+                tags: Directives::parse(&entry.content).tags,
+            },
+        };
+
+        statuses.push(status);
+    }
+
+    statuses.sort_by(|a, b| a.filename.cmp(&b.filename));
+
+    Ok(statuses)
+}
+
+/// Returns the status of the migrations in the order they were applied, followed by the ones not applied yet.
+pub(crate) async fn read_history<C: Connection>(
+    db: &Surreal<C>,
+    source: &dyn MigrationSource,
+    table: &str,
+    pattern: &FilePattern,
+) -> Result<Vec<MigrationStatus>, Error> {
+    let mut statuses = read_status(db, source, table, pattern).await?;
+
+    // The sort is stable: the migrations not applied yet stay sorted by filename.
+    statuses.sort_by_key(|status| (status.applied_at.is_none(), status.applied_at));
+
+    Ok(statuses)
+}
+
+/// Returns the applied migrations, the repeatable ones included, in the order they were applied.
+pub(crate) async fn read_applied<C: Connection>(
+    db: &Surreal<C>,
+    source: &dyn MigrationSource,
+    table: &str,
+) -> Result<Vec<AppliedMigration>, Error> {
+    let migrations = get_migrations(db, table)
+        .await?
+        .into_iter()
+        .map(|migration| (migration, false))
+        .chain(get_repeatable_migrations(db, table).await?.into_iter().map(|migration| (migration, true)));
+
+    let mut applied: Vec<AppliedMigration> = vec![];
+    for (migration, repeatable) in migrations {
+        applied.push(AppliedMigration {
+            path: source.path(&migration.filename).await?,
+            filename: migration.filename,
+            checksum: migration.checksum,
+            applied_at: migration.created_at,
+            execution_time_ms: migration.execution_time_ms,
+            applied_by: migration.applied_by,
+            tool_version: migration.tool_version,
+            repeatable,
+            skipped: migration.skipped.unwrap_or(false),
+        });
+    }
+    applied.sort_by_key(|migration| migration.applied_at);
+
+    Ok(applied)
+}
@@ -0,0 +1,127 @@
+//! Helpers for the test suites of the applications, applying their migrations to an in-memory database so their
+//! schema can be verified without a running SurrealDB server. Requires the `testing` feature.
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn it_rejects_duplicated_emails() {
+//!     let db = TestMigrator::in_memory()
+//!         .dir("./migrations")
+//!         .run()
+//!         .await
+//!         .unwrap();
+//!
+//!     db.query("CREATE user SET email = 'a@b.c';").await.unwrap().check().unwrap();
+//!     assert!(db.query("CREATE user SET email = 'a@b.c';").await.unwrap().check().is_err());
+//! }
+//! ```
+
+use std::{collections::HashMap, path::PathBuf};
+
+use surrealdb::{engine::local::{Db, Mem}, Surreal};
+
+use crate::{Error, FilePattern, FsSource, MigrationSource, Migrator};
+
+/// Applies migration files to a fresh in-memory database, handing back its connection.
+pub struct TestMigrator {
+    source: Box<dyn MigrationSource>,
+    pattern: FilePattern,
+    namespace: String,
+    database: String,
+    vars: HashMap<String, String>,
+    environment: Option<String>,
+}
+
+impl TestMigrator {
+    /// A migrator of the `./migrations` directory to an in-memory database.
+    pub fn in_memory() -> Self {
+        TestMigrator {
+            source: Box::new(FsSource::new("./migrations")),
+            pattern: FilePattern::default(),
+            namespace: "test".to_string(),
+            database: "test".to_string(),
+            vars: HashMap::new(),
+            environment: None,
+        }
+    }
+
+    /// The directory of the migration files, relative to the package being tested. (default: "./migrations")
+    pub fn dir(self, dir: impl Into<PathBuf>) -> Self {
+        self.source(FsSource::new(dir))
+    }
+
+    /// Where the migration files are read from, instead of a directory (e.g. an `EmbeddedSource` built with
+    /// `embed_migrations!`, or a `VecSource`).
+    pub fn source(mut self, source: impl MigrationSource + 'static) -> Self {
+        self.source = Box::new(source);
+        self
+    }
+
+    /// Which files of the source are migration files. (default: `.surql` files)
+    pub fn file_pattern(mut self, pattern: FilePattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// The namespace of the in-memory database. (default: "test")
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// The name of the in-memory database. (default: "test")
+    pub fn database(mut self, database: impl Into<String>) -> Self {
+        self.database = database.into();
+        self
+    }
+
+    /// Sets the value of a `${VAR}` placeholder of the migration files.
+    pub fn var(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.vars.insert(name.into(), value.into());
+        self
+    }
+
+    /// The environment the migrations are applied to, for the files restricted with `-- ssm:env`. (default: none)
+    pub fn environment(mut self, environment: impl Into<String>) -> Self {
+        self.environment = Some(environment.into());
+        self
+    }
+
+    /// Starts an in-memory database, applies every migration file to it and returns its connection.
+    pub async fn run(self) -> Result<Surreal<Db>, Error> {
+        let db = Surreal::new::<Mem>(()).await?;
+        db.use_ns(&self.namespace).use_db(&self.database).await?;
+
+        let mut migrator = Migrator::new(&db)
+            .source(self.source.as_ref())
+            .file_pattern(self.pattern)
+            .vars(self.vars);
+        if let Some(environment) = self.environment {
+            migrator = migrator.environment(environment);
+        }
+        let _ = migrator.run().await?;
+
+        Ok(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::VecSource;
+
+    use super::TestMigrator;
+
+    #[tokio::test]
+    async fn it_hands_back_the_migrated_database() {
+        let db = TestMigrator::in_memory()
+            .source(VecSource::new([
+                ("001_users.surql", "DEFINE TABLE user SCHEMAFULL;\nDEFINE FIELD email ON user TYPE string;"),
+                ("002_index.surql", "DEFINE INDEX email ON user FIELDS email UNIQUE;"),
+            ]))
+            .run()
+            .await
+            .unwrap();
+
+        db.query("CREATE user SET email = 'a@b.c';").await.unwrap().check().unwrap();
+        assert!(db.query("CREATE user SET email = 'a@b.c';").await.unwrap().check().is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use std::{collections::HashMap, env};
+
+use regex::Regex;
+
+use crate::Error;
+
+/// Replaces the `${VAR}` placeholders of a migration file with the given variables, or else the environment
+/// variables. `$${VAR}` is kept as a literal `${VAR}`.
+pub(crate) fn substitute(content: &str, vars: &HashMap<String, String>) -> Result<String, Error> {
+    let regex = Regex::new(r"\$(\$)?\{([a-zA-Z_][a-zA-Z_0-9]*)\}").expect("Failed to build the regexp");
+
+    let mut result = String::with_capacity(content.len());
+    let mut last = 0;
+
+    for captures in regex.captures_iter(content) {
+        let placeholder = captures.get(0).expect("The regexp always captures the whole match");
+        let name = &captures[2];
+
+        result.push_str(&content[last..placeholder.start()]);
+
+        if captures.get(1).is_some() {
+            result.push_str(&format!("${{{}}}", name));
+        } else {
+            match vars.get(name).cloned().or_else(|| env::var(name).ok()) {
+                Some(value) => result.push_str(&value),
+                None => {
+                    return Err(
+                        Error::UndefinedVariable {
+                            name: name.to_string(),
+                        }
+                    );
+                },
+            }
+        }
+
+        last = placeholder.end();
+    }
+
+    result.push_str(&content[last..]);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::Error;
+
+    use super::substitute;
+
+    #[test]
+    fn it_substitutes_variables() {
+        let vars = HashMap::from([("BUCKET".to_string(), "assets".to_string())]);
+
+        assert_eq!(
+            substitute("DEFINE BUCKET ${BUCKET}; -- $${BUCKET}", &vars).unwrap(),
+            "DEFINE BUCKET assets; -- ${BUCKET}"
+        );
+        assert!(matches!(
+            substitute("DEFINE PARAM $a VALUE '${SSM_UNDEFINED_TEST_VARIABLE}';", &vars),
+            Err(Error::UndefinedVariable { .. })
+        ));
+    }
+}
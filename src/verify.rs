@@ -0,0 +1,134 @@
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    get_migrations, is_out_of_order, last_applied_migration, source::read_migration_files, Error, FilePattern,
+    MigrationSource, Policy, PolicyAction,
+};
+
+/// An applied migration file changed since its migration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedMigration {
+    pub filename: String,
+    /// The checksum recorded when the file was applied.
+    pub expected: String,
+    /// The checksum of the file, computed with the algorithm of the record.
+    pub actual: String,
+}
+
+/// The deviations of the migration files from the migrations table, found by `Migrator::verify` without applying
+/// anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// The applied files changed since their migration.
+    pub changed: Vec<ChangedMigration>,
+    /// The applied files no longer in the migration directory.
+    pub missing: Vec<String>,
+    /// The pending files with a lower version than the last applied one.
+    pub out_of_order: Vec<String>,
+    /// How many migration files were verified.
+    pub verified: usize,
+    /// The policy of the migrator, telling which deviations are errors.
+    pub policy: Policy,
+}
+
+impl VerifyReport {
+    /// Returns whether none of the deviations is an error under the policy (every deviation is one by default),
+    /// e.g. to answer a health check.
+    pub fn is_ok(&self) -> bool {
+        (self.changed.is_empty() || self.policy.modified != PolicyAction::Error)
+            && (self.missing.is_empty() || self.policy.missing != PolicyAction::Error)
+            && (self.out_of_order.is_empty() || self.policy.out_of_order != PolicyAction::Error)
+    }
+
+    /// Returns whether the migration files match the migrations table, without any deviation.
+    pub fn is_clean(&self) -> bool {
+        self.changed.is_empty() && self.missing.is_empty() && self.out_of_order.is_empty()
+    }
+}
+
+pub(crate) async fn run_verify<C: Connection>(
+    db: &Surreal<C>,
+    source: &dyn MigrationSource,
+    table: &str,
+    pattern: &FilePattern,
+    policy: Policy,
+) -> Result<VerifyReport, Error> {
+    let migrations = get_migrations(db, table).await?;
+    let entries = read_migration_files(source, pattern).await?;
+
+    let last_migration = last_applied_migration(&migrations);
+
+    let mut report = VerifyReport {
+        verified: entries.len(),
+        policy,
+        ..Default::default()
+    };
+
+    for entry in &entries {
+        match migrations.iter().find(|migration| *migration == &entry.filename) {
+            Some(migration) if !entry.matches(migration) => report.changed.push(ChangedMigration {
+                filename: entry.filename.clone(),
+                expected: migration.checksum.clone().unwrap_or_default(),
+                actual: entry.checksum_for(migration),
+            }),
+            Some(_) => (),
+            None if is_out_of_order(entry, last_migration) => report.out_of_order.push(entry.filename.clone()),
+            None => (),
+        }
+    }
+
+    for migration in &migrations {
+        if !entries.iter().any(|entry| migration == &entry.filename) && !report.missing.contains(&migration.filename) {
+            report.missing.push(migration.filename.clone());
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Migrator, Policy, PolicyAction, VecSource};
+
+    #[tokio::test]
+    async fn it_reports_the_deviations_without_applying_anything() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        Migrator::new(&db)
+            .source(VecSource::new([
+                ("001_users.surql", "DEFINE TABLE users;"),
+                ("002_posts.surql", "DEFINE TABLE posts;"),
+                ("004_tags.surql", "DEFINE TABLE tags;"),
+            ]))
+            .run()
+            .await
+            .unwrap();
+
+        let source = VecSource::new([
+            ("001_users.surql", "DEFINE TABLE users SCHEMAFULL;"),
+            ("003_likes.surql", "DEFINE TABLE likes;"),
+            ("004_tags.surql", "DEFINE TABLE tags;"),
+            ("005_comments.surql", "DEFINE TABLE comments;"),
+        ]);
+        let report = Migrator::new(&db).source(&source).verify().await.unwrap();
+
+        assert_eq!(report.changed.len(), 1);
+        assert_eq!(report.changed[0].filename, "001_users.surql");
+        assert_eq!(report.missing, ["002_posts.surql"]);
+        assert_eq!(report.out_of_order, ["003_likes.surql"]);
+        assert_eq!(report.verified, 4);
+        assert!(!report.is_ok());
+
+        let policy = Policy { modified: PolicyAction::Warn, missing: PolicyAction::Ignore, out_of_order: PolicyAction::Warn };
+        let report = Migrator::new(&db).source(&source).policy(policy).verify().await.unwrap();
+        assert!(report.is_ok());
+        assert!(!report.is_clean());
+
+        // Nothing was applied.
+        let statuses = Migrator::new(&db).source(&source).status().await.unwrap();
+        assert_eq!(statuses.iter().filter(|status| status.applied_at.is_some()).count(), 3);
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use tracing::warn;
+
+use crate::{Error, Migration, MigrationFile, MigrationSource};
+
+/// What a `MigrationWarning` is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum WarningKind {
+    /// An applied migration file changed since, allowed by `Policy::modified`.
+    Modified,
+    /// An applied migration file is missing, allowed by `Policy::missing`.
+    Missing,
+    /// A pending migration file has a lower version than the last applied one, allowed by `Policy::out_of_order`.
+    OutOfOrder,
+    /// The last statement of a pending migration file does not end with a semicolon.
+    MissingTrailingSemicolon,
+    /// An applied migration file was written after it was applied, though its checksum still matches (e.g. a
+    /// change reverted).
+    TouchedAfterApplied,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match *self {
+            WarningKind::Modified => "modified",
+            WarningKind::Missing => "missing",
+            WarningKind::OutOfOrder => "out_of_order",
+            WarningKind::MissingTrailingSemicolon => "missing_trailing_semicolon",
+            WarningKind::TouchedAfterApplied => "touched_after_applied",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// A non-fatal anomaly found by a migration run, reported in `MigrationReport::warnings` without failing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationWarning {
+    pub filename: String,
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl MigrationWarning {
+    pub(crate) fn new(filename: impl Into<String>, kind: WarningKind, message: impl Into<String>) -> Self {
+        let warning = MigrationWarning { filename: filename.into(), kind, message: message.into() };
+        warn!(filename = %warning.filename, kind = %warning.kind, "{}", warning.message);
+
+        warning
+    }
+}
+
+/// Warns about the pending migration files whose last statement does not end with a semicolon, which the next
+/// statement appended to the file would be merged with.
+pub(crate) fn check_trailing_semicolons(entries: &[MigrationFile], warnings: &mut Vec<MigrationWarning>) {
+    for entry in entries.iter().filter(|entry| !entry.is_streamed()) {
+        let last_line = entry
+            .content
+            .lines()
+            .map(|line| line.split(" --").next().unwrap_or_default().trim())
+            .rev()
+            .find(|line| !line.is_empty() && !line.starts_with("--") && !line.starts_with("//") && !line.starts_with('#'));
+
+        if last_line.is_some_and(|line| !line.ends_with(';')) {
+            warnings.push(MigrationWarning::new(
+                &entry.filename,
+                WarningKind::MissingTrailingSemicolon,
+                "The last statement does not end with a semicolon.",
+            ));
+        }
+    }
+}
+
+/// Warns about the applied migration files written after they were applied, though their checksum matches. When
+/// all of them were (e.g. a fresh checkout of the repository), none is reported.
+pub(crate) async fn check_modification_times(
+    source: &dyn MigrationSource,
+    migrations: &[Migration],
+    entries: &[MigrationFile],
+    warnings: &mut Vec<MigrationWarning>,
+) -> Result<(), Error> {
+    let mut checked = 0;
+    let mut touched: Vec<(&Migration, DateTime<Utc>)> = vec![];
+
+    for migration in migrations {
+        if !entries.iter().any(|entry| migration == &entry.filename && entry.matches(migration)) {
+            continue;
+        }
+        let Some(path) = source.path(&migration.filename).await? else {
+            continue;
+        };
+
+        let modified: DateTime<Utc> = tokio::fs::metadata(&path).await?.modified()?.into();
+        checked += 1;
+        if modified > migration.created_at {
+            touched.push((migration, modified));
+        }
+    }
+
+    if touched.len() == checked {
+        return Ok(());
+    }
+
+    for (migration, modified) in touched {
+        warnings.push(MigrationWarning::new(
+            &migration.filename,
+            WarningKind::TouchedAfterApplied,
+            format!(
+                "The file was written at {} after it was applied at {}, but its checksum matches.",
+                modified.to_rfc3339(),
+                migration.created_at.to_rfc3339()
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::{engine::local::Mem, Surreal};
+
+    use crate::{Migrator, Policy, PolicyAction, VecSource};
+
+    use super::WarningKind;
+
+    #[tokio::test]
+    async fn it_reports_the_anomalies_without_failing() {
+        let db = Surreal::new::<Mem>(()).await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        Migrator::new(&db)
+            .source(VecSource::new([("001_users.surql", "DEFINE TABLE users;"), ("002_tmp.surql", "DEFINE TABLE tmp;")]))
+            .run()
+            .await
+            .unwrap();
+
+        let source = VecSource::new([
+            ("001_users.surql", "DEFINE TABLE users SCHEMAFULL;"),
+            ("003_posts.surql", "DEFINE TABLE posts;\nDEFINE TABLE tags -- the tags"),
+        ]);
+        let policy = Policy { modified: PolicyAction::Warn, missing: PolicyAction::Warn, out_of_order: PolicyAction::Error };
+        let report = Migrator::new(&db).source(&source).policy(policy).run().await.unwrap();
+
+        let warnings: Vec<(&str, WarningKind)> = report
+            .warnings
+            .iter()
+            .map(|warning| (warning.filename.as_str(), warning.kind))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                ("001_users.surql", WarningKind::Modified),
+                ("002_tmp.surql", WarningKind::Missing),
+                ("003_posts.surql", WarningKind::MissingTrailingSemicolon),
+            ]
+        );
+        assert_eq!(report.applied.len(), 1);
+    }
+}